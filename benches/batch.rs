@@ -0,0 +1,72 @@
+//! Compares batch verification with verifying every signature on its own.
+//!
+//! Run with `cargo bench --features signature-batch --bench batch`.
+
+use fire_crypto::signature::{
+	verify_batch, Keypair, PrecomputedVerifier, PublicKey, Signature,
+};
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const SIGNATURES: usize = 1000;
+const RUNS: usize = 10;
+
+/// Returns the fastest of multiple runs.
+fn measure(mut f: impl FnMut()) -> Duration {
+	(0..RUNS)
+		.map(|_| {
+			let start = Instant::now();
+			f();
+			start.elapsed()
+		})
+		.min()
+		.unwrap()
+}
+
+fn report(name: &str, single: Duration, batch: Duration) {
+	println!(
+		"{name}: {SIGNATURES} signatures, one by one {single:?}, \
+		 batch {batch:?} ({:.1}x)",
+		single.as_secs_f64() / batch.as_secs_f64()
+	);
+}
+
+fn main() {
+	let msgs: Vec<_> =
+		(0..SIGNATURES).map(|i| format!("log line {i}")).collect();
+
+	// every signature from another key
+	let keypairs: Vec<_> = msgs.iter().map(|_| Keypair::new()).collect();
+	let signatures: Vec<_> =
+		keypairs.iter().zip(&msgs).map(|(k, m)| k.sign(m)).collect();
+	let items: Vec<(&PublicKey, &str, &Signature)> = keypairs
+		.iter()
+		.zip(&msgs)
+		.zip(&signatures)
+		.map(|((k, m), s)| (k.public(), m.as_str(), s))
+		.collect();
+
+	let single = measure(|| {
+		for (public_key, msg, signature) in &items {
+			assert!(black_box(public_key.verify(msg, signature)));
+		}
+	});
+	let batch = measure(|| verify_batch(black_box(&items)).unwrap());
+	report("distinct keys", single, batch);
+
+	// every signature from the same key
+	let keypair = Keypair::new();
+	let verifier = PrecomputedVerifier::new(keypair.public().clone());
+	let signatures: Vec<_> = msgs.iter().map(|m| keypair.sign(m)).collect();
+	let items: Vec<_> =
+		msgs.iter().map(String::as_str).zip(&signatures).collect();
+
+	let single = measure(|| {
+		for (msg, signature) in &items {
+			assert!(black_box(verifier.verify(msg, signature)));
+		}
+	});
+	let batch = measure(|| verifier.verify_batch(black_box(&items)).unwrap());
+	report("precomputed key", single, batch);
+}
@@ -0,0 +1,377 @@
+//! Blind indexes, to search for encrypted values.
+//!
+//! A blind index is a keyed Blake2b hash of the normalized plaintext which
+//! is stored next to the encrypted value. To search for a value its index
+//! is computed and compared with equality, without decrypting any rows.
+//!
+//! Every index derives its own key from the [`BlindIndexKey`] and its
+//! name, so a field can have multiple indexes (for example the full email
+//! address and only the domain) which can't be linked to each other.
+//!
+//! ## Truncation
+//! Indexes can be truncated, shorter indexes lead to false positives which
+//! need to be filtered after decrypting, but leak less about which rows
+//! contain the same value. With `n` rows an index of `len` bytes should
+//! leave roughly `n / 2^(8 * len)` false positives per query.
+//!
+//! ## Note
+//! Values with little entropy can be guessed by anyone who knows the key.
+//! Rows with the same value always have the same index.
+//!
+//! ## Example
+//! ```
+//! use fire_crypto::blind_index::BlindIndexKey;
+//!
+//! let key = BlindIndexKey::new();
+//! let email = key.index("email", 8);
+//!
+//! let stored = email.compute("Alice@Example.com");
+//! // the value is normalized before hashing
+//! assert_eq!(email.compute(" alice@example.com "), stored);
+//! assert_eq!(stored.as_slice().len(), 8);
+//!
+//! // another index on the same field doesn't match
+//! assert_ne!(key.index("email-2", 8).compute("alice@example.com"), stored);
+//! ```
+
+use crate::error::TryFromError;
+use crate::hash::{Hash, Hasher};
+
+use std::convert::{TryFrom, TryInto};
+use std::fmt;
+
+use zeroize::Zeroize;
+
+const INDEX_KEY_CONTEXT: &[u8] = b"fire-crypto blind index";
+
+/// Trims the value, converts it to lowercase and collapses whitespace.
+///
+/// This is the default transformation of a [`BlindIndex`].
+pub fn normalize(value: &str) -> String {
+	value
+		.split_whitespace()
+		.map(str::to_lowercase)
+		.collect::<Vec<_>>()
+		.join(" ")
+}
+
+/// The secret all blind indexes are derived from.
+pub struct BlindIndexKey {
+	secret: [u8; 32],
+}
+
+impl BlindIndexKey {
+	pub const LEN: usize = 32;
+
+	/// Creates a new random secret.
+	pub fn new() -> Self {
+		let mut secret = [0u8; 32];
+		crate::fill_random(&mut secret);
+
+		Self { secret }
+	}
+
+	/// ## Panics
+	/// if the slice is not 32 bytes long.
+	pub fn from_slice(slice: &[u8]) -> Self {
+		slice.try_into().unwrap()
+	}
+
+	pub fn to_bytes(&self) -> [u8; 32] {
+		self.secret
+	}
+
+	/// Derives the index with the given name, which is truncated to `len`
+	/// bytes.
+	///
+	/// ## Panics
+	/// if len is zero or bigger than 64.
+	pub fn index(&self, name: &str, len: usize) -> BlindIndex {
+		assert!(
+			len > 0 && len <= Hash::LEN,
+			"len needs to be between 1 and 64"
+		);
+
+		let mut hasher = Hasher::new_keyed(self.secret);
+		hasher.update(INDEX_KEY_CONTEXT);
+		hasher.update(name);
+		let mut bytes = hasher.finalize().to_bytes();
+		let key = bytes[..32].try_into().unwrap();
+		bytes.zeroize();
+
+		BlindIndex {
+			key,
+			len,
+			transform: normalize,
+		}
+	}
+}
+
+impl fmt::Debug for BlindIndexKey {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str("BlindIndexKey")
+	}
+}
+
+impl From<[u8; 32]> for BlindIndexKey {
+	fn from(secret: [u8; 32]) -> Self {
+		Self { secret }
+	}
+}
+
+impl TryFrom<&[u8]> for BlindIndexKey {
+	type Error = TryFromError;
+
+	fn try_from(v: &[u8]) -> Result<Self, Self::Error> {
+		<[u8; 32]>::try_from(v)
+			.map_err(TryFromError::from_any)
+			.map(Self::from)
+	}
+}
+
+impl Drop for BlindIndexKey {
+	fn drop(&mut self) {
+		self.secret.zeroize();
+	}
+}
+
+/// A single blind index, created with [`BlindIndexKey::index`].
+#[derive(Clone)]
+pub struct BlindIndex {
+	key: [u8; 32],
+	len: usize,
+	transform: fn(&str) -> String,
+}
+
+impl BlindIndex {
+	/// Replaces the default [`normalize`] transformation.
+	///
+	/// This allows to index only a part of a value, for example the
+	/// domain of an email address.
+	pub fn with_transform(mut self, transform: fn(&str) -> String) -> Self {
+		self.transform = transform;
+		self
+	}
+
+	/// The length of the index in bytes.
+	pub fn output_len(&self) -> usize {
+		self.len
+	}
+
+	/// Transforms the value and computes its index.
+	pub fn compute(&self, value: &str) -> IndexValue {
+		self.compute_bytes((self.transform)(value))
+	}
+
+	/// Computes the index of the value without transforming it.
+	pub fn compute_bytes(&self, value: impl AsRef<[u8]>) -> IndexValue {
+		let hash = Hasher::hash_keyed(self.key, value);
+
+		IndexValue {
+			bytes: hash.as_ref()[..self.len].to_vec(),
+		}
+	}
+}
+
+impl fmt::Debug for BlindIndex {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("BlindIndex")
+			.field("len", &self.len)
+			.finish()
+	}
+}
+
+impl Drop for BlindIndex {
+	fn drop(&mut self) {
+		self.key.zeroize();
+	}
+}
+
+/// All blind indexes of a field.
+#[derive(Debug, Clone, Default)]
+pub struct FieldIndexes {
+	indexes: Vec<(String, BlindIndex)>,
+}
+
+impl FieldIndexes {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Adds an index, the name is only used to look it up again.
+	pub fn add(mut self, name: impl Into<String>, index: BlindIndex) -> Self {
+		self.indexes.push((name.into(), index));
+		self
+	}
+
+	pub fn get(&self, name: &str) -> Option<&BlindIndex> {
+		self.indexes
+			.iter()
+			.find(|(n, _)| n == name)
+			.map(|(_, index)| index)
+	}
+
+	/// Computes every index of the value, in the order they were added.
+	pub fn compute(&self, value: &str) -> Vec<(&str, IndexValue)> {
+		self.indexes
+			.iter()
+			.map(|(name, index)| (name.as_str(), index.compute(value)))
+			.collect()
+	}
+}
+
+/// The computed index of a value.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct IndexValue {
+	bytes: Vec<u8>,
+}
+
+impl IndexValue {
+	pub fn as_slice(&self) -> &[u8] {
+		&self.bytes
+	}
+
+	pub fn into_bytes(self) -> Vec<u8> {
+		self.bytes
+	}
+}
+
+impl From<Vec<u8>> for IndexValue {
+	fn from(bytes: Vec<u8>) -> Self {
+		Self { bytes }
+	}
+}
+
+impl AsRef<[u8]> for IndexValue {
+	fn as_ref(&self) -> &[u8] {
+		&self.bytes
+	}
+}
+
+#[cfg(feature = "postgres")]
+mod impl_postgres {
+	use super::*;
+
+	use bytes::BytesMut;
+	use postgres_types::{to_sql_checked, FromSql, IsNull, ToSql, Type};
+
+	impl ToSql for IndexValue {
+		fn to_sql(
+			&self,
+			ty: &Type,
+			out: &mut BytesMut,
+		) -> Result<IsNull, Box<dyn std::error::Error + Sync + Send>>
+		where
+			Self: Sized,
+		{
+			self.bytes.to_sql(ty, out)
+		}
+
+		fn accepts(ty: &Type) -> bool
+		where
+			Self: Sized,
+		{
+			<&[u8] as ToSql>::accepts(ty)
+		}
+
+		to_sql_checked!();
+	}
+
+	impl<'r> FromSql<'r> for IndexValue {
+		fn from_sql(
+			ty: &Type,
+			raw: &'r [u8],
+		) -> Result<Self, Box<dyn std::error::Error + Sync + Send>> {
+			<Vec<u8> as FromSql>::from_sql(ty, raw).map(Self::from)
+		}
+
+		fn accepts(ty: &Type) -> bool {
+			<&[u8] as FromSql>::accepts(ty)
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+
+	fn domain(email: &str) -> String {
+		let email = normalize(email);
+		email.rsplit('@').next().unwrap_or_default().to_string()
+	}
+
+	#[test]
+	fn normalized() {
+		assert_eq!(normalize("  Hello \t World\n"), "hello world");
+
+		let key = BlindIndexKey::new();
+		let name = key.index("name", 16);
+		assert_eq!(name.compute("Hello  World"), name.compute("hello world"));
+		assert_ne!(name.compute("hello world"), name.compute("helloworld"));
+		assert_ne!(
+			name.compute_bytes("Hello World"),
+			name.compute_bytes("hello world")
+		);
+
+		// other keys lead to other indexes
+		let other = BlindIndexKey::new().index("name", 16);
+		assert_ne!(name.compute("hello"), other.compute("hello"));
+
+		// the same secret leads to the same indexes
+		let same = BlindIndexKey::from(key.to_bytes()).index("name", 16);
+		assert_eq!(name.compute("hello"), same.compute("hello"));
+	}
+
+	#[test]
+	fn truncated() {
+		let key = BlindIndexKey::new();
+		let long = key.index("email", 64);
+		let short = key.index("email", 2);
+
+		assert_eq!(long.compute("a").as_slice().len(), 64);
+		assert_eq!(short.compute("a").as_slice().len(), 2);
+		// the index key depends only on the name
+		assert_eq!(
+			long.compute("a").as_slice()[..2],
+			*short.compute("a").as_slice()
+		);
+	}
+
+	#[test]
+	fn multiple_indexes() {
+		let key = BlindIndexKey::new();
+		let indexes = FieldIndexes::new()
+			.add("email", key.index("email", 16))
+			.add(
+				"domain",
+				key.index("email_domain", 4).with_transform(domain),
+			);
+
+		let alice = indexes.compute("alice@Example.com");
+		let bob = indexes.compute("bob@example.com");
+		assert_eq!(alice[0].0, "email");
+		assert_ne!(alice[0].1, bob[0].1);
+		assert_eq!(alice[1].0, "domain");
+		assert_eq!(alice[1].1, bob[1].1);
+		assert_eq!(alice[1].1.as_slice().len(), 4);
+
+		let domain_index = indexes.get("domain").unwrap();
+		assert_eq!(domain_index.compute("example.com"), bob[1].1);
+		assert!(indexes.get("phone").is_none());
+	}
+
+	#[cfg(feature = "postgres")]
+	#[test]
+	fn to_sql() {
+		use bytes::BytesMut;
+		use postgres_types::{FromSql, ToSql, Type};
+
+		let index = BlindIndexKey::new().index("email", 8).compute("a@b.c");
+		let mut out = BytesMut::new();
+		index.to_sql_checked(&Type::BYTEA, &mut out).unwrap();
+		assert_eq!(&out[..], index.as_slice());
+		assert_eq!(IndexValue::from_sql(&Type::BYTEA, &out).unwrap(), index);
+		assert!(index.to_sql_checked(&Type::TEXT, &mut out).is_err());
+	}
+}
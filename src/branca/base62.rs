@@ -0,0 +1,54 @@
+//! Base62 as used by branca, the bytes are interpreted as a big endian
+//! number and leading zero bytes are kept as leading `0` characters.
+
+const ALPHABET: &[u8; 62] =
+	b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+pub fn encode(bytes: &[u8]) -> String {
+	let zeros = bytes.iter().take_while(|b| **b == 0).count();
+
+	// digits in base 62, least significant first
+	let mut digits: Vec<u8> = Vec::with_capacity(bytes.len() * 138 / 100 + 1);
+	for &byte in &bytes[zeros..] {
+		let mut carry = byte as u32;
+		for digit in digits.iter_mut() {
+			carry += (*digit as u32) << 8;
+			*digit = (carry % 62) as u8;
+			carry /= 62;
+		}
+
+		while carry > 0 {
+			digits.push((carry % 62) as u8);
+			carry /= 62;
+		}
+	}
+
+	let mut s = String::with_capacity(zeros + digits.len());
+	s.extend(std::iter::repeat('0').take(zeros));
+	s.extend(digits.iter().rev().map(|d| ALPHABET[*d as usize] as char));
+	s
+}
+
+pub fn decode(s: &str) -> Option<Vec<u8>> {
+	let zeros = s.bytes().take_while(|b| *b == b'0').count();
+
+	// bytes, least significant first
+	let mut bytes: Vec<u8> = Vec::with_capacity(s.len() * 75 / 100 + 1);
+	for c in s.bytes().skip(zeros) {
+		let mut carry = ALPHABET.iter().position(|a| *a == c)? as u32;
+		for byte in bytes.iter_mut() {
+			carry += *byte as u32 * 62;
+			*byte = carry as u8;
+			carry >>= 8;
+		}
+
+		while carry > 0 {
+			bytes.push(carry as u8);
+			carry >>= 8;
+		}
+	}
+
+	let mut out = vec![0; zeros];
+	out.extend(bytes.iter().rev());
+	Some(out)
+}
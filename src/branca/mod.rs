@@ -0,0 +1,348 @@
+//! [Branca](https://branca.io) tokens.
+//!
+//! A branca token contains an encrypted payload and the time it was
+//! created. It is a stateless alternative to a random
+//! [`Token`](crate::token::Token), the server does not need to store
+//! anything, it only needs the key to verify and read the token.
+//!
+//! ## Format
+//! `base62(version (0xBA) | timestamp (u32 be) | nonce (24) | ciphertext
+//! | tag (16))` where the payload is encrypted with XChaCha20-Poly1305 and
+//! the first 29 bytes are used as associated data.
+//!
+//! ## Example
+//! ```
+//! use fire_crypto::branca::Branca;
+//!
+//! use std::time::Duration;
+//!
+//! let branca = Branca::new();
+//!
+//! let token = branca.encode(b"user-id=42");
+//! let payload = branca.decode(&token, Some(Duration::from_secs(3600)));
+//! assert_eq!(payload.unwrap(), b"user-id=42");
+//! ```
+
+mod base62;
+
+use crate::error::TryFromError;
+
+use std::convert::{TryFrom, TryInto};
+use std::error::Error;
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+
+use zeroize::Zeroize;
+
+const VERSION: u8 = 0xBA;
+const NONCE_LEN: usize = 24;
+const HEADER_LEN: usize = 1 + 4 + NONCE_LEN;
+const TAG_LEN: usize = 16;
+
+/// Get's returned if a branca token could not be decoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BrancaError {
+	/// The token contains characters which are not base62.
+	InvalidBase62,
+	/// The token is too short.
+	InvalidLength,
+	/// The version byte is not `0xBA`.
+	InvalidVersion,
+	/// The token was modified or encrypted with another key.
+	MacNotEqual,
+	/// The token is valid but older than the ttl.
+	Expired,
+}
+
+impl fmt::Display for BrancaError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		fmt::Debug::fmt(self, f)
+	}
+}
+
+impl Error for BrancaError {}
+
+fn now() -> u64 {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map(|d| d.as_secs())
+		.unwrap_or(0)
+}
+
+/// Decodes the token and checks the version and length.
+fn token_bytes(token: &str) -> Result<Vec<u8>, BrancaError> {
+	let bytes = base62::decode(token).ok_or(BrancaError::InvalidBase62)?;
+	if bytes.len() < HEADER_LEN + TAG_LEN {
+		return Err(BrancaError::InvalidLength);
+	}
+	if bytes[0] != VERSION {
+		return Err(BrancaError::InvalidVersion);
+	}
+
+	Ok(bytes)
+}
+
+/// A key to encode and decode branca tokens.
+pub struct Branca {
+	key: [u8; 32],
+}
+
+impl Branca {
+	pub const LEN: usize = 32;
+
+	/// Creates a new random key.
+	pub fn new() -> Self {
+		let mut key = [0u8; 32];
+		crate::fill_random(&mut key);
+
+		Self { key }
+	}
+
+	/// ## Panics
+	/// if the slice is not 32 bytes long.
+	pub fn from_slice(slice: &[u8]) -> Self {
+		slice.try_into().unwrap()
+	}
+
+	pub fn to_bytes(&self) -> [u8; 32] {
+		self.key
+	}
+
+	/// Creates a token with the current time.
+	pub fn encode(&self, payload: &[u8]) -> String {
+		self.encode_at(payload, now() as u32)
+	}
+
+	/// Creates a token with the given timestamp (seconds since the unix
+	/// epoch).
+	pub fn encode_at(&self, payload: &[u8], timestamp: u32) -> String {
+		let mut nonce = [0u8; NONCE_LEN];
+		crate::fill_random(&mut nonce);
+
+		self.encode_with_nonce(payload, timestamp, &nonce)
+	}
+
+	fn encode_with_nonce(
+		&self,
+		payload: &[u8],
+		timestamp: u32,
+		nonce: &[u8; NONCE_LEN],
+	) -> String {
+		let mut bytes =
+			Vec::with_capacity(HEADER_LEN + payload.len() + TAG_LEN);
+		bytes.push(VERSION);
+		bytes.extend_from_slice(&timestamp.to_be_bytes());
+		bytes.extend_from_slice(nonce);
+
+		let ct = XChaCha20Poly1305::new(&self.key.into())
+			.encrypt(
+				XNonce::from_slice(nonce),
+				Payload {
+					msg: payload,
+					aad: &bytes,
+				},
+			)
+			// only fails if the payload is larger than 256GB
+			.expect("payload too large");
+		bytes.extend_from_slice(&ct);
+
+		base62::encode(&bytes)
+	}
+
+	/// Decodes the token, if a ttl is given tokens which are older are
+	/// rejected.
+	pub fn decode(
+		&self,
+		token: &str,
+		ttl: Option<Duration>,
+	) -> Result<Vec<u8>, BrancaError> {
+		self.decode_at(token, ttl, now())
+	}
+
+	/// Decodes the token, checking the ttl against `now` (seconds since
+	/// the unix epoch).
+	pub fn decode_at(
+		&self,
+		token: &str,
+		ttl: Option<Duration>,
+		now: u64,
+	) -> Result<Vec<u8>, BrancaError> {
+		let bytes = token_bytes(token)?;
+		let (header, ct) = bytes.split_at(HEADER_LEN);
+
+		let payload = XChaCha20Poly1305::new(&self.key.into())
+			.decrypt(
+				XNonce::from_slice(&header[5..]),
+				Payload {
+					msg: ct,
+					aad: header,
+				},
+			)
+			.map_err(|_| BrancaError::MacNotEqual)?;
+
+		let timestamp = u32::from_be_bytes(header[1..5].try_into().unwrap());
+		if let Some(ttl) = ttl {
+			if (timestamp as u64).saturating_add(ttl.as_secs()) < now {
+				return Err(BrancaError::Expired);
+			}
+		}
+
+		Ok(payload)
+	}
+
+	/// Returns the timestamp of the token without verifying it.
+	pub fn timestamp(token: &str) -> Result<u32, BrancaError> {
+		let bytes = token_bytes(token)?;
+		Ok(u32::from_be_bytes(bytes[1..5].try_into().unwrap()))
+	}
+}
+
+impl fmt::Debug for Branca {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str("Branca")
+	}
+}
+
+impl From<[u8; 32]> for Branca {
+	fn from(key: [u8; 32]) -> Self {
+		Self { key }
+	}
+}
+
+impl TryFrom<&[u8]> for Branca {
+	type Error = TryFromError;
+
+	fn try_from(v: &[u8]) -> Result<Self, Self::Error> {
+		<[u8; 32]>::try_from(v)
+			.map_err(TryFromError::from_any)
+			.map(Self::from)
+	}
+}
+
+impl Drop for Branca {
+	fn drop(&mut self) {
+		self.key.zeroize();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+
+	// from https://github.com/tuupola/branca-spec/blob/master/test_vectors.json
+	const KEY: &[u8; 32] = b"supersecretkeyyoushouldnotcommit";
+	const NONCE: [u8; NONCE_LEN] = [
+		0xbe, 0xef, 0xbe, 0xef, 0xbe, 0xef, 0xbe, 0xef, 0xbe, 0xef, 0xbe, 0xef,
+		0xbe, 0xef, 0xbe, 0xef, 0xbe, 0xef, 0xbe, 0xef, 0xbe, 0xef, 0xbe, 0xef,
+	];
+
+	const VECTORS: &[(&str, &[u8], u32)] = &[
+		(
+			"870S4BYxgHw0KnP3W9fgVUHEhT5g86vJ17etaC5Kh5uIraWHCI1psNQGv298ZmjPwoYbjDQ9chy2z",
+			b"Hello world!",
+			0,
+		),
+		(
+			"89i7YCwu5tWAJNHUDdmIqhzOi5hVHOd4afjZcGMcVmM4enl4yeLiDyYv41eMkNmTX6IwYEFErCSqr",
+			b"Hello world!",
+			4294967295,
+		),
+		(
+			"875GH23U0Dr6nHFA63DhOyd9LkYudBkX8RsCTOMz5xoYAMw9sMd5QwcEqLDRnTDHPenOX7nP2trlT",
+			b"Hello world!",
+			123206400,
+		),
+		(
+			"1jIBheHbDdkCDFQmtgw4RUZeQoOJgGwTFJSpwOAk3XYpJJr52DEpILLmmwYl4tjdSbbNqcF1",
+			&[0; 8],
+			0,
+		),
+		(
+			"4sfD0vPFhIif8cy4nB3BQkHeJqkOkDvinI4zIhMjYX4YXZU5WIq9ycCVjGzB5",
+			b"",
+			0,
+		),
+	];
+
+	#[test]
+	fn spec_vectors() {
+		let branca = Branca::from(*KEY);
+
+		for (token, payload, timestamp) in VECTORS {
+			assert_eq!(
+				branca.encode_with_nonce(payload, *timestamp, &NONCE),
+				*token
+			);
+			assert_eq!(branca.decode(token, None).unwrap(), *payload);
+			assert_eq!(Branca::timestamp(token).unwrap(), *timestamp);
+		}
+	}
+
+	#[test]
+	fn invalid_tokens() {
+		let branca = Branca::from(*KEY);
+
+		// wrong version
+		let token = VECTORS[0].0;
+		let mut bytes = base62::decode(token).unwrap();
+		bytes[0] = 0xBB;
+		assert_eq!(
+			branca.decode(&base62::encode(&bytes), None).unwrap_err(),
+			BrancaError::InvalidVersion
+		);
+
+		// modified nonce
+		let mut bytes = base62::decode(token).unwrap();
+		bytes[10] ^= 1;
+		assert_eq!(
+			branca.decode(&base62::encode(&bytes), None).unwrap_err(),
+			BrancaError::MacNotEqual
+		);
+
+		assert_eq!(
+			branca.decode(&format!("{}_", token), None).unwrap_err(),
+			BrancaError::InvalidBase62
+		);
+		assert_eq!(
+			branca.decode("870S4BYxgHw0KnP3", None).unwrap_err(),
+			BrancaError::InvalidLength
+		);
+		assert_eq!(
+			Branca::new().decode(token, None).unwrap_err(),
+			BrancaError::MacNotEqual
+		);
+	}
+
+	#[test]
+	fn ttl() {
+		let branca = Branca::new();
+		let token = branca.encode_at(b"payload", 1000);
+		let ttl = Some(Duration::from_secs(100));
+
+		assert_eq!(branca.decode_at(&token, ttl, 1100).unwrap(), b"payload");
+		assert_eq!(
+			branca.decode_at(&token, ttl, 1101).unwrap_err(),
+			BrancaError::Expired
+		);
+		assert!(branca.decode_at(&token, None, u64::MAX).is_ok());
+		let never = Some(Duration::MAX);
+		assert!(branca.decode_at(&token, never, u64::MAX).is_ok());
+
+		let token = branca.encode(b"now");
+		assert!(branca.decode(&token, ttl).is_ok());
+	}
+
+	#[test]
+	fn base62_roundtrip() {
+		for bytes in [&[][..], &[0], &[0, 0, 1], &[255; 40], &[1, 0, 0]] {
+			assert_eq!(base62::decode(&base62::encode(bytes)).unwrap(), bytes);
+		}
+		assert_eq!(base62::encode(&[61]), "z");
+		assert_eq!(base62::encode(&[62]), "10");
+	}
+}
@@ -0,0 +1,337 @@
+use super::{Key, Mac, MacNotEqual, SyncKey};
+
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::marker::PhantomData;
+
+use fire_protobuf::{
+	bytes::BytesWrite,
+	decode::{DecodeError, DecodeMessage, DecodeMessageOwned, FieldKind},
+	encode::{
+		EncodeError, EncodeMessage, FieldOpt, MessageEncoder, SizeBuilder,
+	},
+	WireType,
+};
+
+/// Get's returned if an [`EncryptedMessage`] could not be decrypted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum EncryptedMessageError {
+	/// The message is shorter than a mac, probably the field was missing.
+	InvalidLength,
+	/// The generated mac and the received mac are not equal.
+	MacNotEqual,
+	/// The decrypted bytes are not a valid `T`.
+	Decode(DecodeError),
+}
+
+impl From<MacNotEqual> for EncryptedMessageError {
+	fn from(_: MacNotEqual) -> Self {
+		Self::MacNotEqual
+	}
+}
+
+impl fmt::Display for EncryptedMessageError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		fmt::Debug::fmt(self, f)
+	}
+}
+
+impl Error for EncryptedMessageError {}
+
+/// A protobuf message `T` which is encrypted with a [`Key`] or a
+/// [`SyncKey`].
+///
+/// It is encoded as a bytes field containing the mac followed by the
+/// ciphertext, so it can be used as a field in other messages.
+///
+/// ## Warning
+/// Since the nonce of a key is incremented with every message, messages
+/// need to be decrypted in the same order as they were encrypted.
+///
+/// ## Example
+/// ```
+/// use fire_crypto::cipher::{EncryptedMessage, Keypair, Nonce};
+///
+/// let alice = Keypair::new();
+/// let bob = Keypair::new();
+/// let nonce = Nonce::new();
+///
+/// let mut alice_key = alice.diffie_hellman(bob.public()).to_key(nonce.clone());
+/// let mut bob_key = bob.diffie_hellman(alice.public()).to_key(nonce);
+///
+/// let mut msg = "secret".to_string();
+/// let encrypted = EncryptedMessage::encrypt(&mut msg, &mut alice_key).unwrap();
+///
+/// let msg: String = encrypted.decrypt(&mut bob_key).unwrap();
+/// assert_eq!(msg, "secret");
+/// ```
+pub struct EncryptedMessage<T> {
+	/// mac | ciphertext
+	bytes: Vec<u8>,
+	marker: PhantomData<fn() -> T>,
+}
+
+impl<T> EncryptedMessage<T> {
+	fn from_encrypted(mac: Mac, mut ciphertext: Vec<u8>) -> Self {
+		let mut bytes = Vec::with_capacity(Mac::LEN + ciphertext.len());
+		bytes.extend_from_slice(&mac.into_bytes());
+		bytes.append(&mut ciphertext);
+
+		Self {
+			bytes,
+			marker: PhantomData,
+		}
+	}
+
+	/// Returns the mac and the ciphertext.
+	pub fn as_bytes(&self) -> &[u8] {
+		&self.bytes
+	}
+
+	pub fn into_bytes(self) -> Vec<u8> {
+		self.bytes
+	}
+
+	fn split(&self) -> Result<(Mac, Vec<u8>), EncryptedMessageError> {
+		if self.bytes.len() < Mac::LEN {
+			return Err(EncryptedMessageError::InvalidLength);
+		}
+
+		let (mac, ciphertext) = self.bytes.split_at(Mac::LEN);
+		Ok((Mac::from_slice(mac), ciphertext.to_vec()))
+	}
+}
+
+impl<T: EncodeMessage> EncryptedMessage<T> {
+	/// Encodes and encrypts the message.
+	pub fn encrypt(msg: &mut T, key: &mut Key) -> Result<Self, EncodeError> {
+		let mut bytes = msg.write_to_bytes()?;
+		let mac = key.encrypt(&mut bytes);
+
+		Ok(Self::from_encrypted(mac, bytes))
+	}
+
+	/// Encodes and encrypts the message.
+	pub fn encrypt_sync(
+		msg: &mut T,
+		key: &SyncKey,
+	) -> Result<Self, EncodeError> {
+		let mut bytes = msg.write_to_bytes()?;
+		let mac = key.encrypt(&mut bytes);
+
+		Ok(Self::from_encrypted(mac, bytes))
+	}
+}
+
+impl<T: DecodeMessageOwned> EncryptedMessage<T> {
+	/// Decrypts and decodes the message.
+	pub fn decrypt(&self, key: &mut Key) -> Result<T, EncryptedMessageError> {
+		let (mac, mut bytes) = self.split()?;
+		key.decrypt(&mut bytes, &mac)?;
+
+		T::parse_from_bytes(&bytes).map_err(EncryptedMessageError::Decode)
+	}
+
+	/// Decrypts and decodes the message.
+	pub fn decrypt_sync(
+		&self,
+		key: &SyncKey,
+	) -> Result<T, EncryptedMessageError> {
+		let (mac, mut bytes) = self.split()?;
+		key.decrypt(&mut bytes, &mac)?;
+
+		T::parse_from_bytes(&bytes).map_err(EncryptedMessageError::Decode)
+	}
+}
+
+impl<T> Clone for EncryptedMessage<T> {
+	fn clone(&self) -> Self {
+		Self {
+			bytes: self.bytes.clone(),
+			marker: PhantomData,
+		}
+	}
+}
+
+impl<T> PartialEq for EncryptedMessage<T> {
+	fn eq(&self, other: &Self) -> bool {
+		self.bytes == other.bytes
+	}
+}
+
+impl<T> Eq for EncryptedMessage<T> {}
+
+impl<T> fmt::Debug for EncryptedMessage<T> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("EncryptedMessage")
+			.field("len", &self.bytes.len())
+			.finish()
+	}
+}
+
+impl<T> TryFrom<&[u8]> for EncryptedMessage<T> {
+	type Error = crate::error::TryFromError;
+
+	fn try_from(v: &[u8]) -> Result<Self, Self::Error> {
+		if v.len() < Mac::LEN {
+			return Err(crate::error::TryFromError::from_any(()));
+		}
+
+		Ok(Self {
+			bytes: v.to_vec(),
+			marker: PhantomData,
+		})
+	}
+}
+
+impl<T> EncodeMessage for EncryptedMessage<T> {
+	const WIRE_TYPE: WireType = WireType::Len;
+
+	fn is_default(&self) -> bool {
+		false
+	}
+
+	fn encoded_size(
+		&mut self,
+		field: Option<FieldOpt>,
+		builder: &mut SizeBuilder,
+	) -> Result<(), EncodeError> {
+		self.bytes.encoded_size(field, builder)
+	}
+
+	fn encode<B>(
+		&mut self,
+		field: Option<FieldOpt>,
+		encoder: &mut MessageEncoder<B>,
+	) -> Result<(), EncodeError>
+	where
+		B: BytesWrite,
+	{
+		self.bytes.encode(field, encoder)
+	}
+}
+
+impl<'m, T> DecodeMessage<'m> for EncryptedMessage<T> {
+	const WIRE_TYPE: WireType = WireType::Len;
+
+	fn decode_default() -> Self {
+		Self {
+			bytes: vec![],
+			marker: PhantomData,
+		}
+	}
+
+	fn merge(
+		&mut self,
+		kind: FieldKind<'m>,
+		is_field: bool,
+	) -> Result<(), DecodeError> {
+		self.bytes.merge(kind, is_field)?;
+
+		if self.bytes.len() < Mac::LEN {
+			return Err(DecodeError::Other(
+				"encrypted message is shorter than a mac".into(),
+			));
+		}
+
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+	use crate::cipher::{Keypair, Nonce};
+
+	use fire_protobuf::{from_slice, to_vec, DecodeMessage, EncodeMessage};
+
+	#[derive(Debug, PartialEq, Eq, EncodeMessage, DecodeMessage)]
+	struct User {
+		#[field(1)]
+		name: String,
+		#[field(2)]
+		age: u32,
+	}
+
+	#[derive(Debug, PartialEq, Eq, EncodeMessage, DecodeMessage)]
+	struct Response {
+		#[field(1)]
+		id: u64,
+		#[field(2)]
+		user: EncryptedMessage<User>,
+	}
+
+	fn keys() -> (Key, Key) {
+		let alice = Keypair::new();
+		let bob = Keypair::new();
+		let nonce = Nonce::new();
+
+		(
+			alice.diffie_hellman(bob.public()).to_key(nonce.clone()),
+			bob.diffie_hellman(alice.public()).to_key(nonce),
+		)
+	}
+
+	#[test]
+	fn nested_field() {
+		let (mut alice, mut bob) = keys();
+
+		let mut user = User {
+			name: "Alice".into(),
+			age: 30,
+		};
+		let mut response = Response {
+			id: 1,
+			user: EncryptedMessage::encrypt(&mut user, &mut alice).unwrap(),
+		};
+
+		let bytes = to_vec(&mut response).unwrap();
+		let response: Response = from_slice(&bytes).unwrap();
+		assert_eq!(response.id, 1);
+		assert_eq!(response.user.decrypt(&mut bob).unwrap(), user);
+	}
+
+	#[test]
+	fn sync_key() {
+		let (alice, bob) = keys();
+		let (alice, bob) = (alice.into_sync(), bob.into_sync());
+
+		let mut user = User {
+			name: "Bob".into(),
+			age: 40,
+		};
+		let a = EncryptedMessage::encrypt_sync(&mut user, &alice).unwrap();
+		let b = EncryptedMessage::encrypt_sync(&mut user, &alice).unwrap();
+		// every message uses another nonce
+		assert_ne!(a, b);
+
+		assert_eq!(a.decrypt_sync(&bob).unwrap(), user);
+		assert_eq!(b.decrypt_sync(&bob).unwrap(), user);
+	}
+
+	#[test]
+	fn modified() {
+		let (mut alice, mut bob) = keys();
+
+		let mut user = User {
+			name: "Alice".into(),
+			age: 30,
+		};
+		let encrypted =
+			EncryptedMessage::encrypt(&mut user, &mut alice).unwrap();
+		let mut bytes = encrypted.into_bytes();
+		bytes[Mac::LEN] ^= 1;
+
+		let encrypted =
+			EncryptedMessage::<User>::try_from(bytes.as_slice()).unwrap();
+		assert_eq!(
+			encrypted.decrypt(&mut bob).unwrap_err(),
+			EncryptedMessageError::MacNotEqual
+		);
+
+		assert!(EncryptedMessage::<User>::try_from(&[0; 15][..]).is_err());
+	}
+}
@@ -0,0 +1,323 @@
+//! Arithmetic in the field of curve25519 (integers modulo 2^255 - 19).
+//!
+//! Only the operations needed for elligator2 are implemented. An element
+//! is stored as five 51 bit limbs, like in curve25519-dalek.
+//!
+//! curve25519-dalek keeps its field arithmetic private and doesn't provide
+//! the inverse map (public key to representative) at all, so it can't be
+//! used here. Points, scalars and x25519 still come from dalek.
+//!
+//! ## Warning
+//! This code is not constant time, comparisons, `is_square` and `sqrt`
+//! branch on the values. It must only be used with public values, like
+//! public keys and representatives which are sent over the wire anyway.
+
+const MASK: u64 = (1 << 51) - 1;
+
+/// p - 2
+const P_MINUS_2: [u8; 32] = exponent(0xeb, 0x7f);
+/// (p - 1) / 2
+const P_MINUS_1_HALF: [u8; 32] = exponent(0xf6, 0x3f);
+/// (p + 3) / 8
+const P_PLUS_3_EIGHTH: [u8; 32] = exponent(0xfe, 0x0f);
+/// (p - 1) / 4
+const P_MINUS_1_QUARTER: [u8; 32] = exponent(0xfb, 0x1f);
+
+/// Returns an exponent where every byte except the first and the last
+/// are 0xff.
+const fn exponent(first: u8, last: u8) -> [u8; 32] {
+	let mut bytes = [0xff; 32];
+	bytes[0] = first;
+	bytes[31] = last;
+	bytes
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct FieldElement([u64; 5]);
+
+impl FieldElement {
+	pub const ZERO: Self = Self([0; 5]);
+	pub const ONE: Self = Self([1, 0, 0, 0, 0]);
+
+	pub fn from_u64(n: u64) -> Self {
+		Self([n & MASK, n >> 51, 0, 0, 0])
+	}
+
+	/// Ignores the highest bit.
+	pub fn from_bytes(bytes: &[u8; 32]) -> Self {
+		let load = |i: usize| {
+			let mut b = [0u8; 8];
+			b.copy_from_slice(&bytes[i..i + 8]);
+			u64::from_le_bytes(b)
+		};
+
+		Self([
+			load(0) & MASK,
+			(load(6) >> 3) & MASK,
+			(load(12) >> 6) & MASK,
+			(load(19) >> 1) & MASK,
+			(load(24) >> 12) & MASK,
+		])
+	}
+
+	/// Returns the canonical encoding.
+	pub fn to_bytes(self) -> [u8; 32] {
+		let mut l = self.carry().0;
+
+		// check if the value is larger or equal to p
+		let mut q = (l[0] + 19) >> 51;
+		q = (l[1] + q) >> 51;
+		q = (l[2] + q) >> 51;
+		q = (l[3] + q) >> 51;
+		q = (l[4] + q) >> 51;
+
+		l[0] += 19 * q;
+		l[1] += l[0] >> 51;
+		l[0] &= MASK;
+		l[2] += l[1] >> 51;
+		l[1] &= MASK;
+		l[3] += l[2] >> 51;
+		l[2] &= MASK;
+		l[4] += l[3] >> 51;
+		l[3] &= MASK;
+		// this discards 2^255
+		l[4] &= MASK;
+
+		let mut bytes = [0u8; 32];
+		let mut acc: u128 = 0;
+		let mut acc_bits = 0;
+		let mut pos = 0;
+		for limb in l {
+			acc |= (limb as u128) << acc_bits;
+			acc_bits += 51;
+			while acc_bits >= 8 && pos < 32 {
+				bytes[pos] = acc as u8;
+				acc >>= 8;
+				acc_bits -= 8;
+				pos += 1;
+			}
+		}
+		if pos < 32 {
+			bytes[pos] = acc as u8;
+		}
+
+		bytes
+	}
+
+	fn carry(self) -> Self {
+		let mut l = self.0;
+
+		l[1] += l[0] >> 51;
+		l[0] &= MASK;
+		l[2] += l[1] >> 51;
+		l[1] &= MASK;
+		l[3] += l[2] >> 51;
+		l[2] &= MASK;
+		l[4] += l[3] >> 51;
+		l[3] &= MASK;
+		l[0] += 19 * (l[4] >> 51);
+		l[4] &= MASK;
+
+		Self(l)
+	}
+
+	pub fn add(&self, other: &Self) -> Self {
+		let mut l = self.0;
+		for (a, b) in l.iter_mut().zip(other.0) {
+			*a += b;
+		}
+
+		Self(l).carry()
+	}
+
+	pub fn sub(&self, other: &Self) -> Self {
+		// add 16 * p to make sure the result is not negative
+		let a = self.carry().0;
+		let b = other.carry().0;
+
+		Self([
+			(a[0] + 36028797018963664) - b[0],
+			(a[1] + 36028797018963952) - b[1],
+			(a[2] + 36028797018963952) - b[2],
+			(a[3] + 36028797018963952) - b[3],
+			(a[4] + 36028797018963952) - b[4],
+		])
+		.carry()
+	}
+
+	pub fn neg(&self) -> Self {
+		Self::ZERO.sub(self)
+	}
+
+	pub fn mul(&self, other: &Self) -> Self {
+		let a = self.carry().0;
+		let b = other.carry().0;
+
+		let m = |x: u64, y: u64| (x as u128) * (y as u128);
+
+		let b1 = b[1] * 19;
+		let b2 = b[2] * 19;
+		let b3 = b[3] * 19;
+		let b4 = b[4] * 19;
+
+		let c0 = m(a[0], b[0])
+			+ m(a[4], b1)
+			+ m(a[3], b2)
+			+ m(a[2], b3)
+			+ m(a[1], b4);
+		let mut c1 = m(a[1], b[0])
+			+ m(a[0], b[1])
+			+ m(a[4], b2)
+			+ m(a[3], b3)
+			+ m(a[2], b4);
+		let mut c2 = m(a[2], b[0])
+			+ m(a[1], b[1])
+			+ m(a[0], b[2])
+			+ m(a[4], b3)
+			+ m(a[3], b4);
+		let mut c3 = m(a[3], b[0])
+			+ m(a[2], b[1])
+			+ m(a[1], b[2])
+			+ m(a[0], b[3])
+			+ m(a[4], b4);
+		let mut c4 = m(a[4], b[0])
+			+ m(a[3], b[1])
+			+ m(a[2], b[2])
+			+ m(a[1], b[3])
+			+ m(a[0], b[4]);
+
+		c1 += c0 >> 51;
+		c2 += c1 >> 51;
+		c3 += c2 >> 51;
+		c4 += c3 >> 51;
+		let carry = (c4 >> 51) as u64;
+
+		Self([
+			(c0 as u64 & MASK) + carry * 19,
+			c1 as u64 & MASK,
+			c2 as u64 & MASK,
+			c3 as u64 & MASK,
+			c4 as u64 & MASK,
+		])
+		.carry()
+	}
+
+	pub fn square(&self) -> Self {
+		self.mul(self)
+	}
+
+	/// The exponent is public, so it's fine to branch on it.
+	fn pow(&self, exp: &[u8; 32]) -> Self {
+		let mut res = Self::ONE;
+		for byte in exp.iter().rev() {
+			for i in (0..8).rev() {
+				res = res.square();
+				if (byte >> i) & 1 == 1 {
+					res = res.mul(self);
+				}
+			}
+		}
+
+		res
+	}
+
+	pub fn invert(&self) -> Self {
+		self.pow(&P_MINUS_2)
+	}
+
+	pub fn is_zero(&self) -> bool {
+		self.to_bytes() == [0; 32]
+	}
+
+	pub fn is_negative(&self) -> bool {
+		self.to_bytes()[0] & 1 == 1
+	}
+
+	/// Returns true if the element is a square (zero is a square).
+	pub fn is_square(&self) -> bool {
+		let l = self.pow(&P_MINUS_1_HALF);
+		l.is_zero() || l == Self::ONE
+	}
+
+	/// Returns the non negative square root if it exists.
+	pub fn sqrt(&self) -> Option<Self> {
+		let mut root = self.pow(&P_PLUS_3_EIGHTH);
+
+		if root.square() != *self {
+			let sqrt_m1 = Self::from_u64(2).pow(&P_MINUS_1_QUARTER);
+			root = root.mul(&sqrt_m1);
+		}
+
+		if root.square() != *self {
+			return None;
+		}
+
+		if root.is_negative() {
+			root = root.neg();
+		}
+
+		Some(root)
+	}
+}
+
+impl PartialEq for FieldElement {
+	fn eq(&self, other: &Self) -> bool {
+		self.to_bytes() == other.to_bytes()
+	}
+}
+
+impl Eq for FieldElement {}
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+
+	fn random() -> FieldElement {
+		let mut bytes = [0u8; 32];
+		crate::fill_random(&mut bytes);
+		FieldElement::from_bytes(&bytes)
+	}
+
+	#[test]
+	fn bytes() {
+		let mut bytes = [0u8; 32];
+		bytes[0] = 42;
+		bytes[17] = 1;
+		assert_eq!(FieldElement::from_bytes(&bytes).to_bytes(), bytes);
+
+		// p - 1
+		let p_1 = exponent(0xec, 0x7f);
+		assert_eq!(FieldElement::from_bytes(&p_1).to_bytes(), p_1);
+
+		// p is reduced to zero
+		let p = exponent(0xed, 0x7f);
+		assert!(FieldElement::from_bytes(&p).is_zero());
+		assert!(FieldElement::from_bytes(&p_1)
+			.add(&FieldElement::ONE)
+			.is_zero());
+	}
+
+	#[test]
+	fn arithmetic() {
+		for _ in 0..16 {
+			let a = random();
+			let b = random();
+
+			assert_eq!(a.add(&b).sub(&b), a);
+			assert_eq!(a.mul(&a.invert()), FieldElement::ONE);
+			assert!(a.neg().add(&a).is_zero());
+
+			let sq = a.mul(&b).square();
+			let root = sq.sqrt().unwrap();
+			assert_eq!(root.square(), sq);
+			assert!(!root.is_negative());
+			assert!(sq.is_square());
+		}
+
+		// two is not a square
+		let two = FieldElement::from_u64(2);
+		assert!(!two.is_square());
+		assert!(two.sqrt().is_none());
+	}
+}
@@ -1,4 +1,4 @@
-use super::{Mac, MacNotEqual};
+use super::{Mac, MacNotEqual, Nonce};
 use crate::xor;
 
 use std::sync::atomic::{AtomicU64, Ordering};
@@ -48,6 +48,16 @@ impl Key {
 		}
 	}
 
+	/// Creates a key from a secret and an initial nonce.
+	///
+	/// ## Warning
+	/// Don't call this function with the same secret and nonce again
+	/// to encrypt different messages. This leads to nonce reuse.
+	#[allow(dead_code)]
+	pub(crate) fn from_secret(secret: [u8; 32], initial_nonce: Nonce) -> Self {
+		Self::new(secret, initial_nonce.into_bytes())
+	}
+
 	/// Encrypts bytes generating returning the generated Mac-
 	pub fn encrypt(&mut self, msg: &mut [u8]) -> Mac {
 		self.new_cipher().encrypt(msg)
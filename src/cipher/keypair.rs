@@ -1,4 +1,6 @@
-use super::{PublicKey, SharedSecret};
+#[cfg(feature = "elligator")]
+use super::representative::{representable, Representative};
+use super::{NonContributory, PublicKey, SharedSecret};
 #[cfg(feature = "b64")]
 use crate::error::DecodeError;
 use crate::error::TryFromError;
@@ -29,12 +31,35 @@ impl EphemeralKeypair {
 		Self { secret, public }
 	}
 
+	/// Creates a keypair whose public key can be sent as a
+	/// [`Representative`], which looks like random bytes.
+	#[cfg(feature = "elligator")]
+	pub fn new_representable() -> (Self, Representative) {
+		loop {
+			let mut keypair = Self::new();
+			if let Some(repr) = representable(keypair.public()) {
+				keypair.public = repr.to_public_key();
+				return (keypair, repr);
+			}
+		}
+	}
+
 	// maybe return a Key??
 	pub fn diffie_hellman(self, public_key: &PublicKey) -> SharedSecret {
 		let secret = self.secret.diffie_hellman(public_key.inner());
 		SharedSecret::from_shared_secret(secret)
 	}
 
+	/// Like `diffie_hellman` but returns an error if the public key is a
+	/// low order point, which would result in an all zero shared secret.
+	pub fn try_diffie_hellman(
+		self,
+		public_key: &PublicKey,
+	) -> Result<SharedSecret, NonContributory> {
+		let secret = self.secret.diffie_hellman(public_key.inner());
+		SharedSecret::from_contributory(secret)
+	}
+
 	pub fn public(&self) -> &PublicKey {
 		&self.public
 	}
@@ -70,6 +95,24 @@ impl Keypair {
 		Self::from_static_secret(x::StaticSecret::random_from_rng(OsRng))
 	}
 
+	/// Creates a keypair whose public key can be sent as a
+	/// [`Representative`], which looks like random bytes.
+	///
+	/// ## Note
+	/// The public key contains a low order component, if the keypair gets
+	/// restored from it's bytes the public key will differ but the
+	/// result of diffie hellman stays the same.
+	#[cfg(feature = "elligator")]
+	pub fn new_representable() -> (Self, Representative) {
+		loop {
+			let mut keypair = Self::new();
+			if let Some(repr) = representable(keypair.public()) {
+				keypair.public = repr.to_public_key();
+				return (keypair, repr);
+			}
+		}
+	}
+
 	/// ## Panics
 	/// if the slice is not 32 bytes long.
 	pub fn from_slice(slice: &[u8]) -> Self {
@@ -92,6 +135,16 @@ impl Keypair {
 		let secret = self.secret.diffie_hellman(public_key.inner());
 		SharedSecret::from_shared_secret(secret)
 	}
+
+	/// Like `diffie_hellman` but returns an error if the public key is a
+	/// low order point, which would result in an all zero shared secret.
+	pub fn try_diffie_hellman(
+		&self,
+		public_key: &PublicKey,
+	) -> Result<SharedSecret, NonContributory> {
+		let secret = self.secret.diffie_hellman(public_key.inner());
+		SharedSecret::from_contributory(secret)
+	}
 }
 
 #[cfg(not(feature = "b64"))]
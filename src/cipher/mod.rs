@@ -39,6 +39,9 @@
 //! // Alice securely said hi to bob.
 //! ```
 
+use std::error::Error;
+use std::fmt;
+
 mod key;
 pub use key::{Key, SyncKey};
 
@@ -57,11 +60,39 @@ pub use shared_secret::SharedSecret;
 mod nonce;
 pub use nonce::Nonce;
 
+#[cfg(feature = "elligator")]
+mod field;
+#[cfg(feature = "elligator")]
+mod representative;
+#[cfg(feature = "elligator")]
+pub use representative::Representative;
+
+#[cfg(feature = "protobuf")]
+mod encrypted_message;
+#[cfg(feature = "protobuf")]
+pub use encrypted_message::{EncryptedMessage, EncryptedMessageError};
+
 /// Get's returned as an error if the generated mac and the received
 /// MAC are not equal.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct MacNotEqual;
 
+/// Get's returned as an error if diffie hellman resulted in an all zero
+/// shared secret.
+///
+/// This happens if the public key is a low order point, which allows the
+/// peer to force a known shared secret.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NonContributory;
+
+impl fmt::Display for NonContributory {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		fmt::Debug::fmt(self, f)
+	}
+}
+
+impl Error for NonContributory {}
+
 // TESTS
 
 #[cfg(test)]
@@ -97,6 +128,77 @@ mod tests {
 		assert_eq!(alice_ssk, bob_ssk);
 	}
 
+	fn low_order_points() -> Vec<[u8; 32]> {
+		let mut p_minus_1 = [0xff; 32];
+		p_minus_1[0] = 0xec;
+		p_minus_1[31] = 0x7f;
+
+		// a point of order eight
+		let order_eight = [
+			0xe0, 0xeb, 0x7a, 0x7c, 0x3b, 0x41, 0xb8, 0xae, 0x16, 0x56, 0xe3,
+			0xfa, 0xf1, 0x9f, 0xc4, 0x6a, 0xda, 0x09, 0x8d, 0xeb, 0x9c, 0x32,
+			0xb1, 0xfd, 0x86, 0x62, 0x05, 0x16, 0x5f, 0x49, 0xb8, 0x00,
+		];
+
+		let mut one = [0u8; 32];
+		one[0] = 1;
+
+		vec![[0u8; 32], one, p_minus_1, order_eight]
+	}
+
+	#[test]
+	pub fn non_contributory() {
+		let alice = Keypair::new();
+		let bob = Keypair::new();
+
+		assert!(!bob.public().is_low_order());
+		assert!(alice.try_diffie_hellman(bob.public()).is_ok());
+
+		for bytes in low_order_points() {
+			let public = PublicKey::from(bytes);
+			assert!(public.is_low_order());
+			assert_eq!(public.validate(), Err(NonContributory));
+
+			assert_eq!(
+				alice.try_diffie_hellman(&public).unwrap_err(),
+				NonContributory
+			);
+			assert_eq!(
+				EphemeralKeypair::new()
+					.try_diffie_hellman(&public)
+					.unwrap_err(),
+				NonContributory
+			);
+
+			assert!(matches!(
+				PublicKey::try_from_validated(&bytes),
+				Err(crate::error::DecodeError::NonContributory)
+			));
+		}
+
+		assert!(PublicKey::try_from_validated(bob.public().as_ref()).is_ok());
+		assert!(matches!(
+			PublicKey::try_from_validated(&[1, 2, 3]),
+			Err(crate::error::DecodeError::InvalidLength)
+		));
+	}
+
+	#[cfg(feature = "b64")]
+	#[test]
+	pub fn b64_validated() {
+		let bob = Keypair::new();
+		let s = bob.public().to_string();
+		assert_eq!(&PublicKey::from_str_validated(&s).unwrap(), bob.public());
+
+		let s = PublicKey::from([0u8; 32]).to_string();
+		// the unvalidated version accepts the key
+		assert!(PublicKey::from_str(&s).is_ok());
+		assert!(matches!(
+			PublicKey::from_str_validated(&s),
+			Err(crate::error::DecodeError::NonContributory)
+		));
+	}
+
 	#[cfg(feature = "b64")]
 	#[test]
 	pub fn b64() {
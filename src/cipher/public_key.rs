@@ -1,6 +1,5 @@
-#[cfg(feature = "b64")]
-use crate::error::DecodeError;
-use crate::error::TryFromError;
+use super::NonContributory;
+use crate::error::{DecodeError, TryFromError};
 
 use std::convert::{TryFrom, TryInto};
 use std::hash::{Hash, Hasher};
@@ -44,6 +43,42 @@ impl PublicKey {
 	pub fn inner(&self) -> &x::PublicKey {
 		&self.inner
 	}
+
+	/// Returns true if the public key is a low order point.
+	///
+	/// Diffie hellman with a low order point always results in an all
+	/// zero shared secret.
+	pub fn is_low_order(&self) -> bool {
+		// clamping makes every scalar a multiple of eight, which maps
+		// every low order point to zero
+		x::x25519([1u8; 32], self.to_bytes()) == [0u8; 32]
+	}
+
+	/// Returns an error if the public key is a low order point.
+	pub fn validate(&self) -> Result<(), NonContributory> {
+		if self.is_low_order() {
+			Err(NonContributory)
+		} else {
+			Ok(())
+		}
+	}
+
+	/// Like `TryFrom<&[u8]>` but rejects low order points.
+	pub fn try_from_validated(v: &[u8]) -> Result<Self, DecodeError> {
+		let me = Self::try_from(v).map_err(|_| DecodeError::InvalidLength)?;
+		me.validate()
+			.map(|_| me)
+			.map_err(|_| DecodeError::NonContributory)
+	}
+
+	/// Like `FromStr` but rejects low order points.
+	#[cfg(feature = "b64")]
+	pub fn from_str_validated(s: &str) -> Result<Self, DecodeError> {
+		let me: Self = s.parse()?;
+		me.validate()
+			.map(|_| me)
+			.map_err(|_| DecodeError::NonContributory)
+	}
 }
 
 #[cfg(not(feature = "b64"))]
@@ -154,6 +189,21 @@ mod impl_serde {
 			Self::from_str(s.as_ref()).map_err(D::Error::custom)
 		}
 	}
+
+	impl PublicKey {
+		/// Like `Deserialize` but rejects low order points.
+		///
+		/// Can be used with `#[serde(deserialize_with = "...")]`.
+		pub fn deserialize_validated<'de, D>(
+			deserializer: D,
+		) -> Result<Self, D::Error>
+		where
+			D: Deserializer<'de>,
+		{
+			let s: Cow<'_, str> = Deserialize::deserialize(deserializer)?;
+			Self::from_str_validated(s.as_ref()).map_err(D::Error::custom)
+		}
+	}
 }
 
 #[cfg(all(feature = "b64", feature = "postgres"))]
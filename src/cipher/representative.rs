@@ -0,0 +1,327 @@
+use super::field::FieldElement;
+use super::PublicKey;
+#[cfg(feature = "b64")]
+use crate::error::DecodeError;
+use crate::error::TryFromError;
+use crate::fill_random;
+
+use std::convert::{TryFrom, TryInto};
+use std::fmt;
+
+use curve25519_dalek::constants::EIGHT_TORSION;
+use curve25519_dalek::MontgomeryPoint;
+
+#[cfg(feature = "b64")]
+use base64::engine::{general_purpose::URL_SAFE_NO_PAD, Engine};
+
+/// The montgomery curve constant A of curve25519.
+const A: u64 = 486662;
+
+/// The two highest bits of a representative are not used and get filled
+/// with random bits.
+const PADDING_MASK: u8 = 0b1100_0000;
+
+/// An elligator2 representative of a [`PublicKey`].
+///
+/// Representatives are indistinguishable from uniform random bytes, unlike
+/// public keys which can be recognized as curve points.
+///
+/// ## Example
+/// ```
+/// use fire_crypto::cipher::Keypair;
+///
+/// let (alice, representative) = Keypair::new_representable();
+/// let bob = Keypair::new();
+///
+/// // alice sends the representative instead of her public key
+/// let alice_public = representative.to_public_key();
+///
+/// let alice_ssk = alice.diffie_hellman(bob.public());
+/// let bob_ssk = bob.diffie_hellman(&alice_public);
+/// assert_eq!(alice_ssk, bob_ssk);
+/// ```
+#[derive(Clone, PartialEq, Eq)]
+pub struct Representative {
+	bytes: [u8; 32],
+}
+
+impl Representative {
+	pub const LEN: usize = 32;
+
+	/// Creates a representative of a public key, returns None if the
+	/// public key has no representative (about half of them don't).
+	///
+	/// ## Warning
+	/// A public key which was not created by `new_representable` is in
+	/// the prime order subgroup, this can be detected even when sending
+	/// the representative. Use [`Keypair::new_representable`] or
+	/// [`EphemeralKeypair::new_representable`] instead.
+	///
+	/// [`Keypair::new_representable`]: super::Keypair::new_representable
+	/// [`EphemeralKeypair::new_representable`]: super::EphemeralKeypair::new_representable
+	pub fn from_public_key(public_key: &PublicKey) -> Option<Self> {
+		let mut tweak = [0u8; 1];
+		fill_random(&mut tweak);
+
+		Self::from_u(&public_key.to_bytes(), tweak[0])
+	}
+
+	/// Returns the public key this representative belongs to.
+	pub fn to_public_key(&self) -> PublicKey {
+		let mut bytes = self.bytes;
+		bytes[31] &= !PADDING_MASK;
+
+		let a = FieldElement::from_u64(A);
+		let r = FieldElement::from_bytes(&bytes);
+
+		// w = -A / (1 + 2r^2)
+		let denom = FieldElement::ONE.add(&r.square().add(&r.square()));
+		let w = a.neg().mul(&denom.invert());
+
+		// w^3 + Aw^2 + w = w (w^2 + Aw + 1)
+		let y2 = w.mul(&w.square().add(&a.mul(&w)).add(&FieldElement::ONE));
+
+		let u = if y2.is_square() { w } else { w.neg().sub(&a) };
+
+		PublicKey::from(u.to_bytes())
+	}
+
+	/// The lowest bit of the tweak decides which root to use, the two
+	/// highest bits are used as padding.
+	fn from_u(u: &[u8; 32], tweak: u8) -> Option<Self> {
+		let two = FieldElement::from_u64(2);
+		let u = FieldElement::from_bytes(u);
+		let u_a = u.add(&FieldElement::from_u64(A));
+
+		if u.is_zero() || u_a.is_zero() {
+			return None;
+		}
+
+		// -2u(u + A) needs to be a square
+		if !two.mul(&u).mul(&u_a).neg().is_square() {
+			return None;
+		}
+
+		// r^2 = -u / (2(u + A)) or r^2 = -(u + A) / 2u
+		let r2 = if tweak & 1 == 0 {
+			u.neg().mul(&two.mul(&u_a).invert())
+		} else {
+			u_a.neg().mul(&two.mul(&u).invert())
+		};
+
+		// use the smaller root, which is below 2^254 and leaves the two
+		// highest bits for padding
+		let mut root = r2.sqrt()?;
+		if root.to_bytes()[31] & PADDING_MASK != 0 {
+			root = root.neg();
+		}
+
+		let mut bytes = root.to_bytes();
+		bytes[31] |= tweak & PADDING_MASK;
+
+		Some(Self { bytes })
+	}
+
+	/// ## Panics
+	/// if the slice is not 32 bytes long.
+	pub fn from_slice(slice: &[u8]) -> Self {
+		slice.try_into().unwrap()
+	}
+
+	pub fn to_bytes(&self) -> [u8; 32] {
+		self.bytes
+	}
+}
+
+/// Adds a random low order component to the public key and returns a
+/// representative of the result, or None if it has no representative.
+///
+/// The low order component doesn't change the result of diffie hellman
+/// since x25519 multiplies with a multiple of eight.
+pub(super) fn representable(public_key: &PublicKey) -> Option<Representative> {
+	let mut rand = [0u8; 2];
+	fill_random(&mut rand);
+
+	let torsion = EIGHT_TORSION[(rand[0] & 0b111) as usize];
+	let point = MontgomeryPoint(public_key.to_bytes()).to_edwards(0)? + torsion;
+
+	Representative::from_u(&point.to_montgomery().to_bytes(), rand[1])
+}
+
+#[cfg(not(feature = "b64"))]
+impl fmt::Debug for Representative {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_tuple("Representative")
+			.field(&self.as_ref())
+			.finish()
+	}
+}
+
+#[cfg(feature = "b64")]
+impl fmt::Debug for Representative {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_tuple("Representative")
+			.field(&self.to_string())
+			.finish()
+	}
+}
+
+#[cfg(feature = "b64")]
+impl fmt::Display for Representative {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		base64::display::Base64Display::new(self.as_ref(), &URL_SAFE_NO_PAD)
+			.fmt(f)
+	}
+}
+
+impl From<[u8; 32]> for Representative {
+	/// Every 32 bytes are a valid representative.
+	fn from(bytes: [u8; 32]) -> Self {
+		Self { bytes }
+	}
+}
+
+impl TryFrom<&[u8]> for Representative {
+	type Error = TryFromError;
+
+	fn try_from(v: &[u8]) -> Result<Self, Self::Error> {
+		<[u8; 32]>::try_from(v)
+			.map_err(TryFromError::from_any)
+			.map(Self::from)
+	}
+}
+
+#[cfg(feature = "b64")]
+impl crate::FromStr for Representative {
+	type Err = DecodeError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		if s.len() != crate::calculate_b64_len(Self::LEN) {
+			return Err(DecodeError::InvalidLength);
+		}
+
+		let mut bytes = [0u8; Self::LEN];
+		URL_SAFE_NO_PAD
+			.decode_slice_unchecked(s, &mut bytes)
+			.map_err(DecodeError::inv_bytes)
+			.map(|_| Self::from(bytes))
+	}
+}
+
+impl AsRef<[u8]> for Representative {
+	fn as_ref(&self) -> &[u8] {
+		&self.bytes
+	}
+}
+
+#[cfg(all(feature = "b64", feature = "serde"))]
+mod impl_serde {
+
+	use super::*;
+
+	use std::borrow::Cow;
+	use std::str::FromStr;
+
+	use _serde::de::Error;
+	use _serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+	impl Serialize for Representative {
+		fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+		where
+			S: Serializer,
+		{
+			serializer.collect_str(&self)
+		}
+	}
+
+	impl<'de> Deserialize<'de> for Representative {
+		fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+		where
+			D: Deserializer<'de>,
+		{
+			let s: Cow<'_, str> = Deserialize::deserialize(deserializer)?;
+			Self::from_str(s.as_ref()).map_err(D::Error::custom)
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+	use crate::cipher::{EphemeralKeypair, Keypair};
+
+	#[test]
+	fn representable_keypair() {
+		for _ in 0..8 {
+			let (alice, repr) = Keypair::new_representable();
+			let bob = Keypair::new();
+
+			let alice_public = repr.to_public_key();
+
+			let alice_ssk = alice.diffie_hellman(bob.public());
+			let bob_ssk = bob.diffie_hellman(&alice_public);
+			assert_eq!(alice_ssk, bob_ssk);
+		}
+	}
+
+	#[test]
+	fn representable_ephemeral_keypair() {
+		let (alice, repr) = EphemeralKeypair::new_representable();
+		let bob = Keypair::new();
+
+		let bob_ssk = bob.diffie_hellman(&repr.to_public_key());
+		let alice_ssk = alice.diffie_hellman(bob.public());
+		assert_eq!(alice_ssk, bob_ssk);
+	}
+
+	#[test]
+	fn on_curve() {
+		// checks the field arithmetic against curve25519-dalek
+		for _ in 0..16 {
+			let mut bytes = [0u8; 32];
+			fill_random(&mut bytes);
+			let public = Representative::from(bytes).to_public_key();
+
+			assert!(MontgomeryPoint(public.to_bytes()).to_edwards(0).is_some());
+		}
+	}
+
+	#[test]
+	fn public_key_roundtrip() {
+		let mut found = 0;
+		while found < 8 {
+			let public = Keypair::new().public().clone();
+			if let Some(repr) = Representative::from_public_key(&public) {
+				assert_eq!(repr.to_public_key(), public);
+				found += 1;
+			}
+		}
+	}
+
+	#[test]
+	fn padding_is_random() {
+		let mut high_bits = 0u8;
+		for _ in 0..32 {
+			let (_, repr) = Keypair::new_representable();
+			high_bits |= 1 << (repr.to_bytes()[31] >> 6);
+		}
+
+		// with a very high probability every combination was seen
+		assert_eq!(high_bits, 0b1111);
+	}
+
+	#[test]
+	fn every_representative_is_valid() {
+		for _ in 0..8 {
+			let mut bytes = [0u8; 32];
+			fill_random(&mut bytes);
+
+			let public = Representative::from(bytes).to_public_key();
+			// the point is on the curve, so diffie hellman does not
+			// fail
+			let ssk = Keypair::new().diffie_hellman(&public);
+			assert_ne!(ssk.as_slice(), [0u8; 32]);
+		}
+	}
+}
@@ -1,19 +1,37 @@
-use super::{Key, Nonce};
+use super::{Key, NonContributory, Nonce};
 
 use std::{cmp, fmt};
 
 use x25519_dalek as x;
 
+use zeroize::Zeroize;
+
 // should be hashed with
 pub struct SharedSecret {
-	inner: x::SharedSecret,
+	bytes: [u8; 32],
 }
 
 impl SharedSecret {
 	pub const LEN: usize = 32;
 
 	pub(crate) fn from_shared_secret(inner: x::SharedSecret) -> Self {
-		Self { inner }
+		Self::from_bytes(inner.to_bytes())
+	}
+
+	pub(crate) fn from_contributory(
+		inner: x::SharedSecret,
+	) -> Result<Self, NonContributory> {
+		if inner.was_contributory() {
+			Ok(Self::from_shared_secret(inner))
+		} else {
+			Err(NonContributory)
+		}
+	}
+
+	/// Used by protocols which derive the shared secret from multiple
+	/// diffie hellman exchanges (see x3dh).
+	pub(crate) fn from_bytes(bytes: [u8; 32]) -> Self {
+		Self { bytes }
 	}
 
 	// nonce size U24
@@ -25,11 +43,17 @@ impl SharedSecret {
 	}
 
 	fn to_bytes(&self) -> [u8; 32] {
-		self.inner.to_bytes()
+		self.bytes
 	}
 
 	pub(crate) fn as_slice(&self) -> &[u8] {
-		self.inner.as_bytes()
+		&self.bytes
+	}
+}
+
+impl Drop for SharedSecret {
+	fn drop(&mut self) {
+		self.bytes.zeroize();
 	}
 }
 
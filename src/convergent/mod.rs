@@ -0,0 +1,248 @@
+//! Convergent encryption, identical plaintexts produce identical ciphertexts.
+//!
+//! The key and nonce are derived from a keyed hash of the content and a
+//! tenant secret. This allows to deduplicate encrypted blocks within a
+//! tenant, while other tenants can't tell if they store the same content.
+//!
+//! ## Note
+//! Convergent encryption leaks if two ciphertexts contain the same
+//! plaintext. Anyone knowing the tenant secret can also confirm if a
+//! guessed plaintext is stored.
+//!
+//! ## Example
+//! ```
+//! use fire_crypto::convergent::ConvergentKey;
+//!
+//! let key = ConvergentKey::new();
+//!
+//! let mut block = *b"some block of a backup";
+//! let (id, mac) = key.encrypt(block.as_mut());
+//!
+//! let mut other = *b"some block of a backup";
+//! let (other_id, _) = key.encrypt(other.as_mut());
+//! // the same content leads to the same id and ciphertext
+//! assert_eq!(id, other_id);
+//! assert_eq!(block, other);
+//!
+//! key.decrypt(&id, block.as_mut(), &mac).unwrap();
+//! assert_eq!(&block, b"some block of a backup");
+//! ```
+
+use crate::cipher::{Key, Mac, Nonce};
+use crate::error::TryFromError;
+use crate::hash::{Hash, Hasher};
+
+use std::convert::{TryFrom, TryInto};
+use std::error::Error;
+use std::fmt;
+
+use zeroize::Zeroize;
+
+/// The id of some content, the same content always has the same id.
+pub type ContentId = Hash;
+
+const ID_DOMAIN: u8 = 0;
+const KEY_DOMAIN: u8 = 1;
+
+/// A secret per tenant used to encrypt content convergently.
+pub struct ConvergentKey {
+	secret: [u8; 32],
+}
+
+impl ConvergentKey {
+	pub const LEN: usize = 32;
+
+	/// Creates a new random tenant secret.
+	pub fn new() -> Self {
+		let mut secret = [0u8; 32];
+		crate::fill_random(&mut secret);
+
+		Self { secret }
+	}
+
+	/// ## Panics
+	/// if the slice is not 32 bytes long.
+	pub fn from_slice(slice: &[u8]) -> Self {
+		slice.try_into().unwrap()
+	}
+
+	pub fn to_bytes(&self) -> [u8; 32] {
+		self.secret
+	}
+
+	/// Returns the id of the content without encrypting it.
+	pub fn content_id(&self, msg: &[u8]) -> ContentId {
+		let mut hasher = Hasher::new_keyed(self.secret);
+		hasher.update([ID_DOMAIN]);
+		hasher.update(msg);
+		hasher.finalize()
+	}
+
+	fn content_key(&self, id: &ContentId) -> Key {
+		let mut hasher = Hasher::new_keyed(self.secret);
+		hasher.update([KEY_DOMAIN]);
+		hasher.update(id);
+		let mut bytes = hasher.finalize().to_bytes();
+
+		let secret = bytes[..32].try_into().unwrap();
+		let nonce = Nonce::from_slice(&bytes[32..32 + Nonce::LEN]);
+		bytes.zeroize();
+
+		Key::from_secret(secret, nonce)
+	}
+
+	/// Encrypts the message returning the content id and the mac.
+	///
+	/// Both need to be stored to be able to decrypt the message.
+	pub fn encrypt(&self, msg: &mut [u8]) -> (ContentId, Mac) {
+		let id = self.content_id(msg);
+		let mac = self.content_key(&id).encrypt(msg);
+
+		(id, mac)
+	}
+
+	/// Decrypts the message and verifies that the plaintext matches
+	/// its id.
+	///
+	/// If an error is returned the content of msg is unspecified.
+	pub fn decrypt(
+		&self,
+		id: &ContentId,
+		msg: &mut [u8],
+		mac: &Mac,
+	) -> Result<(), ConvergentError> {
+		self.content_key(id)
+			.decrypt(msg, mac)
+			.map_err(|_| ConvergentError::MacNotEqual)?;
+
+		if &self.content_id(msg) != id {
+			return Err(ConvergentError::IdMismatch);
+		}
+
+		Ok(())
+	}
+}
+
+impl fmt::Debug for ConvergentKey {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str("ConvergentKey")
+	}
+}
+
+impl From<[u8; 32]> for ConvergentKey {
+	fn from(secret: [u8; 32]) -> Self {
+		Self { secret }
+	}
+}
+
+impl TryFrom<&[u8]> for ConvergentKey {
+	type Error = TryFromError;
+
+	fn try_from(v: &[u8]) -> Result<Self, Self::Error> {
+		<[u8; 32]>::try_from(v)
+			.map_err(TryFromError::from_any)
+			.map(Self::from)
+	}
+}
+
+impl Drop for ConvergentKey {
+	fn drop(&mut self) {
+		self.secret.zeroize();
+	}
+}
+
+/// Get's returned if convergently encrypted content could not be decrypted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ConvergentError {
+	/// The mac does not match, either the id, the mac or the
+	/// ciphertext were modified.
+	MacNotEqual,
+	/// The decrypted plaintext does not belong to the id.
+	IdMismatch,
+}
+
+impl fmt::Display for ConvergentError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		fmt::Debug::fmt(self, f)
+	}
+}
+
+impl Error for ConvergentError {}
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+
+	#[test]
+	fn same_content_same_ciphertext() {
+		let key = ConvergentKey::new();
+
+		let msg = b"hey thats a nice block";
+		let mut msg1 = *msg;
+		let mut msg2 = *msg;
+
+		let (id1, mac1) = key.encrypt(&mut msg1);
+		let (id2, mac2) = key.encrypt(&mut msg2);
+
+		assert_eq!(id1, id2);
+		assert_eq!(mac1, mac2);
+		assert_eq!(msg1, msg2);
+		assert_ne!(&msg1, msg);
+
+		key.decrypt(&id1, &mut msg1, &mac1).unwrap();
+		assert_eq!(&msg1, msg);
+	}
+
+	#[test]
+	fn tenants_differ() {
+		let alice = ConvergentKey::new();
+		let bob = ConvergentKey::new();
+
+		let msg = b"hey thats a nice block";
+		let mut msg1 = *msg;
+		let mut msg2 = *msg;
+
+		let (id1, mac1) = alice.encrypt(&mut msg1);
+		let (id2, _) = bob.encrypt(&mut msg2);
+
+		assert_ne!(id1, id2);
+		assert_ne!(msg1, msg2);
+
+		assert_eq!(
+			bob.decrypt(&id1, &mut msg1, &mac1),
+			Err(ConvergentError::MacNotEqual)
+		);
+	}
+
+	#[test]
+	fn modified_ciphertext() {
+		let key = ConvergentKey::new();
+
+		let mut msg = *b"hey thats a nice block";
+		let (id, mac) = key.encrypt(&mut msg);
+		msg[0] ^= 1;
+
+		assert_eq!(
+			key.decrypt(&id, &mut msg, &mac),
+			Err(ConvergentError::MacNotEqual)
+		);
+	}
+
+	#[test]
+	fn id_mismatch() {
+		let key = ConvergentKey::new();
+
+		// someone knowing the tenant secret could store content
+		// under the id of other content
+		let id = key.content_id(b"the real content");
+		let mut msg = *b"some other content";
+		let mac = key.content_key(&id).encrypt(&mut msg);
+
+		assert_eq!(
+			key.decrypt(&id, &mut msg, &mac),
+			Err(ConvergentError::IdMismatch)
+		);
+	}
+}
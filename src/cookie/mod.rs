@@ -0,0 +1,407 @@
+//! Signed or encrypted cookie values.
+//!
+//! A [`CookieCodec`] turns a value into a string which only contains
+//! base64 (url safe, without padding) characters and can be used as a
+//! cookie value without further escaping.
+//!
+//! - Signed cookies can be read by the client but not modified, they are
+//!   authenticated with keyed Blake2b.
+//! - Encrypted cookies can neither be read nor modified, they use
+//!   XChaCha20-Poly1305.
+//!
+//! The name of the cookie and the expiry are authenticated, so a value
+//! can't be moved to another cookie or used after it expired.
+//!
+//! Keys come from a [`Keyring`], new cookies always use the primary key,
+//! cookies issued with an older key can be decoded as long as the key is
+//! in the keyring. [`DecodedCookie::key_id`] tells if the cookie should be
+//! issued again.
+//!
+//! ## Example
+//! ```
+//! use fire_crypto::cookie::CookieCodec;
+//! use fire_crypto::keyring::Keyring;
+//!
+//! use std::time::{Duration, SystemTime};
+//!
+//! let mut keyring = Keyring::new();
+//! keyring.generate_key();
+//! let codec = CookieCodec::encrypted(keyring);
+//!
+//! let expires = SystemTime::now() + Duration::from_secs(60 * 60);
+//! let cookie = codec
+//!     .encode("session", b"user-id=42", Some(expires))
+//!     .unwrap();
+//!
+//! let decoded = codec.decode("session", &cookie).unwrap();
+//! assert_eq!(decoded.value, b"user-id=42");
+//!
+//! // the name is authenticated
+//! assert!(codec.decode("other", &cookie).is_err());
+//! ```
+
+use crate::hash::Hasher;
+use crate::keyring::{KeyId, Keyring, KeyringError};
+
+use std::convert::TryInto;
+use std::error::Error;
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+
+use zeroize::Zeroize;
+
+const SIGN_CONTEXT: &[u8] = b"fire-crypto cookie sign";
+const AAD_CONTEXT: &[u8] = b"fire-crypto cookie";
+
+const KEY_ID_LEN: usize = 4;
+const EXPIRES_LEN: usize = 8;
+const TAG_LEN: usize = 32;
+
+/// Get's returned if a cookie could not be encoded or decoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CookieError {
+	/// The cookie is not valid base64 or too short.
+	InvalidFormat,
+	/// The cookie was modified, belongs to another name or was issued
+	/// with another key.
+	MacNotEqual,
+	/// The cookie is authentic but expired.
+	Expired,
+	Keyring(KeyringError),
+}
+
+impl From<KeyringError> for CookieError {
+	fn from(e: KeyringError) -> Self {
+		match e {
+			KeyringError::MacNotEqual => Self::MacNotEqual,
+			KeyringError::InvalidCiphertext => Self::InvalidFormat,
+			e => Self::Keyring(e),
+		}
+	}
+}
+
+impl fmt::Display for CookieError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		fmt::Debug::fmt(self, f)
+	}
+}
+
+impl Error for CookieError {}
+
+/// If the cookie value is only signed or also encrypted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CookieMode {
+	Signed,
+	Encrypted,
+}
+
+/// A cookie value which was successfully decoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedCookie {
+	pub value: Vec<u8>,
+	/// `None` if the cookie does not expire.
+	pub expires: Option<SystemTime>,
+	/// The key which was used to encode the cookie, if it is not the
+	/// primary key the cookie should be encoded again.
+	pub key_id: KeyId,
+}
+
+fn expires_to_bytes(expires: Option<SystemTime>) -> [u8; EXPIRES_LEN] {
+	// 0 means the cookie does not expire
+	let secs = expires
+		.map(|e| {
+			e.duration_since(UNIX_EPOCH)
+				.map(|d| d.as_secs().max(1))
+				.unwrap_or(1)
+		})
+		.unwrap_or(0);
+
+	secs.to_be_bytes()
+}
+
+fn expires_from_bytes(bytes: &[u8]) -> Option<SystemTime> {
+	match u64::from_be_bytes(bytes.try_into().unwrap()) {
+		0 => None,
+		secs => Some(UNIX_EPOCH + Duration::from_secs(secs)),
+	}
+}
+
+/// The name is length prefixed so `a` + `bc` and `ab` + `c` differ.
+fn aad(name: &str) -> Vec<u8> {
+	let mut aad = Vec::with_capacity(AAD_CONTEXT.len() + 8 + name.len());
+	aad.extend_from_slice(AAD_CONTEXT);
+	aad.extend_from_slice(&(name.len() as u64).to_be_bytes());
+	aad.extend_from_slice(name.as_bytes());
+	aad
+}
+
+/// Encodes and decodes cookie values.
+#[derive(Debug, Clone)]
+pub struct CookieCodec {
+	keyring: Keyring,
+	mode: CookieMode,
+}
+
+impl CookieCodec {
+	pub fn new(keyring: Keyring, mode: CookieMode) -> Self {
+		Self { keyring, mode }
+	}
+
+	pub fn signed(keyring: Keyring) -> Self {
+		Self::new(keyring, CookieMode::Signed)
+	}
+
+	pub fn encrypted(keyring: Keyring) -> Self {
+		Self::new(keyring, CookieMode::Encrypted)
+	}
+
+	pub fn mode(&self) -> CookieMode {
+		self.mode
+	}
+
+	pub fn keyring(&self) -> &Keyring {
+		&self.keyring
+	}
+
+	/// Allows to rotate keys.
+	pub fn keyring_mut(&mut self) -> &mut Keyring {
+		&mut self.keyring
+	}
+
+	fn sign_tag(&self, id: KeyId, name: &str, msg: &[u8]) -> Option<[u8; 32]> {
+		let key = self.keyring.key(id)?;
+
+		let mut sign_key = Hasher::hash_keyed(key, SIGN_CONTEXT).to_bytes();
+		let mut hasher = Hasher::new_keyed(&sign_key[..32]);
+		sign_key.zeroize();
+		hasher.update(aad(name));
+		hasher.update(msg);
+
+		let hash = hasher.finalize().to_bytes();
+		Some(hash[..TAG_LEN].try_into().unwrap())
+	}
+
+	/// Encodes the value, expires should be the same as the expiry of the
+	/// cookie itself.
+	pub fn encode(
+		&self,
+		name: &str,
+		value: &[u8],
+		expires: Option<SystemTime>,
+	) -> Result<String, CookieError> {
+		let expires = expires_to_bytes(expires);
+
+		let bytes = match self.mode {
+			CookieMode::Signed => {
+				let id = self
+					.keyring
+					.primary_id()
+					.ok_or(KeyringError::NoPrimaryKey)?;
+
+				let mut bytes = Vec::with_capacity(
+					KEY_ID_LEN + EXPIRES_LEN + value.len() + TAG_LEN,
+				);
+				bytes.extend_from_slice(&id.to_be_bytes());
+				bytes.extend_from_slice(&expires);
+				bytes.extend_from_slice(value);
+				let tag = self.sign_tag(id, name, &bytes).unwrap();
+				bytes.extend_from_slice(&tag);
+				bytes
+			}
+			CookieMode::Encrypted => {
+				let mut msg = Vec::with_capacity(EXPIRES_LEN + value.len());
+				msg.extend_from_slice(&expires);
+				msg.extend_from_slice(value);
+				let bytes = self.keyring.encrypt_with_aad(&msg, &aad(name))?;
+				msg.zeroize();
+				bytes
+			}
+		};
+
+		Ok(URL_SAFE_NO_PAD.encode(bytes))
+	}
+
+	/// Decodes the cookie and checks that it is not expired.
+	pub fn decode(
+		&self,
+		name: &str,
+		cookie: &str,
+	) -> Result<DecodedCookie, CookieError> {
+		self.decode_at(name, cookie, SystemTime::now())
+	}
+
+	/// Decodes the cookie and checks that it was not expired at `now`.
+	pub fn decode_at(
+		&self,
+		name: &str,
+		cookie: &str,
+		now: SystemTime,
+	) -> Result<DecodedCookie, CookieError> {
+		let bytes = URL_SAFE_NO_PAD
+			.decode(cookie)
+			.map_err(|_| CookieError::InvalidFormat)?;
+		let key_id =
+			Keyring::key_id_of(&bytes).ok_or(CookieError::InvalidFormat)?;
+
+		// contains the expiry followed by the value
+		let msg = match self.mode {
+			CookieMode::Signed => {
+				if bytes.len() < KEY_ID_LEN + EXPIRES_LEN + TAG_LEN {
+					return Err(CookieError::InvalidFormat);
+				}
+
+				let (msg, tag) = bytes.split_at(bytes.len() - TAG_LEN);
+				let expected = self
+					.sign_tag(key_id, name, msg)
+					.ok_or(KeyringError::UnknownKeyId)?;
+				if !crate::constant_time_eq(&expected, tag) {
+					return Err(CookieError::MacNotEqual);
+				}
+
+				msg[KEY_ID_LEN..].to_vec()
+			}
+			CookieMode::Encrypted => {
+				let msg = self.keyring.decrypt_with_aad(&bytes, &aad(name))?;
+				if msg.len() < EXPIRES_LEN {
+					return Err(CookieError::InvalidFormat);
+				}
+
+				msg
+			}
+		};
+
+		let expires = expires_from_bytes(&msg[..EXPIRES_LEN]);
+		if matches!(expires, Some(expires) if now >= expires) {
+			return Err(CookieError::Expired);
+		}
+
+		Ok(DecodedCookie {
+			value: msg[EXPIRES_LEN..].to_vec(),
+			expires,
+			key_id,
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+
+	fn keyring() -> Keyring {
+		let mut keyring = Keyring::new();
+		keyring.generate_key();
+		keyring
+	}
+
+	#[test]
+	fn signed() {
+		let codec = CookieCodec::signed(keyring());
+
+		let cookie = codec.encode("theme", b"dark", None).unwrap();
+		assert!(cookie
+			.bytes()
+			.all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_'));
+
+		let decoded = codec.decode("theme", &cookie).unwrap();
+		assert_eq!(decoded.value, b"dark");
+		assert_eq!(decoded.expires, None);
+
+		// the value is readable
+		let bytes = URL_SAFE_NO_PAD.decode(&cookie).unwrap();
+		assert!(bytes.windows(4).any(|w| w == b"dark"));
+
+		let mut modified = bytes.clone();
+		modified[KEY_ID_LEN + EXPIRES_LEN] ^= 1;
+		let modified = URL_SAFE_NO_PAD.encode(modified);
+		assert_eq!(
+			codec.decode("theme", &modified).unwrap_err(),
+			CookieError::MacNotEqual
+		);
+		assert_eq!(
+			codec.decode("themes", &cookie).unwrap_err(),
+			CookieError::MacNotEqual
+		);
+
+		// an encrypted codec with the same keys can't read signed cookies
+		let encrypted = CookieCodec::encrypted(codec.keyring().clone());
+		assert!(encrypted.decode("theme", &cookie).is_err());
+	}
+
+	#[test]
+	fn encrypted() {
+		let codec = CookieCodec::encrypted(keyring());
+
+		let cookie = codec.encode("session", b"user-id=42", None).unwrap();
+		let bytes = URL_SAFE_NO_PAD.decode(&cookie).unwrap();
+		assert!(!bytes.windows(7).any(|w| w == b"user-id"));
+
+		assert_eq!(
+			codec.decode("session", &cookie).unwrap().value,
+			b"user-id=42"
+		);
+		assert_eq!(
+			codec.decode("Session", &cookie).unwrap_err(),
+			CookieError::MacNotEqual
+		);
+		assert_eq!(
+			codec.decode("session", "not a cookie").unwrap_err(),
+			CookieError::InvalidFormat
+		);
+		assert_eq!(
+			codec.decode("session", "AAAA").unwrap_err(),
+			CookieError::InvalidFormat
+		);
+	}
+
+	#[test]
+	fn expiry() {
+		let now = SystemTime::now();
+		let in_an_hour = now + Duration::from_secs(60 * 60);
+
+		for codec in [
+			CookieCodec::signed(keyring()),
+			CookieCodec::encrypted(keyring()),
+		] {
+			let cookie = codec.encode("a", b"1", Some(in_an_hour)).unwrap();
+
+			let decoded = codec.decode_at("a", &cookie, now).unwrap();
+			let expires = decoded.expires.unwrap();
+			assert!(expires <= in_an_hour);
+			assert!(in_an_hour.duration_since(expires).unwrap().as_secs() < 1);
+
+			assert_eq!(
+				codec.decode_at("a", &cookie, in_an_hour).unwrap_err(),
+				CookieError::Expired
+			);
+		}
+	}
+
+	#[test]
+	fn rotation() {
+		for mode in [CookieMode::Signed, CookieMode::Encrypted] {
+			let mut codec = CookieCodec::new(keyring(), mode);
+			let first = codec.keyring().primary_id().unwrap();
+			let old = codec.encode("a", b"old", None).unwrap();
+
+			let second = codec.keyring_mut().generate_key();
+			codec.keyring_mut().promote(second).unwrap();
+			let new = codec.encode("a", b"new", None).unwrap();
+
+			let decoded = codec.decode("a", &old).unwrap();
+			assert_eq!(decoded.value, b"old");
+			assert_eq!(decoded.key_id, first);
+			assert_eq!(codec.decode("a", &new).unwrap().key_id, second);
+
+			codec.keyring_mut().retire(first).unwrap();
+			assert_eq!(
+				codec.decode("a", &old).unwrap_err(),
+				CookieError::Keyring(KeyringError::UnknownKeyId)
+			);
+		}
+	}
+}
@@ -0,0 +1,363 @@
+//! A minimal line based kms protocol, see [`KmsKeyProvider`].
+
+use super::{KeyProvider, ProviderError};
+
+use std::io;
+
+use zeroize::Zeroize;
+
+fn to_hex(bytes: &[u8]) -> String {
+	bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn from_hex(s: &str) -> Option<Vec<u8>> {
+	if s.len() % 2 != 0 {
+		return None;
+	}
+
+	(0..s.len())
+		.step_by(2)
+		.map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+		.collect()
+}
+
+fn from_error_code(code: &str) -> ProviderError {
+	match code {
+		"NO_CURRENT_KEY" => ProviderError::NoCurrentKey,
+		"UNKNOWN_KEY_ID" => ProviderError::UnknownKeyId,
+		"INVALID_KEY_ID" => ProviderError::InvalidKeyId,
+		"INVALID_WRAPPED_KEY" => ProviderError::InvalidWrappedKey,
+		code => io::Error::new(
+			io::ErrorKind::Other,
+			format!("kms returned error {code}"),
+		)
+		.into(),
+	}
+}
+
+fn parse_response(line: &str) -> Result<String, ProviderError> {
+	if let Some(resp) = line.strip_prefix("OK ") {
+		Ok(resp.to_string())
+	} else if let Some(code) = line.strip_prefix("ERR ") {
+		Err(from_error_code(code))
+	} else {
+		Err(
+			io::Error::new(io::ErrorKind::InvalidData, "invalid kms response")
+				.into(),
+		)
+	}
+}
+
+/// Sends a request line to a kms and returns the response line.
+///
+/// See [`KmsKeyProvider`] for the protocol and why the connection needs
+/// to be encrypted.
+pub trait KmsTransport {
+	/// The request ends with a newline, the returned response may or may
+	/// not.
+	fn send(&self, request: &str) -> io::Result<String>;
+}
+
+/// A [`KeyProvider`] which talks to a kms with a minimal line based
+/// protocol.
+///
+/// Every request is one line and receives one response line. Binary data
+/// is hex encoded.
+///
+/// - `CURRENT` -> `OK <key_id>`
+/// - `WRAP <key_id> <data_key>` -> `OK <wrapped_key>`
+/// - `UNWRAP <key_id> <wrapped_key>` -> `OK <data_key>`
+///
+/// Errors are returned as `ERR <code>`.
+///
+/// ## Warning
+/// Data keys are sent in plain text, the [`KmsTransport`] needs to
+/// authenticate the kms and encrypt the connection (for example with TLS).
+/// Otherwise anyone on the network path can read every data key.
+#[derive(Debug, Clone)]
+pub struct KmsKeyProvider<T> {
+	transport: T,
+}
+
+impl<T: KmsTransport> KmsKeyProvider<T> {
+	pub fn with_transport(transport: T) -> Self {
+		Self { transport }
+	}
+
+	fn request(&self, req: &str) -> Result<String, ProviderError> {
+		let mut line = self.transport.send(req)?;
+		let r = parse_response(line.trim_end());
+		line.zeroize();
+
+		r
+	}
+
+	fn request_bytes(&self, req: &str) -> Result<Vec<u8>, ProviderError> {
+		let mut resp = self.request(req)?;
+		let bytes = from_hex(&resp).ok_or_else(|| {
+			io::Error::new(io::ErrorKind::InvalidData, "invalid kms response")
+		});
+		resp.zeroize();
+
+		Ok(bytes?)
+	}
+}
+
+impl<T: KmsTransport> KeyProvider for KmsKeyProvider<T> {
+	fn current_key_id(&self) -> Result<String, ProviderError> {
+		self.request("CURRENT\n")
+	}
+
+	fn wrap(
+		&self,
+		key_id: &str,
+		data_key: &[u8],
+	) -> Result<Vec<u8>, ProviderError> {
+		if !super::is_valid_key_id(key_id) {
+			return Err(ProviderError::InvalidKeyId);
+		}
+
+		let mut req = format!("WRAP {key_id} {}\n", to_hex(data_key));
+		let r = self.request_bytes(&req);
+		req.zeroize();
+
+		r
+	}
+
+	fn unwrap(
+		&self,
+		key_id: &str,
+		wrapped_key: &[u8],
+	) -> Result<Vec<u8>, ProviderError> {
+		if !super::is_valid_key_id(key_id) {
+			return Err(ProviderError::InvalidKeyId);
+		}
+
+		self.request_bytes(&format!(
+			"UNWRAP {key_id} {}\n",
+			to_hex(wrapped_key)
+		))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+	use crate::envelope::{Envelope, EnvelopeError, MemoryKeyProvider};
+
+	use std::cell::RefCell;
+	use std::io::{BufRead, BufReader, Write};
+	use std::net::{SocketAddr, TcpListener, TcpStream};
+	use std::sync::atomic::{AtomicBool, Ordering};
+	use std::sync::{Arc, RwLock};
+	use std::thread::{self, JoinHandle};
+	use std::time::Duration;
+
+	const TIMEOUT: Duration = Duration::from_secs(5);
+
+	fn error_code(e: &ProviderError) -> &'static str {
+		match e {
+			ProviderError::NoCurrentKey => "NO_CURRENT_KEY",
+			ProviderError::UnknownKeyId => "UNKNOWN_KEY_ID",
+			ProviderError::InvalidKeyId => "INVALID_KEY_ID",
+			ProviderError::InvalidWrappedKey => "INVALID_WRAPPED_KEY",
+			ProviderError::Io(_) => "IO",
+		}
+	}
+
+	/// Handles a request like a kms would.
+	fn handle_request(line: &str, keys: &MemoryKeyProvider) -> String {
+		let mut parts = line.trim_end().split(' ');
+
+		let res = match (parts.next(), parts.next(), parts.next()) {
+			(Some("CURRENT"), None, None) => keys.current_key_id().map(Some),
+			(Some("WRAP"), Some(key_id), Some(data)) => match from_hex(data) {
+				Some(data) => {
+					keys.wrap(key_id, &data).map(|w| Some(to_hex(&w)))
+				}
+				None => Ok(None),
+			},
+			(Some("UNWRAP"), Some(key_id), Some(data)) => {
+				match from_hex(data) {
+					Some(data) => {
+						keys.unwrap(key_id, &data).map(|k| Some(to_hex(&k)))
+					}
+					None => Ok(None),
+				}
+			}
+			_ => Ok(None),
+		};
+
+		match res {
+			Ok(Some(s)) => format!("OK {s}\n"),
+			Ok(None) => "ERR BAD_REQUEST\n".to_string(),
+			Err(e) => format!("ERR {}\n", error_code(&e)),
+		}
+	}
+
+	/// A kms server listening on localhost, the master keys are only kept
+	/// in memory. The server get's stopped when it is dropped.
+	struct MockKmsServer {
+		addr: SocketAddr,
+		keys: Arc<RwLock<MemoryKeyProvider>>,
+		shutdown: Arc<AtomicBool>,
+		handle: Option<JoinHandle<()>>,
+	}
+
+	impl MockKmsServer {
+		fn start() -> io::Result<Self> {
+			let listener = TcpListener::bind("127.0.0.1:0")?;
+			let addr = listener.local_addr()?;
+			let keys = Arc::new(RwLock::new(MemoryKeyProvider::new()));
+			let shutdown = Arc::new(AtomicBool::new(false));
+
+			let handle = {
+				let keys = keys.clone();
+				let shutdown = shutdown.clone();
+				thread::spawn(move || {
+					for stream in listener.incoming() {
+						if shutdown.load(Ordering::Relaxed) {
+							break;
+						}
+
+						// a failing connection should not stop the server
+						if let Ok(stream) = stream {
+							let _ = handle_connection(stream, &keys);
+						}
+					}
+				})
+			};
+
+			Ok(Self {
+				addr,
+				keys,
+				shutdown,
+				handle: Some(handle),
+			})
+		}
+
+		fn generate_key(&self, key_id: &str) {
+			self.keys.write().unwrap().generate_key(key_id);
+		}
+
+		fn remove_key(&self, key_id: &str) {
+			self.keys.write().unwrap().remove_key(key_id);
+		}
+	}
+
+	impl Drop for MockKmsServer {
+		fn drop(&mut self) {
+			self.shutdown.store(true, Ordering::Relaxed);
+			// wake up the listener
+			let _ = TcpStream::connect(self.addr);
+
+			if let Some(handle) = self.handle.take() {
+				let _ = handle.join();
+			}
+		}
+	}
+
+	fn handle_connection(
+		stream: TcpStream,
+		keys: &RwLock<MemoryKeyProvider>,
+	) -> io::Result<()> {
+		stream.set_read_timeout(Some(TIMEOUT))?;
+
+		let mut line = String::new();
+		BufReader::new(&stream).read_line(&mut line)?;
+
+		let resp = handle_request(&line, &keys.read().unwrap());
+		(&stream).write_all(resp.as_bytes())
+	}
+
+	/// Opens a tcp connection for every request, only used to talk to the
+	/// mock server on localhost.
+	struct TcpTransport {
+		addr: SocketAddr,
+	}
+
+	impl KmsTransport for TcpTransport {
+		fn send(&self, request: &str) -> io::Result<String> {
+			let mut stream = TcpStream::connect_timeout(&self.addr, TIMEOUT)?;
+			stream.set_read_timeout(Some(TIMEOUT))?;
+			stream.write_all(request.as_bytes())?;
+
+			let mut line = String::new();
+			BufReader::new(&stream).read_line(&mut line)?;
+
+			Ok(line)
+		}
+	}
+
+	/// Calls the kms directly and records the requests.
+	struct MemoryTransport {
+		keys: MemoryKeyProvider,
+		requests: RefCell<Vec<String>>,
+	}
+
+	impl KmsTransport for MemoryTransport {
+		fn send(&self, request: &str) -> io::Result<String> {
+			self.requests.borrow_mut().push(request.to_string());
+			Ok(handle_request(request, &self.keys))
+		}
+	}
+
+	#[test]
+	fn hex() {
+		assert_eq!(to_hex(&[0, 15, 255]), "000fff");
+		assert_eq!(from_hex("000fff").unwrap(), [0, 15, 255]);
+		assert!(from_hex("0").is_none());
+		assert!(from_hex("zz").is_none());
+	}
+
+	#[test]
+	fn mock_kms() {
+		let server = MockKmsServer::start().unwrap();
+		let provider =
+			KmsKeyProvider::with_transport(TcpTransport { addr: server.addr });
+
+		assert!(matches!(
+			provider.current_key_id(),
+			Err(ProviderError::NoCurrentKey)
+		));
+
+		server.generate_key("a");
+		let mut envelope =
+			Envelope::seal(&provider, b"hello".to_vec()).unwrap();
+		assert_eq!(envelope.key_id(), "a");
+		assert_eq!(envelope.open(&provider).unwrap(), b"hello");
+
+		server.generate_key("b");
+		envelope.rewrap(&provider).unwrap();
+		server.remove_key("a");
+		assert_eq!(envelope.open(&provider).unwrap(), b"hello");
+
+		assert!(matches!(
+			envelope.rewrap_to(&provider, "a"),
+			Err(EnvelopeError::Provider(ProviderError::UnknownKeyId))
+		));
+		assert!(matches!(
+			provider.unwrap("b", b"invalid"),
+			Err(ProviderError::InvalidWrappedKey)
+		));
+	}
+
+	#[test]
+	fn custom_transport() {
+		let mut keys = MemoryKeyProvider::new();
+		keys.generate_key("a");
+		let provider = KmsKeyProvider::with_transport(MemoryTransport {
+			keys,
+			requests: RefCell::new(vec![]),
+		});
+
+		let envelope = Envelope::seal(&provider, b"hello".to_vec()).unwrap();
+		assert_eq!(envelope.open(&provider).unwrap(), b"hello");
+
+		let requests = provider.transport.requests.borrow();
+		assert_eq!(requests[0], "CURRENT\n");
+		assert!(requests[1].starts_with("WRAP a "));
+		assert!(requests[2].starts_with("UNWRAP a "));
+	}
+}
@@ -0,0 +1,538 @@
+//! Envelope encryption for data at rest.
+//!
+//! Every object is encrypted with a fresh data key. The data key is then
+//! wrapped (encrypted) by a master key which never leaves its
+//! [`KeyProvider`]. The id of the master key and the wrapped data key are
+//! stored next to the ciphertext.
+//!
+//! Rotating the master key only requires to re-wrap the data key, the
+//! data itself does not need to be encrypted again.
+//!
+//! ## Example
+//! ```
+//! use fire_crypto::envelope::{Envelope, MemoryKeyProvider};
+//!
+//! let mut provider = MemoryKeyProvider::new();
+//! provider.generate_key("master-1");
+//!
+//! let envelope = Envelope::seal(&provider, b"secret data".to_vec()).unwrap();
+//! assert_eq!(envelope.key_id(), "master-1");
+//!
+//! // rotate the master key
+//! provider.generate_key("master-2");
+//!
+//! let mut envelope = Envelope::from_bytes(&envelope.to_bytes()).unwrap();
+//! envelope.rewrap(&provider).unwrap();
+//! assert_eq!(envelope.key_id(), "master-2");
+//!
+//! assert_eq!(envelope.open(&provider).unwrap(), b"secret data");
+//! ```
+
+mod provider;
+pub use provider::{FileKeyProvider, MemoryKeyProvider};
+
+mod kms;
+pub use kms::{KmsKeyProvider, KmsTransport};
+
+use crate::cipher::{Key, Mac, Nonce};
+use crate::error::DecodeError;
+
+use std::convert::TryInto;
+use std::error::Error;
+use std::{fmt, io};
+
+use zeroize::Zeroize;
+
+/// The length of an unwrapped data key (secret and nonce).
+pub const DATA_KEY_LEN: usize = 32 + Nonce::LEN;
+
+/// The length a master key needs to have.
+pub const MASTER_KEY_LEN: usize = 32;
+
+/// The maximum length of a key id.
+pub const MAX_KEY_ID_LEN: usize = 255;
+
+/// Holds master keys and wraps or unwraps data keys with them.
+///
+/// The master key should never leave the provider.
+pub trait KeyProvider {
+	/// Returns the id of the master key which should be used to wrap new
+	/// data keys.
+	fn current_key_id(&self) -> Result<String, ProviderError>;
+
+	/// Wraps the data key with the master key `key_id`.
+	fn wrap(
+		&self,
+		key_id: &str,
+		data_key: &[u8],
+	) -> Result<Vec<u8>, ProviderError>;
+
+	/// Unwraps a data key which was wrapped with the master key `key_id`.
+	fn unwrap(
+		&self,
+		key_id: &str,
+		wrapped_key: &[u8],
+	) -> Result<Vec<u8>, ProviderError>;
+}
+
+impl<T> KeyProvider for &T
+where
+	T: KeyProvider + ?Sized,
+{
+	fn current_key_id(&self) -> Result<String, ProviderError> {
+		(**self).current_key_id()
+	}
+
+	fn wrap(
+		&self,
+		key_id: &str,
+		data_key: &[u8],
+	) -> Result<Vec<u8>, ProviderError> {
+		(**self).wrap(key_id, data_key)
+	}
+
+	fn unwrap(
+		&self,
+		key_id: &str,
+		wrapped_key: &[u8],
+	) -> Result<Vec<u8>, ProviderError> {
+		(**self).unwrap(key_id, wrapped_key)
+	}
+}
+
+/// Returns true if the key id is not empty, at most [`MAX_KEY_ID_LEN`]
+/// long and only contains ascii alphanumeric characters, `-`, `_` or `.`.
+///
+/// Key ids are used as file names and in the kms protocol, so other
+/// characters are not allowed.
+pub fn is_valid_key_id(key_id: &str) -> bool {
+	!key_id.is_empty()
+		&& key_id.len() <= MAX_KEY_ID_LEN
+		&& key_id
+			.bytes()
+			.all(|b| b.is_ascii_alphanumeric() || b"-_.".contains(&b))
+		// don't allow relative paths
+		&& key_id != "."
+		&& key_id != ".."
+}
+
+/// Wraps a data key with a master key.
+///
+/// The result contains a random nonce, the encrypted data key and a mac.
+pub(crate) fn wrap_with(master_key: &[u8; 32], data_key: &[u8]) -> Vec<u8> {
+	let nonce = Nonce::new();
+	let mut wrapped = nonce.to_bytes().to_vec();
+	wrapped.extend_from_slice(data_key);
+
+	let mut key = Key::from_secret(*master_key, nonce);
+	let mac = key.encrypt(&mut wrapped[Nonce::LEN..]);
+	wrapped.extend_from_slice(&mac.into_bytes());
+
+	wrapped
+}
+
+/// Unwraps a data key wrapped by [`wrap_with`].
+pub(crate) fn unwrap_with(
+	master_key: &[u8; 32],
+	wrapped_key: &[u8],
+) -> Result<Vec<u8>, ProviderError> {
+	if wrapped_key.len() < Nonce::LEN + Mac::LEN {
+		return Err(ProviderError::InvalidWrappedKey);
+	}
+
+	let (nonce, rest) = wrapped_key.split_at(Nonce::LEN);
+	let (data_key, mac) = rest.split_at(rest.len() - Mac::LEN);
+
+	let mut data_key = data_key.to_vec();
+	let mut key = Key::from_secret(*master_key, Nonce::from_slice(nonce));
+	match key.decrypt(&mut data_key, &Mac::from_slice(mac)) {
+		Ok(()) => Ok(data_key),
+		Err(_) => {
+			data_key.zeroize();
+			Err(ProviderError::InvalidWrappedKey)
+		}
+	}
+}
+
+/// A key which is used to encrypt a single object.
+struct DataKey {
+	bytes: [u8; DATA_KEY_LEN],
+}
+
+impl DataKey {
+	fn new() -> Self {
+		let mut bytes = [0u8; DATA_KEY_LEN];
+		crate::fill_random(&mut bytes);
+
+		Self { bytes }
+	}
+
+	fn from_unwrapped(mut bytes: Vec<u8>) -> Result<Self, EnvelopeError> {
+		let r = bytes
+			.as_slice()
+			.try_into()
+			.map(|bytes| Self { bytes })
+			.map_err(|_| EnvelopeError::InvalidDataKey);
+		bytes.zeroize();
+
+		r
+	}
+
+	fn to_key(&self) -> Key {
+		Key::from_secret(
+			self.bytes[..32].try_into().unwrap(),
+			Nonce::from_slice(&self.bytes[32..]),
+		)
+	}
+}
+
+impl Drop for DataKey {
+	fn drop(&mut self) {
+		self.bytes.zeroize();
+	}
+}
+
+/// An encrypted object together with its wrapped data key.
+///
+/// ## Format
+/// `to_bytes` returns `key_id_len (u8) | key_id | wrapped_key_len (u16 be)
+/// | wrapped_key | mac | ciphertext`.
+#[derive(Clone)]
+pub struct Envelope {
+	key_id: String,
+	wrapped_key: Vec<u8>,
+	mac: Mac,
+	ciphertext: Vec<u8>,
+}
+
+impl Envelope {
+	/// Encrypts the data with a fresh data key and wraps the data key
+	/// with the current master key of the provider.
+	pub fn seal<P>(
+		provider: &P,
+		mut data: Vec<u8>,
+	) -> Result<Self, EnvelopeError>
+	where
+		P: KeyProvider + ?Sized,
+	{
+		let key_id = provider.current_key_id()?;
+		if !is_valid_key_id(&key_id) {
+			return Err(ProviderError::InvalidKeyId.into());
+		}
+
+		let data_key = DataKey::new();
+		let wrapped_key = provider.wrap(&key_id, &data_key.bytes)?;
+		if wrapped_key.len() > u16::MAX as usize {
+			return Err(EnvelopeError::InvalidDataKey);
+		}
+
+		let mac = data_key.to_key().encrypt(&mut data);
+
+		Ok(Self {
+			key_id,
+			wrapped_key,
+			mac,
+			ciphertext: data,
+		})
+	}
+
+	/// Unwraps the data key and decrypts the data.
+	pub fn open<P>(&self, provider: &P) -> Result<Vec<u8>, EnvelopeError>
+	where
+		P: KeyProvider + ?Sized,
+	{
+		let data_key = self.unwrap_data_key(provider)?;
+
+		let mut data = self.ciphertext.clone();
+		match data_key.to_key().decrypt(&mut data, &self.mac) {
+			Ok(()) => Ok(data),
+			Err(_) => {
+				data.zeroize();
+				Err(EnvelopeError::MacNotEqual)
+			}
+		}
+	}
+
+	/// Wraps the data key with the current master key of the provider.
+	///
+	/// The ciphertext is not changed.
+	pub fn rewrap<P>(&mut self, provider: &P) -> Result<(), EnvelopeError>
+	where
+		P: KeyProvider + ?Sized,
+	{
+		let key_id = provider.current_key_id()?;
+		self.rewrap_to(provider, &key_id)
+	}
+
+	/// Wraps the data key with the master key `key_id`.
+	///
+	/// The ciphertext is not changed.
+	pub fn rewrap_to<P>(
+		&mut self,
+		provider: &P,
+		key_id: &str,
+	) -> Result<(), EnvelopeError>
+	where
+		P: KeyProvider + ?Sized,
+	{
+		if !is_valid_key_id(key_id) {
+			return Err(ProviderError::InvalidKeyId.into());
+		}
+
+		let data_key = self.unwrap_data_key(provider)?;
+		let wrapped_key = provider.wrap(key_id, &data_key.bytes)?;
+		if wrapped_key.len() > u16::MAX as usize {
+			return Err(EnvelopeError::InvalidDataKey);
+		}
+
+		self.key_id = key_id.to_string();
+		self.wrapped_key = wrapped_key;
+
+		Ok(())
+	}
+
+	fn unwrap_data_key<P>(&self, provider: &P) -> Result<DataKey, EnvelopeError>
+	where
+		P: KeyProvider + ?Sized,
+	{
+		let unwrapped = provider.unwrap(&self.key_id, &self.wrapped_key)?;
+		DataKey::from_unwrapped(unwrapped)
+	}
+
+	/// The id of the master key which wrapped the data key.
+	pub fn key_id(&self) -> &str {
+		&self.key_id
+	}
+
+	pub fn wrapped_key(&self) -> &[u8] {
+		&self.wrapped_key
+	}
+
+	pub fn ciphertext(&self) -> &[u8] {
+		&self.ciphertext
+	}
+
+	pub fn to_bytes(&self) -> Vec<u8> {
+		let mut bytes = Vec::with_capacity(
+			1 + self.key_id.len()
+				+ 2 + self.wrapped_key.len()
+				+ Mac::LEN + self.ciphertext.len(),
+		);
+
+		bytes.push(self.key_id.len() as u8);
+		bytes.extend_from_slice(self.key_id.as_bytes());
+		bytes.extend_from_slice(&(self.wrapped_key.len() as u16).to_be_bytes());
+		bytes.extend_from_slice(&self.wrapped_key);
+		bytes.extend_from_slice(&self.mac.clone().into_bytes());
+		bytes.extend_from_slice(&self.ciphertext);
+
+		bytes
+	}
+
+	pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+		fn take<'a>(
+			bytes: &mut &'a [u8],
+			len: usize,
+		) -> Result<&'a [u8], DecodeError> {
+			if bytes.len() < len {
+				return Err(DecodeError::InvalidLength);
+			}
+
+			let (a, b) = bytes.split_at(len);
+			*bytes = b;
+			Ok(a)
+		}
+
+		let mut bytes = bytes;
+
+		let key_id_len = take(&mut bytes, 1)?[0] as usize;
+		let key_id = std::str::from_utf8(take(&mut bytes, key_id_len)?)
+			.map_err(|_| DecodeError::InvalidBytes)?;
+		if !is_valid_key_id(key_id) {
+			return Err(DecodeError::InvalidBytes);
+		}
+
+		let wrapped_len = take(&mut bytes, 2)?;
+		let wrapped_len = u16::from_be_bytes([wrapped_len[0], wrapped_len[1]]);
+		let wrapped_key = take(&mut bytes, wrapped_len as usize)?;
+
+		let mac = Mac::from_slice(take(&mut bytes, Mac::LEN)?);
+
+		Ok(Self {
+			key_id: key_id.to_string(),
+			wrapped_key: wrapped_key.to_vec(),
+			mac,
+			ciphertext: bytes.to_vec(),
+		})
+	}
+}
+
+impl fmt::Debug for Envelope {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("Envelope")
+			.field("key_id", &self.key_id)
+			.field("ciphertext_len", &self.ciphertext.len())
+			.finish()
+	}
+}
+
+/// Get's returned by a [`KeyProvider`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ProviderError {
+	/// The provider doesn't have a current master key.
+	NoCurrentKey,
+	/// The master key is not known to the provider.
+	UnknownKeyId,
+	/// The key id contains invalid characters, see [`is_valid_key_id`].
+	InvalidKeyId,
+	/// The wrapped key was not wrapped by this master key or was
+	/// modified.
+	InvalidWrappedKey,
+	/// Reading the master key or talking to the kms failed.
+	Io(io::Error),
+}
+
+impl From<io::Error> for ProviderError {
+	fn from(e: io::Error) -> Self {
+		Self::Io(e)
+	}
+}
+
+impl fmt::Display for ProviderError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		fmt::Debug::fmt(self, f)
+	}
+}
+
+impl Error for ProviderError {
+	fn source(&self) -> Option<&(dyn Error + 'static)> {
+		match self {
+			Self::Io(e) => Some(e),
+			_ => None,
+		}
+	}
+}
+
+/// Get's returned if sealing, opening or re-wrapping an envelope fails.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum EnvelopeError {
+	Provider(ProviderError),
+	/// The unwrapped data key has the wrong length.
+	InvalidDataKey,
+	MacNotEqual,
+}
+
+impl From<ProviderError> for EnvelopeError {
+	fn from(e: ProviderError) -> Self {
+		Self::Provider(e)
+	}
+}
+
+impl fmt::Display for EnvelopeError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		fmt::Debug::fmt(self, f)
+	}
+}
+
+impl Error for EnvelopeError {
+	fn source(&self) -> Option<&(dyn Error + 'static)> {
+		match self {
+			Self::Provider(e) => Some(e),
+			_ => None,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+
+	#[test]
+	fn seal_open() {
+		let mut provider = MemoryKeyProvider::new();
+		provider.generate_key("a");
+
+		let envelope = Envelope::seal(&provider, b"hello".to_vec()).unwrap();
+		assert_ne!(envelope.ciphertext(), b"hello");
+		assert_eq!(envelope.open(&provider).unwrap(), b"hello");
+
+		// every envelope uses a fresh data key
+		let other = Envelope::seal(&provider, b"hello".to_vec()).unwrap();
+		assert_ne!(envelope.ciphertext(), other.ciphertext());
+	}
+
+	#[test]
+	fn rewrap() {
+		let mut provider = MemoryKeyProvider::new();
+		provider.generate_key("a");
+
+		let mut envelope =
+			Envelope::seal(&provider, b"hello".to_vec()).unwrap();
+		let ciphertext = envelope.ciphertext().to_vec();
+
+		provider.generate_key("b");
+		envelope.rewrap(&provider).unwrap();
+		assert_eq!(envelope.key_id(), "b");
+		assert_eq!(envelope.ciphertext(), ciphertext);
+
+		// the old master key is not needed anymore
+		provider.remove_key("a");
+		assert_eq!(envelope.open(&provider).unwrap(), b"hello");
+
+		envelope.rewrap_to(&provider, "b").unwrap();
+		assert!(matches!(
+			envelope.rewrap_to(&provider, "a"),
+			Err(EnvelopeError::Provider(ProviderError::UnknownKeyId))
+		));
+	}
+
+	#[test]
+	fn bytes() {
+		let mut provider = MemoryKeyProvider::new();
+		provider.generate_key("master.2024");
+
+		let envelope = Envelope::seal(&provider, b"hello".to_vec()).unwrap();
+		let bytes = envelope.to_bytes();
+
+		let envelope = Envelope::from_bytes(&bytes).unwrap();
+		assert_eq!(envelope.key_id(), "master.2024");
+		assert_eq!(envelope.open(&provider).unwrap(), b"hello");
+
+		assert!(Envelope::from_bytes(&bytes[..10]).is_err());
+	}
+
+	#[test]
+	fn tampering() {
+		let mut provider = MemoryKeyProvider::new();
+		provider.generate_key("a");
+
+		let envelope = Envelope::seal(&provider, b"hello".to_vec()).unwrap();
+		let mut bytes = envelope.to_bytes();
+		*bytes.last_mut().unwrap() ^= 1;
+		assert!(matches!(
+			Envelope::from_bytes(&bytes).unwrap().open(&provider),
+			Err(EnvelopeError::MacNotEqual)
+		));
+
+		// modify the wrapped key
+		let mut bytes = envelope.to_bytes();
+		bytes[5] ^= 1;
+		assert!(matches!(
+			Envelope::from_bytes(&bytes).unwrap().open(&provider),
+			Err(EnvelopeError::Provider(ProviderError::InvalidWrappedKey))
+		));
+	}
+
+	#[test]
+	fn key_ids() {
+		assert!(is_valid_key_id("master-1_a.b"));
+		assert!(!is_valid_key_id(""));
+		assert!(!is_valid_key_id(".."));
+		assert!(!is_valid_key_id("a/b"));
+		assert!(!is_valid_key_id("a b"));
+		assert!(!is_valid_key_id(&"a".repeat(256)));
+	}
+}
@@ -0,0 +1,307 @@
+use super::{
+	is_valid_key_id, unwrap_with, wrap_with, KeyProvider, ProviderError,
+	MASTER_KEY_LEN,
+};
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use zeroize::Zeroize;
+
+/// Keeps master keys in memory.
+///
+/// Adding a key makes it the current one, older keys can still unwrap
+/// data keys until they are removed.
+#[derive(Default)]
+pub struct MemoryKeyProvider {
+	keys: HashMap<String, [u8; MASTER_KEY_LEN]>,
+	current: Option<String>,
+}
+
+impl MemoryKeyProvider {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Generates a random master key and makes it the current one.
+	///
+	/// ## Panics
+	/// if the key id is not valid, see [`is_valid_key_id`].
+	pub fn generate_key(&mut self, key_id: &str) {
+		let mut key = [0u8; MASTER_KEY_LEN];
+		crate::fill_random(&mut key);
+		self.insert_key(key_id, key);
+	}
+
+	/// Adds the master key and makes it the current one.
+	///
+	/// ## Panics
+	/// if the key id is not valid, see [`is_valid_key_id`].
+	pub fn insert_key(&mut self, key_id: &str, key: [u8; MASTER_KEY_LEN]) {
+		assert!(is_valid_key_id(key_id), "invalid key id");
+
+		if let Some(mut old) = self.keys.insert(key_id.to_string(), key) {
+			old.zeroize();
+		}
+		self.current = Some(key_id.to_string());
+	}
+
+	/// Removes a master key, if it is the current one no key will be
+	/// current anymore.
+	pub fn remove_key(&mut self, key_id: &str) {
+		if let Some(mut key) = self.keys.remove(key_id) {
+			key.zeroize();
+		}
+
+		if self.current.as_deref() == Some(key_id) {
+			self.current = None;
+		}
+	}
+
+	fn key(&self, key_id: &str) -> Result<&[u8; 32], ProviderError> {
+		self.keys.get(key_id).ok_or(ProviderError::UnknownKeyId)
+	}
+}
+
+impl KeyProvider for MemoryKeyProvider {
+	fn current_key_id(&self) -> Result<String, ProviderError> {
+		self.current.clone().ok_or(ProviderError::NoCurrentKey)
+	}
+
+	fn wrap(
+		&self,
+		key_id: &str,
+		data_key: &[u8],
+	) -> Result<Vec<u8>, ProviderError> {
+		Ok(wrap_with(self.key(key_id)?, data_key))
+	}
+
+	fn unwrap(
+		&self,
+		key_id: &str,
+		wrapped_key: &[u8],
+	) -> Result<Vec<u8>, ProviderError> {
+		unwrap_with(self.key(key_id)?, wrapped_key)
+	}
+}
+
+impl fmt::Debug for MemoryKeyProvider {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("MemoryKeyProvider")
+			.field("current", &self.current)
+			.finish()
+	}
+}
+
+impl Drop for MemoryKeyProvider {
+	fn drop(&mut self) {
+		for key in self.keys.values_mut() {
+			key.zeroize();
+		}
+	}
+}
+
+/// Reads master keys from a directory.
+///
+/// Every master key is stored in `<key_id>.key` as 32 raw bytes, the
+/// file `current` contains the id of the current key.
+#[derive(Debug, Clone)]
+pub struct FileKeyProvider {
+	dir: PathBuf,
+}
+
+impl FileKeyProvider {
+	const CURRENT: &'static str = "current";
+
+	/// The directory needs to exist.
+	pub fn new(dir: impl Into<PathBuf>) -> Self {
+		Self { dir: dir.into() }
+	}
+
+	pub fn dir(&self) -> &Path {
+		&self.dir
+	}
+
+	fn key_path(&self, key_id: &str) -> Result<PathBuf, ProviderError> {
+		if !is_valid_key_id(key_id) {
+			return Err(ProviderError::InvalidKeyId);
+		}
+
+		Ok(self.dir.join(format!("{key_id}.key")))
+	}
+
+	/// Generates a random master key and makes it the current one.
+	///
+	/// Returns an error if the key already exists.
+	pub fn generate_key(&self, key_id: &str) -> Result<(), ProviderError> {
+		let path = self.key_path(key_id)?;
+
+		let mut options = OpenOptions::new();
+		options.write(true).create_new(true);
+		#[cfg(unix)]
+		{
+			use std::os::unix::fs::OpenOptionsExt;
+			options.mode(0o600);
+		}
+
+		let mut key = [0u8; MASTER_KEY_LEN];
+		crate::fill_random(&mut key);
+		let r = options.open(path).and_then(|mut f| f.write_all(&key));
+		key.zeroize();
+		r?;
+
+		self.set_current(key_id)
+	}
+
+	/// Makes the key the current one.
+	pub fn set_current(&self, key_id: &str) -> Result<(), ProviderError> {
+		// make sure the key exists
+		let mut key = self.read_key(key_id)?;
+		key.zeroize();
+
+		fs::write(self.dir.join(Self::CURRENT), key_id)?;
+		Ok(())
+	}
+
+	fn read_key(&self, key_id: &str) -> Result<[u8; 32], ProviderError> {
+		let mut bytes = match fs::read(self.key_path(key_id)?) {
+			Ok(b) => b,
+			Err(e) if e.kind() == io::ErrorKind::NotFound => {
+				return Err(ProviderError::UnknownKeyId)
+			}
+			Err(e) => return Err(e.into()),
+		};
+
+		let r =
+			<[u8; MASTER_KEY_LEN]>::try_from(bytes.as_slice()).map_err(|_| {
+				io::Error::new(
+					io::ErrorKind::InvalidData,
+					"master key has the wrong length",
+				)
+				.into()
+			});
+		bytes.zeroize();
+
+		r
+	}
+}
+
+impl KeyProvider for FileKeyProvider {
+	fn current_key_id(&self) -> Result<String, ProviderError> {
+		match fs::read_to_string(self.dir.join(Self::CURRENT)) {
+			Ok(id) => Ok(id.trim().to_string()),
+			Err(e) if e.kind() == io::ErrorKind::NotFound => {
+				Err(ProviderError::NoCurrentKey)
+			}
+			Err(e) => Err(e.into()),
+		}
+	}
+
+	fn wrap(
+		&self,
+		key_id: &str,
+		data_key: &[u8],
+	) -> Result<Vec<u8>, ProviderError> {
+		let mut key = self.read_key(key_id)?;
+		let wrapped = wrap_with(&key, data_key);
+		key.zeroize();
+
+		Ok(wrapped)
+	}
+
+	fn unwrap(
+		&self,
+		key_id: &str,
+		wrapped_key: &[u8],
+	) -> Result<Vec<u8>, ProviderError> {
+		let mut key = self.read_key(key_id)?;
+		let r = unwrap_with(&key, wrapped_key);
+		key.zeroize();
+
+		r
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+	use crate::envelope::{Envelope, EnvelopeError};
+
+	fn temp_dir() -> PathBuf {
+		let mut name = [0u8; 8];
+		crate::fill_random(&mut name);
+		let name: String = name.iter().map(|b| format!("{b:02x}")).collect();
+
+		let dir = std::env::temp_dir().join(format!("fire-crypto-{name}"));
+		fs::create_dir(&dir).unwrap();
+		dir
+	}
+
+	#[test]
+	fn memory_provider() {
+		let mut provider = MemoryKeyProvider::new();
+		assert!(matches!(
+			provider.current_key_id(),
+			Err(ProviderError::NoCurrentKey)
+		));
+
+		provider.insert_key("a", [1u8; 32]);
+		let wrapped = provider.wrap("a", b"data key").unwrap();
+		assert_eq!(provider.unwrap("a", &wrapped).unwrap(), b"data key");
+
+		provider.insert_key("b", [2u8; 32]);
+		assert_eq!(provider.current_key_id().unwrap(), "b");
+		assert!(matches!(
+			provider.unwrap("b", &wrapped),
+			Err(ProviderError::InvalidWrappedKey)
+		));
+
+		provider.remove_key("b");
+		assert!(provider.current_key_id().is_err());
+		assert!(matches!(
+			provider.unwrap("b", &wrapped),
+			Err(ProviderError::UnknownKeyId)
+		));
+	}
+
+	#[test]
+	fn file_provider() {
+		let dir = temp_dir();
+		let provider = FileKeyProvider::new(&dir);
+
+		assert!(matches!(
+			Envelope::seal(&provider, b"hello".to_vec()),
+			Err(EnvelopeError::Provider(ProviderError::NoCurrentKey))
+		));
+
+		provider.generate_key("2023").unwrap();
+		assert!(provider.generate_key("2023").is_err());
+		assert!(matches!(
+			provider.generate_key("../a"),
+			Err(ProviderError::InvalidKeyId)
+		));
+
+		let mut envelope =
+			Envelope::seal(&provider, b"hello".to_vec()).unwrap();
+		assert_eq!(envelope.key_id(), "2023");
+
+		provider.generate_key("2024").unwrap();
+		envelope.rewrap(&provider).unwrap();
+		assert_eq!(envelope.key_id(), "2024");
+
+		fs::remove_file(dir.join("2023.key")).unwrap();
+		assert_eq!(envelope.open(&provider).unwrap(), b"hello");
+
+		provider.set_current("2024").unwrap();
+		assert!(matches!(
+			provider.set_current("2023"),
+			Err(ProviderError::UnknownKeyId)
+		));
+
+		fs::remove_dir_all(dir).unwrap();
+	}
+}
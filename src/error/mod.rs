@@ -28,6 +28,9 @@ impl Error for TryFromError {}
 pub enum DecodeError {
 	InvalidLength,
 	InvalidBytes,
+	/// Only returned by validating functions, if a public key would
+	/// result in a non contributory diffie hellman.
+	NonContributory,
 }
 
 impl DecodeError {
@@ -0,0 +1,350 @@
+//! Format preserving encryption for integer ids.
+//!
+//! Turns sequential ids into opaque strings with a fixed length, which
+//! can only be reversed knowing the key. The id gets encrypted with a
+//! Feistel network (like FF1) which uses ChaCha20 as round function.
+//!
+//! ## Note
+//! The ids are not authenticated, every string with the correct length
+//! and alphabet decrypts to some id.
+//!
+//! ## Example
+//! ```
+//! use fire_crypto::fpe::IdCipher;
+//!
+//! let cipher = IdCipher::url_safe([42u8; 32]);
+//!
+//! let s = cipher.encrypt(1).unwrap();
+//! assert_eq!(s.len(), IdCipher::URL_SAFE_LEN);
+//! assert_eq!(cipher.decrypt(&s).unwrap(), 1);
+//! ```
+
+use crate::error::DecodeError;
+
+use std::error::Error;
+use std::fmt;
+
+use zeroize::Zeroize;
+
+use chacha20::cipher::{KeyIvInit, StreamCipher};
+use chacha20::XChaCha20;
+
+const ROUNDS: u8 = 10;
+
+/// The smallest domain which is allowed, smaller domains are vulnerable
+/// to attacks enumerating all values.
+const MIN_DOMAIN: u128 = 1_000_000;
+
+/// The characters which can be used to represent an id.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Alphabet {
+	chars: Vec<u8>,
+}
+
+impl Alphabet {
+	/// The url safe alphabet used by base64.
+	pub const URL_SAFE: &'static str =
+		"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+	/// Lowercase letters and digits.
+	pub const ALPHANUMERIC_LOWER: &'static str =
+		"0123456789abcdefghijklmnopqrstuvwxyz";
+
+	/// Only digits.
+	pub const DIGITS: &'static str = "0123456789";
+
+	/// Creates an alphabet with the characters in the given order.
+	///
+	/// Returns an error if the alphabet contains less than two
+	/// characters, non ascii characters or a character twice.
+	pub fn new(chars: &str) -> Result<Self, InvalidAlphabet> {
+		let chars = chars.as_bytes();
+		if chars.len() < 2 || !chars.is_ascii() {
+			return Err(InvalidAlphabet);
+		}
+
+		for (i, c) in chars.iter().enumerate() {
+			if chars[i + 1..].contains(c) {
+				return Err(InvalidAlphabet);
+			}
+		}
+
+		Ok(Self {
+			chars: chars.to_vec(),
+		})
+	}
+
+	pub fn url_safe() -> Self {
+		Self::new(Self::URL_SAFE).unwrap()
+	}
+
+	fn radix(&self) -> u128 {
+		self.chars.len() as u128
+	}
+
+	fn position(&self, c: u8) -> Option<u128> {
+		self.chars.iter().position(|a| *a == c).map(|p| p as u128)
+	}
+}
+
+/// Get's returned if an alphabet is not valid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidAlphabet;
+
+impl fmt::Display for InvalidAlphabet {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		fmt::Debug::fmt(self, f)
+	}
+}
+
+impl Error for InvalidAlphabet {}
+
+/// Get's returned if an id is too large to be represented.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutOfRange;
+
+impl fmt::Display for OutOfRange {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		fmt::Debug::fmt(self, f)
+	}
+}
+
+impl Error for OutOfRange {}
+
+/// Encrypts ids into strings with a fixed length.
+pub struct IdCipher {
+	key: [u8; 32],
+	alphabet: Alphabet,
+	len: usize,
+	// radix^(len / 2)
+	left_mod: u128,
+	// radix^(len - len / 2)
+	right_mod: u128,
+}
+
+impl IdCipher {
+	/// The length needed to represent every u64 with the url safe
+	/// alphabet.
+	pub const URL_SAFE_LEN: usize = 11;
+
+	/// Creates a new cipher which encrypts ids into strings with `len`
+	/// characters from the alphabet.
+	///
+	/// ## Panics
+	/// if the alphabet with the length has less than a million or more
+	/// than 2^128 possible values.
+	pub fn new(key: [u8; 32], alphabet: Alphabet, len: usize) -> Self {
+		let radix = alphabet.radix();
+		let domain = u32::try_from(len)
+			.ok()
+			.and_then(|len| radix.checked_pow(len))
+			.expect("the domain needs to be smaller than 2^128");
+		assert!(domain >= MIN_DOMAIN, "the domain needs to be at least 10^6");
+
+		Self {
+			key,
+			left_mod: radix.pow((len / 2) as u32),
+			right_mod: radix.pow((len - len / 2) as u32),
+			alphabet,
+			len,
+		}
+	}
+
+	/// Creates a cipher which uses the url safe alphabet and can
+	/// represent every u64.
+	pub fn url_safe(key: [u8; 32]) -> Self {
+		Self::new(key, Alphabet::url_safe(), Self::URL_SAFE_LEN)
+	}
+
+	/// The length of every encrypted id.
+	pub fn str_len(&self) -> usize {
+		self.len
+	}
+
+	/// Returns the largest id which can be encrypted.
+	pub fn max_id(&self) -> u64 {
+		// left_mod * right_mod can't overflow since it was checked in new
+		(self.left_mod * self.right_mod - 1)
+			.try_into()
+			.unwrap_or(u64::MAX)
+	}
+
+	/// Encrypts the id, returns an error if the id is larger than
+	/// [`IdCipher::max_id`].
+	pub fn encrypt(&self, id: u64) -> Result<String, OutOfRange> {
+		if id > self.max_id() {
+			return Err(OutOfRange);
+		}
+
+		let id = id as u128;
+		let mut a = id / self.right_mod;
+		let mut b = id % self.right_mod;
+
+		for round in 0..ROUNDS {
+			let m = self.round_mod(round);
+			let c = (a + self.round_fn(round, b) % m) % m;
+			a = b;
+			b = c;
+		}
+
+		Ok(self.format_num(a * self.right_mod + b))
+	}
+
+	/// Decrypts a string created by [`IdCipher::encrypt`].
+	pub fn decrypt(&self, s: &str) -> Result<u64, DecodeError> {
+		if s.len() != self.len {
+			return Err(DecodeError::InvalidLength);
+		}
+
+		let num = self.parse_num(s)?;
+		let mut a = num / self.right_mod;
+		let mut b = num % self.right_mod;
+
+		for round in (0..ROUNDS).rev() {
+			let m = self.round_mod(round);
+			let c = b;
+			b = a;
+			a = (c + m - self.round_fn(round, b) % m) % m;
+		}
+
+		(a * self.right_mod + b)
+			.try_into()
+			.map_err(|_| DecodeError::InvalidBytes)
+	}
+
+	/// In even rounds the left part gets modified.
+	fn round_mod(&self, round: u8) -> u128 {
+		if round % 2 == 0 {
+			self.left_mod
+		} else {
+			self.right_mod
+		}
+	}
+
+	fn round_fn(&self, round: u8, b: u128) -> u128 {
+		let mut nonce = [0u8; 24];
+		nonce[0] = round;
+		nonce[1] = self.len as u8;
+		nonce[2] = self.alphabet.chars.len() as u8;
+		nonce[8..].copy_from_slice(&b.to_be_bytes());
+
+		let mut bytes = [0u8; 16];
+		let mut cipher = XChaCha20::new(&self.key.into(), &nonce.into());
+		cipher.apply_keystream(&mut bytes);
+
+		u128::from_be_bytes(bytes)
+	}
+
+	fn format_num(&self, mut num: u128) -> String {
+		let radix = self.alphabet.radix();
+		let mut s = vec![0u8; self.len];
+		for c in s.iter_mut().rev() {
+			*c = self.alphabet.chars[(num % radix) as usize];
+			num /= radix;
+		}
+
+		// the alphabet only contains ascii characters
+		String::from_utf8(s).unwrap()
+	}
+
+	fn parse_num(&self, s: &str) -> Result<u128, DecodeError> {
+		let radix = self.alphabet.radix();
+		s.bytes().try_fold(0u128, |num, c| {
+			self.alphabet
+				.position(c)
+				.map(|p| num * radix + p)
+				.ok_or(DecodeError::InvalidBytes)
+		})
+	}
+}
+
+impl fmt::Debug for IdCipher {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("IdCipher")
+			.field("alphabet", &self.alphabet)
+			.field("len", &self.len)
+			.finish()
+	}
+}
+
+impl Drop for IdCipher {
+	fn drop(&mut self) {
+		self.key.zeroize();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+
+	use std::collections::HashSet;
+
+	#[test]
+	fn url_safe() {
+		let cipher = IdCipher::url_safe([1u8; 32]);
+
+		let mut seen = HashSet::new();
+		for id in (0..256).chain([u64::MAX - 1, u64::MAX]) {
+			let s = cipher.encrypt(id).unwrap();
+			assert_eq!(s.len(), IdCipher::URL_SAFE_LEN);
+			assert!(seen.insert(s.clone()));
+
+			assert_eq!(cipher.decrypt(&s).unwrap(), id);
+		}
+	}
+
+	#[test]
+	fn static_ids() {
+		let cipher = IdCipher::url_safe([1u8; 32]);
+
+		assert_eq!(cipher.encrypt(1).unwrap(), "wFEWy65YufI");
+		assert_eq!(cipher.encrypt(2).unwrap(), "gNUEOXlOChT");
+	}
+
+	#[test]
+	fn custom_alphabet() {
+		let alphabet = Alphabet::new(Alphabet::DIGITS).unwrap();
+		let cipher = IdCipher::new([2u8; 32], alphabet, 7);
+		assert_eq!(cipher.max_id(), 9_999_999);
+
+		for id in (0..500).chain([9_999_999]) {
+			let s = cipher.encrypt(id).unwrap();
+			assert_eq!(s.len(), 7);
+			assert!(s.bytes().all(|c| c.is_ascii_digit()));
+
+			assert_eq!(cipher.decrypt(&s).unwrap(), id);
+		}
+
+		assert_eq!(cipher.encrypt(10_000_000), Err(OutOfRange));
+	}
+
+	#[test]
+	fn different_keys() {
+		let a = IdCipher::url_safe([1u8; 32]);
+		let b = IdCipher::url_safe([2u8; 32]);
+
+		assert_ne!(a.encrypt(1).unwrap(), b.encrypt(1).unwrap());
+	}
+
+	#[test]
+	fn invalid_strings() {
+		let cipher = IdCipher::url_safe([1u8; 32]);
+
+		assert!(matches!(
+			cipher.decrypt("abc"),
+			Err(DecodeError::InvalidLength)
+		));
+		assert!(matches!(
+			cipher.decrypt("abcdefghij="),
+			Err(DecodeError::InvalidBytes)
+		));
+	}
+
+	#[test]
+	fn invalid_alphabet() {
+		assert_eq!(Alphabet::new("a"), Err(InvalidAlphabet));
+		assert_eq!(Alphabet::new("abca"), Err(InvalidAlphabet));
+		assert_eq!(Alphabet::new("abä"), Err(InvalidAlphabet));
+	}
+}
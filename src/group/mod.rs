@@ -0,0 +1,624 @@
+//! Group encryption with sender keys.
+//!
+//! Every member of a group creates a [`GroupSender`] and sends its
+//! [`SenderKeyDistribution`] to every other member over an already
+//! encrypted pairwise channel. A message then only needs to be encrypted
+//! once for the whole group.
+//!
+//! The chain key get's ratcheted forward with every message, so a leaked
+//! chain key can't decrypt earlier messages. Every message is signed with
+//! a signature keypair which only the sender knows, so other members
+//! can't forge messages.
+//!
+//! ## Example
+//! ```
+//! use fire_crypto::group::{GroupReceiver, GroupSender};
+//!
+//! let mut alice = GroupSender::new();
+//! // send this to every member over a pairwise channel
+//! let distribution = alice.distribution();
+//!
+//! let mut bob = GroupReceiver::new(&distribution);
+//!
+//! let msg1 = alice.encrypt(b"hey group");
+//! let msg2 = alice.encrypt(b"how are you");
+//!
+//! // messages can arrive out of order
+//! assert_eq!(bob.decrypt(&msg2).unwrap(), b"how are you");
+//! assert_eq!(bob.decrypt(&msg1).unwrap(), b"hey group");
+//! ```
+
+use crate::cipher::{Key, Mac, Nonce};
+use crate::error::TryFromError;
+use crate::hash::Hasher;
+use crate::signature::{Keypair, PublicKey, Signature};
+
+use std::collections::BTreeMap;
+use std::convert::{TryFrom, TryInto};
+use std::error::Error;
+use std::fmt;
+
+use zeroize::Zeroize;
+
+/// How many messages can be skipped at once.
+pub const MAX_SKIP: u32 = 1000;
+
+/// How many message keys of skipped messages are kept.
+pub const MAX_SKIPPED_KEYS: usize = 2000;
+
+const MESSAGE_KEY_DOMAIN: u8 = 1;
+const CHAIN_KEY_DOMAIN: u8 = 2;
+
+#[derive(Clone)]
+struct ChainKey {
+	key: [u8; 32],
+	iteration: u32,
+}
+
+impl ChainKey {
+	fn message_key(&self) -> MessageKey {
+		let mut bytes =
+			Hasher::hash_keyed(self.key, [MESSAGE_KEY_DOMAIN]).to_bytes();
+		let key = MessageKey {
+			secret: bytes[..32].try_into().unwrap(),
+			nonce: bytes[32..32 + Nonce::LEN].try_into().unwrap(),
+		};
+		bytes.zeroize();
+
+		key
+	}
+
+	/// Returns `None` if the iteration would overflow.
+	fn next(&self) -> Option<Self> {
+		let iteration = self.iteration.checked_add(1)?;
+
+		let mut bytes =
+			Hasher::hash_keyed(self.key, [CHAIN_KEY_DOMAIN]).to_bytes();
+		let key = bytes[..32].try_into().unwrap();
+		bytes.zeroize();
+
+		Some(Self { key, iteration })
+	}
+}
+
+impl Drop for ChainKey {
+	fn drop(&mut self) {
+		self.key.zeroize();
+	}
+}
+
+struct MessageKey {
+	secret: [u8; 32],
+	nonce: [u8; 24],
+}
+
+impl MessageKey {
+	fn to_key(&self) -> Key {
+		Key::from_secret(self.secret, Nonce::from(self.nonce))
+	}
+}
+
+impl Drop for MessageKey {
+	fn drop(&mut self) {
+		self.secret.zeroize();
+		self.nonce.zeroize();
+	}
+}
+
+/// The part of the sender key which other members need to decrypt
+/// messages.
+///
+/// This needs to be sent over an encrypted and authenticated channel.
+#[derive(Clone)]
+pub struct SenderKeyDistribution {
+	key_id: u32,
+	iteration: u32,
+	chain_key: [u8; 32],
+	signing_key: PublicKey,
+}
+
+impl SenderKeyDistribution {
+	pub const LEN: usize = 4 + 4 + 32 + PublicKey::LEN;
+
+	pub fn key_id(&self) -> u32 {
+		self.key_id
+	}
+
+	pub fn to_bytes(&self) -> [u8; Self::LEN] {
+		let mut bytes = [0u8; Self::LEN];
+		bytes[..4].copy_from_slice(&self.key_id.to_be_bytes());
+		bytes[4..8].copy_from_slice(&self.iteration.to_be_bytes());
+		bytes[8..40].copy_from_slice(&self.chain_key);
+		bytes[40..].copy_from_slice(&self.signing_key.to_bytes());
+		bytes
+	}
+}
+
+impl TryFrom<&[u8]> for SenderKeyDistribution {
+	type Error = TryFromError;
+
+	fn try_from(v: &[u8]) -> Result<Self, Self::Error> {
+		if v.len() != Self::LEN {
+			return Err(TryFromError::from_any(()));
+		}
+
+		Ok(Self {
+			key_id: u32::from_be_bytes(v[..4].try_into().unwrap()),
+			iteration: u32::from_be_bytes(v[4..8].try_into().unwrap()),
+			chain_key: v[8..40].try_into().unwrap(),
+			signing_key: PublicKey::try_from(&v[40..])?,
+		})
+	}
+}
+
+impl fmt::Debug for SenderKeyDistribution {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("SenderKeyDistribution")
+			.field("key_id", &self.key_id)
+			.field("iteration", &self.iteration)
+			.field("signing_key", &self.signing_key)
+			.finish()
+	}
+}
+
+impl Drop for SenderKeyDistribution {
+	fn drop(&mut self) {
+		self.chain_key.zeroize();
+	}
+}
+
+fn signed_bytes(
+	key_id: u32,
+	iteration: u32,
+	mac: &Mac,
+	ciphertext: &[u8],
+) -> Vec<u8> {
+	let mut bytes = Vec::with_capacity(4 + 4 + Mac::LEN + ciphertext.len());
+	bytes.extend_from_slice(&key_id.to_be_bytes());
+	bytes.extend_from_slice(&iteration.to_be_bytes());
+	bytes.extend_from_slice(&mac.clone().into_bytes());
+	bytes.extend_from_slice(ciphertext);
+	bytes
+}
+
+/// An encrypted and signed group message.
+#[derive(Clone)]
+pub struct SenderKeyMessage {
+	key_id: u32,
+	iteration: u32,
+	ciphertext: Vec<u8>,
+	mac: Mac,
+	signature: Signature,
+}
+
+impl SenderKeyMessage {
+	/// The length of a message without the ciphertext.
+	pub const OVERHEAD: usize = 4 + 4 + Mac::LEN + Signature::LEN;
+
+	pub fn key_id(&self) -> u32 {
+		self.key_id
+	}
+
+	pub fn iteration(&self) -> u32 {
+		self.iteration
+	}
+
+	fn signed_bytes(&self) -> Vec<u8> {
+		signed_bytes(self.key_id, self.iteration, &self.mac, &self.ciphertext)
+	}
+
+	/// Returns `key_id | iteration | mac | ciphertext | signature`.
+	pub fn to_bytes(&self) -> Vec<u8> {
+		let mut bytes = self.signed_bytes();
+		bytes.extend_from_slice(&self.signature.to_bytes());
+		bytes
+	}
+}
+
+impl TryFrom<&[u8]> for SenderKeyMessage {
+	type Error = TryFromError;
+
+	fn try_from(v: &[u8]) -> Result<Self, Self::Error> {
+		if v.len() < Self::OVERHEAD {
+			return Err(TryFromError::from_any(()));
+		}
+
+		let (v, signature) = v.split_at(v.len() - Signature::LEN);
+
+		Ok(Self {
+			key_id: u32::from_be_bytes(v[..4].try_into().unwrap()),
+			iteration: u32::from_be_bytes(v[4..8].try_into().unwrap()),
+			mac: Mac::from_slice(&v[8..8 + Mac::LEN]),
+			ciphertext: v[8 + Mac::LEN..].to_vec(),
+			signature: Signature::try_from(signature)?,
+		})
+	}
+}
+
+impl fmt::Debug for SenderKeyMessage {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("SenderKeyMessage")
+			.field("key_id", &self.key_id)
+			.field("iteration", &self.iteration)
+			.field("ciphertext_len", &self.ciphertext.len())
+			.finish()
+	}
+}
+
+/// The sending side of a sender key, every member of a group has one.
+pub struct GroupSender {
+	key_id: u32,
+	chain: ChainKey,
+	signing: Keypair,
+}
+
+impl GroupSender {
+	/// Creates a new random sender key.
+	pub fn new() -> Self {
+		let mut key_id = [0u8; 4];
+		crate::fill_random(&mut key_id);
+		let mut key = [0u8; 32];
+		crate::fill_random(&mut key);
+
+		Self {
+			key_id: u32::from_be_bytes(key_id),
+			chain: ChainKey { key, iteration: 0 },
+			signing: Keypair::new(),
+		}
+	}
+
+	pub fn key_id(&self) -> u32 {
+		self.key_id
+	}
+
+	/// Returns the distribution message for new members.
+	///
+	/// New members can only decrypt messages sent after this call.
+	pub fn distribution(&self) -> SenderKeyDistribution {
+		SenderKeyDistribution {
+			key_id: self.key_id,
+			iteration: self.chain.iteration,
+			chain_key: self.chain.key,
+			signing_key: self.signing.public().clone(),
+		}
+	}
+
+	/// Encrypts and signs a message and ratchets the chain forward.
+	///
+	/// ## Panics
+	/// If the chain is exhausted, which happens after `u32::MAX` messages.
+	/// A new sender key needs to be created before that.
+	pub fn encrypt(&mut self, msg: &[u8]) -> SenderKeyMessage {
+		let next = self.chain.next().expect("chain exhausted");
+
+		let mut ciphertext = msg.to_vec();
+		let mac = self.chain.message_key().to_key().encrypt(&mut ciphertext);
+
+		let iteration = self.chain.iteration;
+		let signature = self.signing.sign(signed_bytes(
+			self.key_id,
+			iteration,
+			&mac,
+			&ciphertext,
+		));
+
+		self.chain = next;
+
+		SenderKeyMessage {
+			key_id: self.key_id,
+			iteration,
+			ciphertext,
+			mac,
+			signature,
+		}
+	}
+}
+
+impl fmt::Debug for GroupSender {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("GroupSender")
+			.field("key_id", &self.key_id)
+			.field("iteration", &self.chain.iteration)
+			.finish()
+	}
+}
+
+/// The receiving side of another members sender key.
+pub struct GroupReceiver {
+	key_id: u32,
+	chain: ChainKey,
+	signing_key: PublicKey,
+	skipped: BTreeMap<u32, MessageKey>,
+}
+
+impl GroupReceiver {
+	pub fn new(distribution: &SenderKeyDistribution) -> Self {
+		Self {
+			key_id: distribution.key_id,
+			chain: ChainKey {
+				key: distribution.chain_key,
+				iteration: distribution.iteration,
+			},
+			signing_key: distribution.signing_key.clone(),
+			skipped: BTreeMap::new(),
+		}
+	}
+
+	pub fn key_id(&self) -> u32 {
+		self.key_id
+	}
+
+	/// Returns the signing key of the sender.
+	pub fn signing_key(&self) -> &PublicKey {
+		&self.signing_key
+	}
+
+	/// Verifies and decrypts a message.
+	///
+	/// Messages can arrive out of order, as long as not more than
+	/// [`MAX_SKIP`] messages are skipped at once. If an error is returned
+	/// the state is not changed.
+	pub fn decrypt(
+		&mut self,
+		msg: &SenderKeyMessage,
+	) -> Result<Vec<u8>, SenderKeyError> {
+		if msg.key_id != self.key_id {
+			return Err(SenderKeyError::UnknownKeyId);
+		}
+
+		if !self.signing_key.verify(msg.signed_bytes(), &msg.signature) {
+			return Err(SenderKeyError::InvalidSignature);
+		}
+
+		let mut plaintext = msg.ciphertext.clone();
+
+		// a skipped message
+		if msg.iteration < self.chain.iteration {
+			let key = self
+				.skipped
+				.get(&msg.iteration)
+				.ok_or(SenderKeyError::DuplicateMessage)?;
+
+			key.to_key()
+				.decrypt(&mut plaintext, &msg.mac)
+				.map_err(|_| SenderKeyError::MacNotEqual)?;
+
+			self.skipped.remove(&msg.iteration);
+			return Ok(plaintext);
+		}
+
+		if msg.iteration - self.chain.iteration > MAX_SKIP {
+			return Err(SenderKeyError::TooManySkipped);
+		}
+
+		// don't modify the state until the message was verified
+		let mut chain = self.chain.clone();
+		let mut skipped = vec![];
+		while chain.iteration < msg.iteration {
+			skipped.push((chain.iteration, chain.message_key()));
+			// can't overflow since chain.iteration < msg.iteration
+			chain = chain.next().unwrap();
+		}
+		let next = chain.next().ok_or(SenderKeyError::ChainExhausted)?;
+
+		chain
+			.message_key()
+			.to_key()
+			.decrypt(&mut plaintext, &msg.mac)
+			.map_err(|_| SenderKeyError::MacNotEqual)?;
+
+		self.chain = next;
+		self.skipped.extend(skipped);
+		while self.skipped.len() > MAX_SKIPPED_KEYS {
+			// drop the oldest keys first
+			let oldest = *self.skipped.keys().next().unwrap();
+			self.skipped.remove(&oldest);
+		}
+
+		Ok(plaintext)
+	}
+}
+
+impl fmt::Debug for GroupReceiver {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("GroupReceiver")
+			.field("key_id", &self.key_id)
+			.field("iteration", &self.chain.iteration)
+			.field("signing_key", &self.signing_key)
+			.finish()
+	}
+}
+
+/// Get's returned if a group message can't be decrypted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SenderKeyError {
+	/// The message was encrypted with another sender key.
+	UnknownKeyId,
+	/// The message was not signed by the sender.
+	InvalidSignature,
+	/// The message was already decrypted or it's key was dropped.
+	DuplicateMessage,
+	/// More than [`MAX_SKIP`] messages would need to be skipped.
+	TooManySkipped,
+	MacNotEqual,
+	/// The message uses the last iteration, after which the chain can't
+	/// be ratcheted forward.
+	ChainExhausted,
+}
+
+impl fmt::Display for SenderKeyError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		fmt::Debug::fmt(self, f)
+	}
+}
+
+impl Error for SenderKeyError {}
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+
+	#[test]
+	fn in_order() {
+		let mut alice = GroupSender::new();
+		let mut bob = GroupReceiver::new(&alice.distribution());
+		let mut carol = GroupReceiver::new(&alice.distribution());
+
+		for i in 0..10u8 {
+			let msg = alice.encrypt(&[i; 20]);
+			assert_eq!(msg.iteration(), i as u32);
+			assert_eq!(bob.decrypt(&msg).unwrap(), [i; 20]);
+			assert_eq!(carol.decrypt(&msg).unwrap(), [i; 20]);
+
+			assert_eq!(
+				bob.decrypt(&msg).unwrap_err(),
+				SenderKeyError::DuplicateMessage
+			);
+		}
+	}
+
+	#[test]
+	fn out_of_order() {
+		let mut alice = GroupSender::new();
+		let mut bob = GroupReceiver::new(&alice.distribution());
+
+		let msgs: Vec<_> = (0..5u8).map(|i| alice.encrypt(&[i])).collect();
+
+		for i in [3, 0, 4, 1, 2] {
+			assert_eq!(bob.decrypt(&msgs[i]).unwrap(), [i as u8]);
+		}
+
+		for msg in &msgs {
+			assert_eq!(
+				bob.decrypt(msg).unwrap_err(),
+				SenderKeyError::DuplicateMessage
+			);
+		}
+	}
+
+	#[test]
+	fn too_many_skipped() {
+		let mut alice = GroupSender::new();
+		let mut bob = GroupReceiver::new(&alice.distribution());
+
+		for _ in 0..=MAX_SKIP {
+			alice.encrypt(b"lost");
+		}
+
+		let msg = alice.encrypt(b"hey");
+		assert_eq!(
+			bob.decrypt(&msg).unwrap_err(),
+			SenderKeyError::TooManySkipped
+		);
+	}
+
+	#[test]
+	fn late_joiner() {
+		let mut alice = GroupSender::new();
+		let before = alice.encrypt(b"before");
+
+		let mut bob = GroupReceiver::new(&alice.distribution());
+		assert_eq!(
+			bob.decrypt(&before).unwrap_err(),
+			SenderKeyError::DuplicateMessage
+		);
+
+		let after = alice.encrypt(b"after");
+		assert_eq!(bob.decrypt(&after).unwrap(), b"after");
+	}
+
+	#[test]
+	fn forgery() {
+		let mut alice = GroupSender::new();
+		let dist = alice.distribution();
+		let mut bob = GroupReceiver::new(&dist);
+
+		// carol knows the chain key but not alice's signing key
+		let mut carol = GroupSender {
+			key_id: dist.key_id,
+			chain: ChainKey {
+				key: dist.chain_key,
+				iteration: dist.iteration,
+			},
+			signing: Keypair::new(),
+		};
+		let forged = carol.encrypt(b"forged");
+		assert_eq!(
+			bob.decrypt(&forged).unwrap_err(),
+			SenderKeyError::InvalidSignature
+		);
+
+		// the state was not modified
+		let msg = alice.encrypt(b"real");
+		assert_eq!(bob.decrypt(&msg).unwrap(), b"real");
+
+		let other = GroupSender::new().encrypt(b"other");
+		assert_eq!(
+			bob.decrypt(&other).unwrap_err(),
+			SenderKeyError::UnknownKeyId
+		);
+	}
+
+	#[test]
+	fn chain_exhausted() {
+		let mut alice = GroupSender::new();
+		alice.chain.iteration = u32::MAX - 1;
+		let mut bob = GroupReceiver::new(&alice.distribution());
+
+		let msg = alice.encrypt(b"last");
+		assert_eq!(bob.decrypt(&msg).unwrap(), b"last");
+
+		// a message alice can't send but a malicious sender could
+		let mut ciphertext = b"after".to_vec();
+		let mac = alice.chain.message_key().to_key().encrypt(&mut ciphertext);
+		let signature = alice.signing.sign(signed_bytes(
+			alice.key_id,
+			u32::MAX,
+			&mac,
+			&ciphertext,
+		));
+		let msg = SenderKeyMessage {
+			key_id: alice.key_id,
+			iteration: u32::MAX,
+			mac,
+			ciphertext,
+			signature,
+		};
+		assert_eq!(
+			bob.decrypt(&msg).unwrap_err(),
+			SenderKeyError::ChainExhausted
+		);
+	}
+
+	#[test]
+	#[should_panic(expected = "chain exhausted")]
+	fn encrypt_exhausted() {
+		let mut alice = GroupSender::new();
+		alice.chain.iteration = u32::MAX;
+		alice.encrypt(b"after");
+	}
+
+	#[test]
+	fn bytes() {
+		let mut alice = GroupSender::new();
+		let dist = alice.distribution().to_bytes();
+		let dist = SenderKeyDistribution::try_from(dist.as_ref()).unwrap();
+		let mut bob = GroupReceiver::new(&dist);
+
+		let msg = alice.encrypt(b"hello").to_bytes();
+		assert_eq!(msg.len(), 5 + SenderKeyMessage::OVERHEAD);
+
+		let mut tampered = msg.clone();
+		tampered[10] ^= 1;
+		let tampered = SenderKeyMessage::try_from(tampered.as_ref()).unwrap();
+		assert_eq!(
+			bob.decrypt(&tampered).unwrap_err(),
+			SenderKeyError::InvalidSignature
+		);
+
+		let msg = SenderKeyMessage::try_from(msg.as_ref()).unwrap();
+		assert_eq!(bob.decrypt(&msg).unwrap(), b"hello");
+	}
+}
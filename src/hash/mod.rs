@@ -14,7 +14,8 @@ use std::convert::{TryFrom, TryInto};
 use std::mem::ManuallyDrop;
 use std::{fmt, ptr};
 
-use blake2::{Blake2b512, Digest};
+use blake2::digest::{FixedOutput, KeyInit, Update};
+use blake2::{Blake2b512, Blake2bMac512, Digest};
 use generic_array::{typenum::U64, GenericArray};
 
 #[cfg(feature = "b64")]
@@ -25,22 +26,56 @@ pub fn hash(data: impl AsRef<[u8]>) -> Hash {
 }
 
 pub struct Hasher {
-	inner: Blake2b512,
+	inner: Inner,
+}
+
+enum Inner {
+	Plain(Blake2b512),
+	Keyed(Blake2bMac512),
 }
 
 impl Hasher {
+	pub const MAX_KEY_LEN: usize = 64;
+
 	pub fn new() -> Self {
 		Self {
-			inner: Blake2b512::new(),
+			inner: Inner::Plain(Blake2b512::new()),
+		}
+	}
+
+	/// Creates a hasher using the keyed mode of Blake2b.
+	///
+	/// The resulting hash can be used as a message authentication code
+	/// or as a pseudo random function, only someone knowing the key
+	/// can compute it.
+	///
+	/// ## Panics
+	/// if the key is empty or longer than 64 bytes.
+	pub fn new_keyed(key: impl AsRef<[u8]>) -> Self {
+		let key = key.as_ref();
+		assert!(
+			!key.is_empty() && key.len() <= Self::MAX_KEY_LEN,
+			"key needs to be between 1 and 64 bytes long"
+		);
+
+		Self {
+			inner: Inner::Keyed(Blake2bMac512::new_from_slice(key).unwrap()),
 		}
 	}
 
 	pub fn update(&mut self, data: impl AsRef<[u8]>) {
-		self.inner.update(data);
+		match &mut self.inner {
+			Inner::Plain(h) => Digest::update(h, data),
+			Inner::Keyed(h) => Update::update(h, data.as_ref()),
+		}
 	}
 
 	pub fn finalize(self) -> Hash {
-		let arr = self.inner.finalize();
+		let arr = match self.inner {
+			Inner::Plain(h) => h.finalize(),
+			Inner::Keyed(h) => h.finalize_fixed(),
+		};
+
 		Hash {
 			bytes: convert_generic_array(arr),
 		}
@@ -51,6 +86,16 @@ impl Hasher {
 		hasher.update(data);
 		hasher.finalize()
 	}
+
+	/// Hashes the data using the keyed mode of Blake2b.
+	///
+	/// ## Panics
+	/// if the key is empty or longer than 64 bytes.
+	pub fn hash_keyed(key: impl AsRef<[u8]>, data: impl AsRef<[u8]>) -> Hash {
+		let mut hasher = Hasher::new_keyed(key);
+		hasher.update(data);
+		hasher.finalize()
+	}
 }
 
 fn convert_generic_array<T>(arr: GenericArray<T, U64>) -> [T; 64] {
@@ -197,6 +242,25 @@ mod tests {
 		assert_eq!(hash.to_bytes(), hash_bytes);
 	}
 
+	#[test]
+	fn hash_keyed() {
+		// first entry of the official blake2b keyed test vectors
+		let key: Vec<u8> = (0..64).collect();
+
+		let hash = Hasher::hash_keyed(&key, []);
+
+		let hash_bytes = [
+			0x10, 0xeb, 0xb6, 0x77, 0x00, 0xb1, 0x86, 0x8e, 0xfb, 0x44, 0x17,
+			0x98, 0x7a, 0xcf, 0x46, 0x90, 0xae, 0x9d, 0x97, 0x2f, 0xb7, 0xa5,
+			0x90, 0xc2, 0xf0, 0x28, 0x71, 0x79, 0x9a, 0xaa, 0x47, 0x86, 0xb5,
+			0xe9, 0x96, 0xe8, 0xf0, 0xf4, 0xeb, 0x98, 0x1f, 0xc2, 0x14, 0xb0,
+			0x05, 0xf4, 0x2d, 0x2f, 0xf4, 0x23, 0x34, 0x99, 0x39, 0x16, 0x53,
+			0xdf, 0x7a, 0xef, 0xcb, 0xc1, 0x3f, 0xc5, 0x15, 0x68,
+		];
+		assert_eq!(hash.to_bytes(), hash_bytes);
+		assert_ne!(hash, Hasher::hash([]));
+	}
+
 	#[test]
 	#[cfg(feature = "b64")]
 	fn hash_b64() {
@@ -0,0 +1,315 @@
+use super::{KeyId, Keyring, KeyringError};
+
+use std::error::Error;
+use std::fmt;
+use std::marker::PhantomData;
+
+use bytes::BytesMut;
+use postgres_types::{to_sql_checked, FromSql, IsNull, ToSql, Type};
+
+/// Get's returned if an [`EncryptedColumn`] could not be encrypted or
+/// decrypted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum EncryptedColumnError {
+	Keyring(KeyringError),
+	/// The value was decrypted but could not be converted back.
+	InvalidValue,
+}
+
+impl From<KeyringError> for EncryptedColumnError {
+	fn from(e: KeyringError) -> Self {
+		Self::Keyring(e)
+	}
+}
+
+impl fmt::Display for EncryptedColumnError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		fmt::Debug::fmt(self, f)
+	}
+}
+
+impl Error for EncryptedColumnError {}
+
+/// A value which can be stored in an [`EncryptedColumn`].
+pub trait ColumnValue: Sized {
+	fn to_column_bytes(&self) -> Vec<u8>;
+
+	fn from_column_bytes(
+		bytes: Vec<u8>,
+	) -> Result<Self, Box<dyn Error + Sync + Send>>;
+}
+
+impl ColumnValue for Vec<u8> {
+	fn to_column_bytes(&self) -> Vec<u8> {
+		self.clone()
+	}
+
+	fn from_column_bytes(
+		bytes: Vec<u8>,
+	) -> Result<Self, Box<dyn Error + Sync + Send>> {
+		Ok(bytes)
+	}
+}
+
+impl ColumnValue for String {
+	fn to_column_bytes(&self) -> Vec<u8> {
+		self.as_bytes().to_vec()
+	}
+
+	fn from_column_bytes(
+		bytes: Vec<u8>,
+	) -> Result<Self, Box<dyn Error + Sync + Send>> {
+		String::from_utf8(bytes).map_err(Into::into)
+	}
+}
+
+/// Where a value is stored, it is authenticated together with the value.
+///
+/// A value encrypted for one context can't be decrypted with another one,
+/// so someone with write access to the database can't move ciphertexts
+/// between columns, or between rows if [`ColumnContext::with_row`] is used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnContext {
+	aad: Vec<u8>,
+}
+
+impl ColumnContext {
+	pub fn new(table: &str, column: &str) -> Self {
+		let mut ctx = Self { aad: vec![] };
+		ctx.push(table.as_bytes());
+		ctx.push(column.as_bytes());
+		ctx
+	}
+
+	/// Binds the value to a row, the id should never change for the row
+	/// (for example the primary key).
+	pub fn with_row(mut self, id: impl AsRef<[u8]>) -> Self {
+		self.push(id.as_ref());
+		self
+	}
+
+	fn push(&mut self, part: &[u8]) {
+		self.aad
+			.extend_from_slice(&(part.len() as u32).to_be_bytes());
+		self.aad.extend_from_slice(part);
+	}
+}
+
+/// An encrypted value which is stored in a `BYTEA` column.
+///
+/// Values are encrypted with the primary key of a [`Keyring`] and contain
+/// the key id, so they can still be read after the primary key was
+/// rotated.
+///
+/// ## Explicit keyring and context
+/// `ToSql` and `FromSql` only move the ciphertext, values are encrypted
+/// and decrypted with [`EncryptedColumn::encrypt`] and
+/// [`EncryptedColumn::decrypt`]. This is deliberate, the value is bound to
+/// its [`ColumnContext`] which `ToSql` and `FromSql` don't know, and a
+/// process wide keyring would be hidden global state. Queries take and
+/// return `EncryptedColumn`, only the code converting it from and to the
+/// plain value needs the keyring.
+///
+/// ## Rotation
+/// After promoting a new key, rows where [`EncryptedColumn::key_id`] is
+/// not the primary id need to be written again before the old key can be
+/// retired.
+///
+/// ## Example
+/// ```
+/// use fire_crypto::keyring::{ColumnContext, EncryptedColumn, Keyring};
+///
+/// let mut keyring = Keyring::new();
+/// keyring.generate_key();
+///
+/// let ctx = ColumnContext::new("users", "email")
+///     .with_row(42u64.to_be_bytes());
+/// let email = "alice@example.com".to_string();
+/// let col = EncryptedColumn::encrypt(&keyring, &email, &ctx).unwrap();
+/// // col can be passed as a query parameter or read from a row
+///
+/// assert_eq!(col.decrypt(&keyring, &ctx).unwrap(), email);
+///
+/// let other_row = ColumnContext::new("users", "email")
+///     .with_row(43u64.to_be_bytes());
+/// assert!(col.decrypt(&keyring, &other_row).is_err());
+/// ```
+#[derive(Clone, PartialEq, Eq)]
+pub struct EncryptedColumn<T> {
+	ciphertext: Vec<u8>,
+	value: PhantomData<fn() -> T>,
+}
+
+impl<T: ColumnValue> EncryptedColumn<T> {
+	pub fn encrypt(
+		keyring: &Keyring,
+		value: &T,
+		ctx: &ColumnContext,
+	) -> Result<Self, EncryptedColumnError> {
+		let ciphertext =
+			keyring.encrypt_with_aad(&value.to_column_bytes(), &ctx.aad)?;
+
+		Ok(Self {
+			ciphertext,
+			value: PhantomData,
+		})
+	}
+
+	pub fn decrypt(
+		&self,
+		keyring: &Keyring,
+		ctx: &ColumnContext,
+	) -> Result<T, EncryptedColumnError> {
+		let bytes = keyring.decrypt_with_aad(&self.ciphertext, &ctx.aad)?;
+		T::from_column_bytes(bytes)
+			.map_err(|_| EncryptedColumnError::InvalidValue)
+	}
+}
+
+impl<T> EncryptedColumn<T> {
+	/// Returns the id of the key the value was encrypted with.
+	pub fn key_id(&self) -> Option<KeyId> {
+		Keyring::key_id_of(&self.ciphertext)
+	}
+
+	pub fn as_bytes(&self) -> &[u8] {
+		&self.ciphertext
+	}
+}
+
+impl<T> fmt::Debug for EncryptedColumn<T> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("EncryptedColumn")
+			.field("key_id", &self.key_id())
+			.finish()
+	}
+}
+
+impl<T> ToSql for EncryptedColumn<T> {
+	fn to_sql(
+		&self,
+		ty: &Type,
+		out: &mut BytesMut,
+	) -> Result<IsNull, Box<dyn Error + Sync + Send>>
+	where
+		Self: Sized,
+	{
+		self.ciphertext.as_slice().to_sql(ty, out)
+	}
+
+	fn accepts(ty: &Type) -> bool
+	where
+		Self: Sized,
+	{
+		<&[u8] as ToSql>::accepts(ty)
+	}
+
+	to_sql_checked!();
+}
+
+impl<'r, T> FromSql<'r> for EncryptedColumn<T> {
+	fn from_sql(
+		ty: &Type,
+		raw: &'r [u8],
+	) -> Result<Self, Box<dyn Error + Sync + Send>> {
+		let bytes = <&[u8] as FromSql>::from_sql(ty, raw)?;
+
+		Ok(Self {
+			ciphertext: bytes.to_vec(),
+			value: PhantomData,
+		})
+	}
+
+	fn accepts(ty: &Type) -> bool {
+		<&[u8] as FromSql>::accepts(ty)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+
+	fn write(col: &EncryptedColumn<String>) -> Vec<u8> {
+		let mut out = BytesMut::new();
+		col.to_sql_checked(&Type::BYTEA, &mut out).unwrap();
+		out.to_vec()
+	}
+
+	fn read(raw: &[u8]) -> EncryptedColumn<String> {
+		EncryptedColumn::from_sql(&Type::BYTEA, raw).unwrap()
+	}
+
+	#[test]
+	fn write_read_rotate() {
+		let ctx = ColumnContext::new("users", "ssn").with_row(b"1");
+		let secret = "secret".to_string();
+
+		let mut keyring = Keyring::new();
+		let first = keyring.generate_key();
+
+		let col = EncryptedColumn::encrypt(&keyring, &secret, &ctx).unwrap();
+		let old = write(&col);
+		assert!(!old.windows(6).any(|w| w == b"secret"));
+		let mut out = BytesMut::new();
+		assert!(col.to_sql_checked(&Type::TEXT, &mut out).is_err());
+
+		let read_col = read(&old);
+		assert_eq!(read_col.decrypt(&keyring, &ctx).unwrap(), "secret");
+		assert_eq!(read_col.key_id(), Some(first));
+
+		// rotate
+		let second = keyring.generate_key();
+		keyring.promote(second).unwrap();
+
+		let value = read(&old).decrypt(&keyring, &ctx).unwrap();
+		let new =
+			write(&EncryptedColumn::encrypt(&keyring, &value, &ctx).unwrap());
+		assert_eq!(read(&new).key_id(), Some(second));
+
+		keyring.retire(first).unwrap();
+		assert!(read(&old).decrypt(&keyring, &ctx).is_err());
+		assert_eq!(read(&new).decrypt(&keyring, &ctx).unwrap(), "secret");
+
+		let mut modified = new.clone();
+		*modified.last_mut().unwrap() ^= 1;
+		assert!(read(&modified).decrypt(&keyring, &ctx).is_err());
+	}
+
+	#[test]
+	fn bound_to_context() {
+		let mut keyring = Keyring::new();
+		keyring.generate_key();
+
+		let ctx = ColumnContext::new("users", "ssn").with_row(b"1");
+		let col =
+			EncryptedColumn::encrypt(&keyring, &"secret".to_string(), &ctx)
+				.unwrap();
+		let raw = write(&col);
+
+		let others = [
+			ColumnContext::new("users", "ssn").with_row(b"2"),
+			ColumnContext::new("users", "ssn"),
+			ColumnContext::new("users", "email").with_row(b"1"),
+			ColumnContext::new("admins", "ssn").with_row(b"1"),
+			// the parts are length prefixed
+			ColumnContext::new("users", "ss").with_row(b"n1"),
+		];
+		for other in &others {
+			assert_eq!(
+				read(&raw).decrypt(&keyring, other).unwrap_err(),
+				EncryptedColumnError::Keyring(KeyringError::MacNotEqual)
+			);
+		}
+		assert_eq!(read(&raw).decrypt(&keyring, &ctx).unwrap(), "secret");
+
+		let bytes =
+			EncryptedColumn::encrypt(&keyring, &vec![0xff], &ctx).unwrap();
+		let col = read(bytes.as_bytes());
+		assert_eq!(
+			col.decrypt(&keyring, &ctx).unwrap_err(),
+			EncryptedColumnError::InvalidValue
+		);
+	}
+}
@@ -0,0 +1,513 @@
+//! A keyring holding multiple versioned symmetric keys.
+//!
+//! New messages are always encrypted with the primary key and contain its
+//! id, so messages encrypted with an older key can still be decrypted as
+//! long as the key is in the keyring.
+//!
+//! Every message uses a random nonce (XChaCha20-Poly1305), unlike
+//! `cipher::SyncKey` which requires both sides to keep a counter in sync.
+//! This makes the keyring suitable for data at rest.
+//!
+//! ## Rotation
+//! 1. Add a new key with [`Keyring::generate_key`] and distribute the
+//!    keyring, the new key can now decrypt but is not used yet.
+//! 2. [`Keyring::promote`] the new key, new messages use it.
+//! 3. Re-encrypt old messages and [`Keyring::retire`] the old key.
+//!
+//! With the `postgres` feature values can be stored in a `BYTEA` column
+//! with `EncryptedColumn`, which binds them to their table, column and row.
+//!
+//! ## Example
+//! ```
+//! use fire_crypto::keyring::Keyring;
+//!
+//! let mut keyring = Keyring::new();
+//! let first = keyring.generate_key();
+//!
+//! let old = keyring.encrypt(b"old message").unwrap();
+//!
+//! let second = keyring.generate_key();
+//! keyring.promote(second).unwrap();
+//!
+//! let new = keyring.encrypt(b"new message").unwrap();
+//! assert_eq!(Keyring::key_id_of(&old), Some(first));
+//! assert_eq!(Keyring::key_id_of(&new), Some(second));
+//!
+//! // both messages can still be decrypted
+//! assert_eq!(keyring.decrypt(&old).unwrap(), b"old message");
+//! assert_eq!(keyring.decrypt(&new).unwrap(), b"new message");
+//! ```
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+
+use zeroize::Zeroize;
+
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+
+#[cfg(feature = "postgres")]
+mod column;
+#[cfg(feature = "postgres")]
+pub use column::{
+	ColumnContext, ColumnValue, EncryptedColumn, EncryptedColumnError,
+};
+
+/// Identifies a key in a keyring.
+pub type KeyId = u32;
+
+const KEY_ID_LEN: usize = 4;
+const NONCE_LEN: usize = 24;
+const TAG_LEN: usize = 16;
+
+const SERIALIZE_VERSION: u8 = 1;
+const SERIALIZE_AAD: &[u8] = b"fire-crypto keyring";
+
+fn seal(key: &[u8; 32], header: &[u8], aad: &[u8], msg: &[u8]) -> Vec<u8> {
+	let mut nonce = [0u8; NONCE_LEN];
+	crate::fill_random(&mut nonce);
+
+	let mut full_aad = header.to_vec();
+	full_aad.extend_from_slice(aad);
+
+	let ct = XChaCha20Poly1305::new(key.into())
+		.encrypt(
+			XNonce::from_slice(&nonce),
+			Payload {
+				msg,
+				aad: &full_aad,
+			},
+		)
+		// only fails if the message is larger than 256GB
+		.expect("message too large");
+
+	let mut out = Vec::with_capacity(header.len() + NONCE_LEN + ct.len());
+	out.extend_from_slice(header);
+	out.extend_from_slice(&nonce);
+	out.extend_from_slice(&ct);
+	out
+}
+
+fn open(
+	key: &[u8; 32],
+	header_len: usize,
+	aad: &[u8],
+	ciphertext: &[u8],
+) -> Result<Vec<u8>, KeyringError> {
+	if ciphertext.len() < header_len + NONCE_LEN + TAG_LEN {
+		return Err(KeyringError::InvalidCiphertext);
+	}
+
+	let (header, rest) = ciphertext.split_at(header_len);
+	let (nonce, ct) = rest.split_at(NONCE_LEN);
+
+	let mut full_aad = header.to_vec();
+	full_aad.extend_from_slice(aad);
+
+	XChaCha20Poly1305::new(key.into())
+		.decrypt(
+			XNonce::from_slice(nonce),
+			Payload {
+				msg: ct,
+				aad: &full_aad,
+			},
+		)
+		.map_err(|_| KeyringError::MacNotEqual)
+}
+
+/// Holds multiple symmetric keys, one of them is the primary key which is
+/// used for encryption.
+#[derive(Clone, Default)]
+pub struct Keyring {
+	keys: BTreeMap<KeyId, [u8; 32]>,
+	primary: Option<KeyId>,
+}
+
+impl Keyring {
+	/// The overhead of an encrypted message (key id, nonce and tag).
+	pub const OVERHEAD: usize = KEY_ID_LEN + NONCE_LEN + TAG_LEN;
+
+	/// Creates an empty keyring.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Generates a new random key with the next free id.
+	///
+	/// The id is one higher than the highest id, if that is already
+	/// `KeyId::MAX` the lowest unused id is taken.
+	///
+	/// The key only becomes the primary key if the keyring was empty.
+	pub fn generate_key(&mut self) -> KeyId {
+		let id = match self.keys.keys().next_back() {
+			None => 1,
+			Some(&id) if id < KeyId::MAX => id + 1,
+			Some(_) => (1..=KeyId::MAX)
+				.find(|id| !self.keys.contains_key(id))
+				// holding KeyId::MAX keys would need more than 128GiB
+				.expect("no key id left"),
+		};
+
+		let mut key = [0u8; 32];
+		crate::fill_random(&mut key);
+		// the id is not used
+		self.insert(id, key).unwrap();
+
+		id
+	}
+
+	/// Adds a key, returns an error if the id is already used.
+	///
+	/// The key only becomes the primary key if the keyring was empty.
+	pub fn insert(
+		&mut self,
+		id: KeyId,
+		mut key: [u8; 32],
+	) -> Result<(), KeyringError> {
+		if self.keys.contains_key(&id) {
+			key.zeroize();
+			return Err(KeyringError::DuplicateKeyId);
+		}
+
+		self.keys.insert(id, key);
+		if self.primary.is_none() {
+			self.primary = Some(id);
+		}
+
+		Ok(())
+	}
+
+	/// Makes the key the primary key, which is used for encryption.
+	pub fn promote(&mut self, id: KeyId) -> Result<(), KeyringError> {
+		if !self.keys.contains_key(&id) {
+			return Err(KeyringError::UnknownKeyId);
+		}
+
+		self.primary = Some(id);
+		Ok(())
+	}
+
+	/// Removes a key, messages encrypted with this key can no longer be
+	/// decrypted.
+	///
+	/// The primary key can't be retired.
+	pub fn retire(&mut self, id: KeyId) -> Result<(), KeyringError> {
+		if self.primary == Some(id) {
+			return Err(KeyringError::RetirePrimaryKey);
+		}
+
+		let mut key =
+			self.keys.remove(&id).ok_or(KeyringError::UnknownKeyId)?;
+		key.zeroize();
+
+		Ok(())
+	}
+
+	pub fn primary_id(&self) -> Option<KeyId> {
+		self.primary
+	}
+
+	/// Returns the ids of all keys in ascending order.
+	pub fn ids(&self) -> impl Iterator<Item = KeyId> + '_ {
+		self.keys.keys().copied()
+	}
+
+	pub fn contains(&self, id: KeyId) -> bool {
+		self.keys.contains_key(&id)
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.keys.is_empty()
+	}
+
+	/// Used by modules which derive other keys from the keyring.
+	#[cfg(feature = "cookie")]
+	pub(crate) fn key(&self, id: KeyId) -> Option<&[u8; 32]> {
+		self.keys.get(&id)
+	}
+
+	/// Returns the id of the key which encrypted the message, without
+	/// verifying it.
+	///
+	/// Can be used to find messages which should be re-encrypted with
+	/// the primary key.
+	pub fn key_id_of(ciphertext: &[u8]) -> Option<KeyId> {
+		let id = ciphertext.get(..KEY_ID_LEN)?;
+		Some(KeyId::from_be_bytes(id.try_into().unwrap()))
+	}
+
+	/// Encrypts the message with the primary key.
+	///
+	/// The result contains the key id, a random nonce, the ciphertext and
+	/// a tag.
+	pub fn encrypt(&self, msg: &[u8]) -> Result<Vec<u8>, KeyringError> {
+		self.encrypt_with_aad(msg, &[])
+	}
+
+	/// Encrypts the message with the primary key, the aad is
+	/// authenticated but not stored in the result.
+	///
+	/// The same aad needs to be passed to decrypt the message.
+	pub fn encrypt_with_aad(
+		&self,
+		msg: &[u8],
+		aad: &[u8],
+	) -> Result<Vec<u8>, KeyringError> {
+		let id = self.primary.ok_or(KeyringError::NoPrimaryKey)?;
+		let key = &self.keys[&id];
+
+		Ok(seal(key, &id.to_be_bytes(), aad, msg))
+	}
+
+	/// Decrypts a message encrypted by any key in the keyring.
+	pub fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>, KeyringError> {
+		self.decrypt_with_aad(ciphertext, &[])
+	}
+
+	/// Decrypts a message encrypted with `encrypt_with_aad`.
+	pub fn decrypt_with_aad(
+		&self,
+		ciphertext: &[u8],
+		aad: &[u8],
+	) -> Result<Vec<u8>, KeyringError> {
+		let id = Self::key_id_of(ciphertext)
+			.ok_or(KeyringError::InvalidCiphertext)?;
+		let key = self.keys.get(&id).ok_or(KeyringError::UnknownKeyId)?;
+
+		open(key, KEY_ID_LEN, aad, ciphertext)
+	}
+
+	/// Serializes all keys and encrypts them with the given key.
+	pub fn to_encrypted_bytes(&self, key: &[u8; 32]) -> Vec<u8> {
+		let mut bytes = Vec::with_capacity(6 + self.keys.len() * 36);
+		bytes.push(SERIALIZE_VERSION);
+		match self.primary {
+			Some(id) => {
+				bytes.push(1);
+				bytes.extend_from_slice(&id.to_be_bytes());
+			}
+			None => {
+				bytes.push(0);
+				bytes.extend_from_slice(&[0; KEY_ID_LEN]);
+			}
+		}
+
+		for (id, key) in &self.keys {
+			bytes.extend_from_slice(&id.to_be_bytes());
+			bytes.extend_from_slice(key);
+		}
+
+		let encrypted = seal(key, &[SERIALIZE_VERSION], SERIALIZE_AAD, &bytes);
+		bytes.zeroize();
+
+		encrypted
+	}
+
+	/// Decrypts a keyring serialized with `to_encrypted_bytes`.
+	pub fn from_encrypted_bytes(
+		bytes: &[u8],
+		key: &[u8; 32],
+	) -> Result<Self, KeyringError> {
+		if bytes.first() != Some(&SERIALIZE_VERSION) {
+			return Err(KeyringError::InvalidCiphertext);
+		}
+
+		let mut plain = open(key, 1, SERIALIZE_AAD, bytes)?;
+		let r = Self::parse(&plain);
+		plain.zeroize();
+
+		r
+	}
+
+	fn parse(bytes: &[u8]) -> Result<Self, KeyringError> {
+		const HEADER: usize = 2 + KEY_ID_LEN;
+		const ENTRY: usize = KEY_ID_LEN + 32;
+
+		if bytes.len() < HEADER
+			|| bytes[0] != SERIALIZE_VERSION
+			|| (bytes.len() - HEADER) % ENTRY != 0
+		{
+			return Err(KeyringError::InvalidCiphertext);
+		}
+
+		let mut me = Self::new();
+		for entry in bytes[HEADER..].chunks_exact(ENTRY) {
+			let id = KeyId::from_be_bytes(entry[..4].try_into().unwrap());
+			me.insert(id, entry[4..].try_into().unwrap())?;
+		}
+
+		me.primary = match bytes[1] {
+			0 => None,
+			1 => {
+				let id = KeyId::from_be_bytes(bytes[2..6].try_into().unwrap());
+				if !me.contains(id) {
+					return Err(KeyringError::UnknownKeyId);
+				}
+				Some(id)
+			}
+			_ => return Err(KeyringError::InvalidCiphertext),
+		};
+
+		Ok(me)
+	}
+}
+
+impl fmt::Debug for Keyring {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("Keyring")
+			.field("ids", &self.keys.keys())
+			.field("primary", &self.primary)
+			.finish()
+	}
+}
+
+impl Drop for Keyring {
+	fn drop(&mut self) {
+		for key in self.keys.values_mut() {
+			key.zeroize();
+		}
+	}
+}
+
+/// Get's returned by keyring operations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum KeyringError {
+	/// The keyring doesn't contain a primary key.
+	NoPrimaryKey,
+	/// The key id is not in the keyring.
+	UnknownKeyId,
+	/// A key with the same id already exists.
+	DuplicateKeyId,
+	/// The primary key can't be retired, promote another key first.
+	RetirePrimaryKey,
+	/// The ciphertext is too short or has an invalid format.
+	InvalidCiphertext,
+	MacNotEqual,
+}
+
+impl fmt::Display for KeyringError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		fmt::Debug::fmt(self, f)
+	}
+}
+
+impl Error for KeyringError {}
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+
+	#[test]
+	fn rotation() {
+		let mut keyring = Keyring::new();
+		assert_eq!(keyring.encrypt(b"a"), Err(KeyringError::NoPrimaryKey));
+
+		let first = keyring.generate_key();
+		let second = keyring.generate_key();
+		assert_eq!((first, second), (1, 2));
+		// adding doesn't change the primary key
+		assert_eq!(keyring.primary_id(), Some(first));
+
+		let old = keyring.encrypt(b"old").unwrap();
+		assert_eq!(old.len(), 3 + Keyring::OVERHEAD);
+
+		keyring.promote(second).unwrap();
+		let new = keyring.encrypt(b"new").unwrap();
+		assert_eq!(Keyring::key_id_of(&new), Some(second));
+
+		assert_eq!(keyring.decrypt(&old).unwrap(), b"old");
+		assert_eq!(keyring.decrypt(&new).unwrap(), b"new");
+
+		assert_eq!(keyring.retire(second), Err(KeyringError::RetirePrimaryKey));
+		keyring.retire(first).unwrap();
+		assert_eq!(keyring.retire(first), Err(KeyringError::UnknownKeyId));
+		assert_eq!(keyring.decrypt(&old), Err(KeyringError::UnknownKeyId));
+		assert_eq!(keyring.promote(first), Err(KeyringError::UnknownKeyId));
+
+		assert_eq!(keyring.generate_key(), 3);
+		assert_eq!(keyring.ids().collect::<Vec<_>>(), [2, 3]);
+	}
+
+	#[test]
+	fn highest_id_taken() {
+		let mut keyring = Keyring::new();
+		keyring.insert(KeyId::MAX, [1; 32]).unwrap();
+		keyring.insert(1, [2; 32]).unwrap();
+
+		assert_eq!(keyring.generate_key(), 2);
+		assert_eq!(keyring.generate_key(), 3);
+	}
+
+	#[test]
+	fn tampering() {
+		let mut keyring = Keyring::new();
+		keyring.generate_key();
+		keyring.generate_key();
+
+		let mut ct = keyring.encrypt(b"hello").unwrap();
+		*ct.last_mut().unwrap() ^= 1;
+		assert_eq!(keyring.decrypt(&ct), Err(KeyringError::MacNotEqual));
+
+		// the key id is authenticated
+		let mut ct = keyring.encrypt(b"hello").unwrap();
+		ct[3] = 2;
+		assert_eq!(keyring.decrypt(&ct), Err(KeyringError::MacNotEqual));
+
+		assert_eq!(
+			keyring.decrypt(&[0, 0, 0, 1]),
+			Err(KeyringError::InvalidCiphertext)
+		);
+	}
+
+	#[test]
+	fn aad() {
+		let mut keyring = Keyring::new();
+		keyring.generate_key();
+
+		let ct = keyring.encrypt_with_aad(b"hello", b"users.email").unwrap();
+		assert_eq!(
+			keyring.decrypt_with_aad(&ct, b"users.email").unwrap(),
+			b"hello"
+		);
+		assert_eq!(
+			keyring.decrypt_with_aad(&ct, b"users.name"),
+			Err(KeyringError::MacNotEqual)
+		);
+		assert_eq!(keyring.decrypt(&ct), Err(KeyringError::MacNotEqual));
+	}
+
+	#[test]
+	fn serialize() {
+		let mut keyring = Keyring::new();
+		keyring.insert(5, [5u8; 32]).unwrap();
+		keyring.insert(7, [7u8; 32]).unwrap();
+		keyring.promote(7).unwrap();
+		assert_eq!(
+			keyring.insert(5, [1u8; 32]),
+			Err(KeyringError::DuplicateKeyId)
+		);
+
+		let ct = keyring.encrypt(b"hello").unwrap();
+
+		let wrapping_key = [42u8; 32];
+		let bytes = keyring.to_encrypted_bytes(&wrapping_key);
+
+		let restored =
+			Keyring::from_encrypted_bytes(&bytes, &wrapping_key).unwrap();
+		assert_eq!(restored.primary_id(), Some(7));
+		assert_eq!(restored.ids().collect::<Vec<_>>(), [5, 7]);
+		assert_eq!(restored.decrypt(&ct).unwrap(), b"hello");
+
+		assert_eq!(
+			Keyring::from_encrypted_bytes(&bytes, &[1u8; 32]).unwrap_err(),
+			KeyringError::MacNotEqual
+		);
+
+		let empty = Keyring::new().to_encrypted_bytes(&wrapping_key);
+		let empty = Keyring::from_encrypted_bytes(&empty, &wrapping_key);
+		assert!(empty.unwrap().is_empty());
+	}
+}
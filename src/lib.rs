@@ -17,10 +17,78 @@ pub mod signature;
 #[cfg(feature = "hash")]
 pub mod hash;
 
+#[cfg(feature = "convergent")]
+pub mod convergent;
+
+#[cfg(feature = "reencrypt")]
+pub mod reencrypt;
+
+#[cfg(feature = "onion")]
+pub mod onion;
+
+#[cfg(feature = "fpe")]
+pub mod fpe;
+
+#[cfg(feature = "envelope")]
+pub mod envelope;
+
+#[cfg(feature = "keyring")]
+pub mod keyring;
+
+#[cfg(feature = "group")]
+pub mod group;
+
+#[cfg(feature = "x3dh")]
+pub mod x3dh;
+
+#[cfg(feature = "sas")]
+pub mod sas;
+
+#[cfg(feature = "signcrypt")]
+pub mod signcrypt;
+
+#[cfg(feature = "sigma")]
+pub mod sigma;
+
+#[cfg(feature = "pake")]
+pub mod pake;
+
+#[cfg(feature = "oprf")]
+pub mod oprf;
+
+#[cfg(feature = "psi")]
+pub mod psi;
+
+#[cfg(feature = "serde-encrypt")]
+pub mod serde_encrypt;
+
+#[cfg(feature = "blind-index")]
+pub mod blind_index;
+
+#[cfg(feature = "secret-config")]
+pub mod secret_config;
+
+#[cfg(feature = "cookie")]
+pub mod cookie;
+
+#[cfg(feature = "branca")]
+pub mod branca;
+
+#[cfg(feature = "web-push")]
+pub mod web_push;
+
 pub mod token;
 
 pub mod error;
 
+#[cfg(any(
+	feature = "reencrypt",
+	feature = "pake",
+	feature = "oprf",
+	feature = "psi"
+))]
+mod ristretto;
+
 // from https://docs.rs/crate/chacha20/0.3.4/source/src/cipher.rs
 /// Xors two buffers. Both buffers need to have the same length.
 ///
@@ -39,6 +107,15 @@ pub fn fill_random(buf: &mut [u8]) {
 	OsRng.fill_bytes(buf)
 }
 
+/// Compares two slices without returning early on the first difference.
+///
+/// Only the length is leaked.
+#[allow(dead_code)]
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+	a.len() == b.len()
+		&& a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
 /// todo replace when rust #88582 get's stabilized
 ///
 /// Since this function multiplies s with 4
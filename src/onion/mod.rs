@@ -0,0 +1,504 @@
+//! Layered encryption for multi-hop relays.
+//!
+//! A [`Packet`] gets wrapped in one encryption layer per relay. Each relay
+//! can remove exactly one layer, which reveals only the next hop. Packets
+//! always have the same size, so a relay doesn't learn its position in the
+//! route.
+//!
+//! The format follows Sphinx. The per-hop keys are derived from a single
+//! ephemeral key which gets blinded at every hop, so the ephemeral public
+//! keys seen by two relays can't be linked.
+//!
+//! ## Note
+//! Relays need to remember processed packets if replays should be
+//! detected.
+//!
+//! ## Example
+//! ```
+//! use fire_crypto::cipher::Keypair;
+//! use fire_crypto::onion::{PacketBuilder, Processed, Relay};
+//!
+//! let relay_a = Relay::new(Keypair::new());
+//! let relay_b = Relay::new(Keypair::new());
+//!
+//! let mut builder = PacketBuilder::new();
+//! builder.add_hop(relay_a.public().clone());
+//! builder.add_hop(relay_b.public().clone());
+//! let packet = builder.build(b"Hey Bob").unwrap();
+//!
+//! // the packet is sent to relay a
+//! let packet = match relay_a.process(packet).unwrap() {
+//!     Processed::Forward { next_hop, packet } => {
+//!         assert_eq!(&next_hop, relay_b.public());
+//!         packet
+//!     }
+//!     Processed::Deliver(_) => unreachable!(),
+//! };
+//!
+//! match relay_b.process(packet).unwrap() {
+//!     Processed::Deliver(msg) => assert_eq!(msg, b"Hey Bob"),
+//!     Processed::Forward { .. } => unreachable!(),
+//! }
+//! ```
+
+use crate::cipher::{Keypair, Mac, PublicKey};
+use crate::error::TryFromError;
+use crate::{fill_random, xor};
+
+use std::convert::{TryFrom, TryInto};
+use std::error::Error;
+use std::fmt;
+
+use zeroize::Zeroize;
+
+use rand::rngs::OsRng;
+
+use chacha20::cipher::{KeyIvInit, StreamCipher};
+use chacha20::XChaCha20;
+
+use poly1305::Poly1305;
+use universal_hash::{KeyInit, UniversalHash};
+
+use x25519_dalek::{x25519, StaticSecret};
+
+/// The maximum number of relays a packet can pass.
+pub const MAX_HOPS: usize = 5;
+
+/// The size of the routing information per hop, the next public key
+/// and the mac for the next hop.
+const HOP_LEN: usize = PublicKey::LEN + Mac::LEN;
+
+const ROUTING_LEN: usize = MAX_HOPS * HOP_LEN;
+
+/// The size of the encrypted payload.
+pub const PAYLOAD_LEN: usize = 1024;
+
+/// The maximum length of a message in a packet.
+pub const MAX_MSG_LEN: usize = PAYLOAD_LEN - 2;
+
+const ALPHA_OFFSET: usize = 0;
+const BETA_OFFSET: usize = ALPHA_OFFSET + PublicKey::LEN;
+const GAMMA_OFFSET: usize = BETA_OFFSET + ROUTING_LEN;
+const PAYLOAD_OFFSET: usize = GAMMA_OFFSET + Mac::LEN;
+
+const ROUTING_DOMAIN: u8 = 1;
+const PAYLOAD_DOMAIN: u8 = 2;
+const KEYS_DOMAIN: u8 = 3;
+
+/// Get's returned if a packet could not be built or processed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum OnionError {
+	/// A packet needs at least one hop.
+	NoHops,
+	/// More than [`MAX_HOPS`] were added.
+	TooManyHops,
+	/// The message is longer than [`MAX_MSG_LEN`].
+	MessageTooLong,
+	/// The ephemeral public key of the packet is not valid.
+	InvalidPublicKey,
+	/// The packet was modified or is not meant for this relay.
+	MacNotEqual,
+	/// The decrypted payload is not valid.
+	InvalidPayload,
+}
+
+impl fmt::Display for OnionError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		fmt::Debug::fmt(self, f)
+	}
+}
+
+impl Error for OnionError {}
+
+/// The keys a hop uses to process a packet.
+struct HopKeys {
+	shared_secret: [u8; 32],
+	mac_key: [u8; 32],
+	blinding: [u8; 32],
+}
+
+impl HopKeys {
+	fn new(shared_secret: [u8; 32]) -> Self {
+		let mut keys = [0u8; 64];
+		keystream(&shared_secret, KEYS_DOMAIN, &mut keys);
+
+		let this = Self {
+			shared_secret,
+			mac_key: keys[..32].try_into().unwrap(),
+			blinding: keys[32..].try_into().unwrap(),
+		};
+		keys.zeroize();
+
+		this
+	}
+
+	fn routing_stream(&self) -> [u8; ROUTING_LEN + HOP_LEN] {
+		let mut stream = [0u8; ROUTING_LEN + HOP_LEN];
+		keystream(&self.shared_secret, ROUTING_DOMAIN, &mut stream);
+		stream
+	}
+
+	fn apply_payload_stream(&self, payload: &mut [u8]) {
+		keystream(&self.shared_secret, PAYLOAD_DOMAIN, payload);
+	}
+
+	fn mac(&self, beta: &[u8], payload: &[u8]) -> Mac {
+		let mut poly = Poly1305::new(self.mac_key.as_ref().into());
+		poly.update_padded(beta);
+		poly.update_padded(payload);
+
+		Mac::new(poly.finalize())
+	}
+}
+
+impl Drop for HopKeys {
+	fn drop(&mut self) {
+		self.shared_secret.zeroize();
+		self.mac_key.zeroize();
+		self.blinding.zeroize();
+	}
+}
+
+fn keystream(secret: &[u8; 32], domain: u8, buf: &mut [u8]) {
+	let mut nonce = [0u8; 24];
+	nonce[0] = domain;
+
+	let mut cipher = XChaCha20::new(secret.into(), nonce.as_ref().into());
+	cipher.apply_keystream(buf);
+}
+
+/// A packet with a fixed size.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Packet {
+	bytes: Vec<u8>,
+}
+
+impl Packet {
+	pub const LEN: usize = PAYLOAD_OFFSET + PAYLOAD_LEN;
+
+	/// ## Panics
+	/// if the slice is not [`Packet::LEN`] bytes long.
+	pub fn from_slice(slice: &[u8]) -> Self {
+		slice.try_into().unwrap()
+	}
+
+	pub fn into_bytes(self) -> Vec<u8> {
+		self.bytes
+	}
+
+	fn alpha(&self) -> PublicKey {
+		PublicKey::from_slice(&self.bytes[ALPHA_OFFSET..BETA_OFFSET])
+	}
+
+	fn beta(&self) -> &[u8] {
+		&self.bytes[BETA_OFFSET..GAMMA_OFFSET]
+	}
+
+	fn gamma(&self) -> Mac {
+		Mac::from_slice(&self.bytes[GAMMA_OFFSET..PAYLOAD_OFFSET])
+	}
+
+	fn payload_mut(&mut self) -> &mut [u8] {
+		&mut self.bytes[PAYLOAD_OFFSET..]
+	}
+}
+
+impl fmt::Debug for Packet {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str("Packet")
+	}
+}
+
+impl TryFrom<&[u8]> for Packet {
+	type Error = TryFromError;
+
+	fn try_from(v: &[u8]) -> Result<Self, Self::Error> {
+		if v.len() != Self::LEN {
+			return Err(TryFromError::from_any(()));
+		}
+
+		Ok(Self { bytes: v.to_vec() })
+	}
+}
+
+impl AsRef<[u8]> for Packet {
+	fn as_ref(&self) -> &[u8] {
+		&self.bytes
+	}
+}
+
+/// Builds a packet which passes through every added hop.
+///
+/// Every builder uses a new ephemeral key.
+pub struct PacketBuilder {
+	// the ephemeral secret is used for one exchange per hop, so it can't
+	// be an EphemeralKeypair
+	ephemeral: StaticSecret,
+	ephemeral_public: PublicKey,
+	route: Vec<PublicKey>,
+}
+
+impl PacketBuilder {
+	pub fn new() -> Self {
+		let ephemeral = StaticSecret::random_from_rng(OsRng);
+		let ephemeral_public = PublicKey::from_static_secret(&ephemeral);
+
+		Self {
+			ephemeral,
+			ephemeral_public,
+			route: vec![],
+		}
+	}
+
+	/// Adds a relay to the route, the last hop receives the message.
+	pub fn add_hop(&mut self, public_key: PublicKey) {
+		self.route.push(public_key);
+	}
+
+	pub fn build(self, msg: &[u8]) -> Result<Packet, OnionError> {
+		let hops = self.route.len();
+		if hops == 0 {
+			return Err(OnionError::NoHops);
+		}
+		if hops > MAX_HOPS {
+			return Err(OnionError::TooManyHops);
+		}
+		if msg.len() > MAX_MSG_LEN {
+			return Err(OnionError::MessageTooLong);
+		}
+
+		// derive the keys of every hop, blinding the ephemeral key after
+		// each hop
+		let mut keys: Vec<HopKeys> = Vec::with_capacity(hops);
+		for public_key in &self.route {
+			let mut shared =
+				self.ephemeral.diffie_hellman(public_key.inner()).to_bytes();
+			for prev in &keys {
+				shared = x25519(prev.blinding, shared);
+			}
+
+			keys.push(HopKeys::new(shared));
+		}
+
+		// the filler is the garbage each hop appends to the routing
+		// information, which the last hop receives
+		let mut filler = vec![];
+		for hop_keys in &keys[..hops - 1] {
+			filler.extend_from_slice(&[0u8; HOP_LEN]);
+			let stream = hop_keys.routing_stream();
+			let start = ROUTING_LEN + HOP_LEN - filler.len();
+			xor(&mut filler, &stream[start..]);
+		}
+
+		let mut payload = vec![0u8; PAYLOAD_LEN];
+		payload[..2].copy_from_slice(&(msg.len() as u16).to_be_bytes());
+		payload[2..2 + msg.len()].copy_from_slice(msg);
+
+		let mut beta = [0u8; ROUTING_LEN];
+		fill_random(&mut beta);
+		let mut next_mac = [0u8; Mac::LEN];
+
+		for (i, hop_keys) in keys.iter().enumerate().rev() {
+			beta.copy_within(..ROUTING_LEN - HOP_LEN, HOP_LEN);
+			// the last hop receives an empty public key
+			beta[..PublicKey::LEN].fill(0);
+			if let Some(next) = self.route.get(i + 1) {
+				beta[..PublicKey::LEN].copy_from_slice(next.as_ref());
+			}
+			beta[PublicKey::LEN..HOP_LEN].copy_from_slice(&next_mac);
+
+			xor(&mut beta, &hop_keys.routing_stream()[..ROUTING_LEN]);
+			if i == hops - 1 {
+				beta[ROUTING_LEN - filler.len()..].copy_from_slice(&filler);
+			}
+
+			hop_keys.apply_payload_stream(&mut payload);
+			next_mac = hop_keys.mac(&beta, &payload).into_bytes();
+		}
+
+		let mut bytes = Vec::with_capacity(Packet::LEN);
+		bytes.extend_from_slice(self.ephemeral_public.as_ref());
+		bytes.extend_from_slice(&beta);
+		bytes.extend_from_slice(&next_mac);
+		bytes.extend_from_slice(&payload);
+
+		Ok(Packet { bytes })
+	}
+}
+
+impl fmt::Debug for PacketBuilder {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("PacketBuilder")
+			.field("ephemeral_public", &self.ephemeral_public)
+			.field("route", &self.route)
+			.finish()
+	}
+}
+
+/// The result of processing a packet.
+#[derive(Debug)]
+pub enum Processed {
+	/// The packet needs to be forwarded to the next hop.
+	Forward { next_hop: PublicKey, packet: Packet },
+	/// This relay is the last hop and received the message.
+	Deliver(Vec<u8>),
+}
+
+/// A relay which can remove one layer of a packet.
+#[derive(Debug, Clone)]
+pub struct Relay {
+	keypair: Keypair,
+}
+
+impl Relay {
+	pub fn new(keypair: Keypair) -> Self {
+		Self { keypair }
+	}
+
+	pub fn public(&self) -> &PublicKey {
+		self.keypair.public()
+	}
+
+	/// Removes one layer of the packet.
+	pub fn process(&self, mut packet: Packet) -> Result<Processed, OnionError> {
+		let alpha = packet.alpha();
+		let shared = self.keypair.secret.diffie_hellman(alpha.inner());
+		if !shared.was_contributory() {
+			return Err(OnionError::InvalidPublicKey);
+		}
+
+		let keys = HopKeys::new(shared.to_bytes());
+
+		let mac = keys.mac(packet.beta(), &packet.bytes[PAYLOAD_OFFSET..]);
+		if mac != packet.gamma() {
+			return Err(OnionError::MacNotEqual);
+		}
+
+		let mut routing = [0u8; ROUTING_LEN + HOP_LEN];
+		routing[..ROUTING_LEN].copy_from_slice(packet.beta());
+		xor(&mut routing, &keys.routing_stream());
+
+		keys.apply_payload_stream(packet.payload_mut());
+
+		let next_hop = PublicKey::from_slice(&routing[..PublicKey::LEN]);
+		if next_hop.as_ref() == [0u8; PublicKey::LEN] {
+			let payload = &packet.bytes[PAYLOAD_OFFSET..];
+			let len = u16::from_be_bytes([payload[0], payload[1]]) as usize;
+			if len > MAX_MSG_LEN {
+				return Err(OnionError::InvalidPayload);
+			}
+
+			return Ok(Processed::Deliver(payload[2..2 + len].to_vec()));
+		}
+
+		let alpha = x25519(keys.blinding, alpha.to_bytes());
+
+		let bytes = &mut packet.bytes;
+		bytes[ALPHA_OFFSET..BETA_OFFSET].copy_from_slice(&alpha);
+		bytes[BETA_OFFSET..GAMMA_OFFSET].copy_from_slice(&routing[HOP_LEN..]);
+		bytes[GAMMA_OFFSET..PAYLOAD_OFFSET]
+			.copy_from_slice(&routing[PublicKey::LEN..HOP_LEN]);
+
+		Ok(Processed::Forward { next_hop, packet })
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+
+	fn route(hops: usize) {
+		let relays: Vec<_> =
+			(0..hops).map(|_| Relay::new(Keypair::new())).collect();
+
+		let mut builder = PacketBuilder::new();
+		for relay in &relays {
+			builder.add_hop(relay.public().clone());
+		}
+
+		let msg = b"hey thats a nice message";
+		let mut packet = builder.build(msg).unwrap();
+		let mut alphas = vec![];
+
+		for (i, relay) in relays.iter().enumerate() {
+			assert_eq!(packet.as_ref().len(), Packet::LEN);
+			alphas.push(packet.alpha());
+
+			match relay.process(packet.clone()).unwrap() {
+				Processed::Forward {
+					next_hop,
+					packet: p,
+				} => {
+					assert_eq!(&next_hop, relays[i + 1].public());
+					packet = p;
+				}
+				Processed::Deliver(m) => {
+					assert_eq!(i, hops - 1);
+					assert_eq!(m, msg);
+				}
+			}
+		}
+
+		// every hop sees another ephemeral key
+		for (i, alpha) in alphas.iter().enumerate() {
+			assert!(!alphas[i + 1..].contains(alpha));
+		}
+	}
+
+	#[test]
+	fn all_route_lengths() {
+		for hops in 1..=MAX_HOPS {
+			route(hops);
+		}
+	}
+
+	#[test]
+	fn too_many_hops() {
+		let mut builder = PacketBuilder::new();
+		for _ in 0..=MAX_HOPS {
+			builder.add_hop(Keypair::new().public().clone());
+		}
+
+		assert_eq!(builder.build(b"").unwrap_err(), OnionError::TooManyHops);
+	}
+
+	#[test]
+	fn modified_packet() {
+		let relay = Relay::new(Keypair::new());
+		let mut builder = PacketBuilder::new();
+		builder.add_hop(relay.public().clone());
+		builder.add_hop(Keypair::new().public().clone());
+		let packet = builder.build(b"hey").unwrap();
+
+		for pos in [BETA_OFFSET, GAMMA_OFFSET, Packet::LEN - 1] {
+			let mut bytes = packet.clone().into_bytes();
+			bytes[pos] ^= 1;
+
+			assert_eq!(
+				relay.process(Packet::from_slice(&bytes)).unwrap_err(),
+				OnionError::MacNotEqual
+			);
+		}
+
+		// another relay can't process the packet
+		let other = Relay::new(Keypair::new());
+		assert_eq!(other.process(packet).unwrap_err(), OnionError::MacNotEqual);
+	}
+
+	#[test]
+	fn low_order_ephemeral_key() {
+		let relay = Relay::new(Keypair::new());
+		let mut builder = PacketBuilder::new();
+		builder.add_hop(relay.public().clone());
+
+		let mut bytes = builder.build(b"hey").unwrap().into_bytes();
+		bytes[..32].fill(0);
+
+		assert_eq!(
+			relay.process(Packet::from_slice(&bytes)).unwrap_err(),
+			OnionError::InvalidPublicKey
+		);
+	}
+}
@@ -0,0 +1,886 @@
+//! Oblivious pseudorandom functions (RFC 9497) over Ristretto255 with
+//! SHA-512.
+//!
+//! The client learns the output of a PRF keyed by the server, without the
+//! server learning the input or the output. This allows to issue tokens
+//! which the server can later verify (by evaluating the PRF itself) but
+//! can't link to the issuance.
+//!
+//! Three modes are supported:
+//! - [`Mode::Oprf`] the basic protocol.
+//! - [`Mode::Voprf`] the server proves that it used the key matching its
+//!   public key, so it can't tag clients with different keys.
+//! - [`Mode::Poprf`] like the verifiable mode but additionally a public
+//!   info gets bound to the output.
+//!
+//! ## Example
+//! ```
+//! use fire_crypto::oprf::{Client, Mode, Server};
+//!
+//! let server = Server::new(Mode::Voprf);
+//! let client = Client::new(Mode::Voprf, Some(server.public_key().clone()));
+//!
+//! // the client blinds a random value
+//! let (state, blinded) = client.blind(b"random token input").unwrap();
+//!
+//! // the server evaluates the blinded value
+//! let evaluation = server.blind_evaluate(&[blinded], b"").unwrap();
+//!
+//! // the client verifies the proof and removes the blind
+//! let tokens = client.finalize(vec![state], &evaluation, b"").unwrap();
+//!
+//! // later the server can check the token without knowing who got it
+//! let token = server.evaluate(b"random token input", b"").unwrap();
+//! assert_eq!(tokens[0], token);
+//! ```
+
+use crate::error::TryFromError;
+use crate::ristretto::{read_non_identity_point, read_scalar};
+use crate::token::Token;
+
+use std::convert::{TryFrom, TryInto};
+use std::error::Error;
+use std::fmt;
+
+use rand::rngs::OsRng;
+
+use curve25519_dalek::traits::Identity;
+use curve25519_dalek::{RistrettoPoint, Scalar};
+
+use sha2::{Digest, Sha512};
+
+use zeroize::Zeroize;
+
+/// The output of the PRF.
+pub type Output = Token<64>;
+
+const IDENTIFIER: &[u8] = b"ristretto255-SHA512";
+
+/// The protocol variant, client and server need to use the same.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Mode {
+	Oprf,
+	Voprf,
+	Poprf,
+}
+
+impl Mode {
+	fn context_string(&self) -> Vec<u8> {
+		let mode = match self {
+			Self::Oprf => 0,
+			Self::Voprf => 1,
+			Self::Poprf => 2,
+		};
+
+		let mut s = b"OPRFV1-".to_vec();
+		s.push(mode);
+		s.push(b'-');
+		s.extend_from_slice(IDENTIFIER);
+		s
+	}
+
+	fn is_verifiable(&self) -> bool {
+		!matches!(self, Self::Oprf)
+	}
+}
+
+/// Get's returned if an operation of the protocol failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum OprfError {
+	/// The input hashes to the identity.
+	InvalidInput,
+	/// The key could not be derived or the tweaked key is the identity.
+	DeriveKeyPair,
+	/// The verifiable modes need a public key, or a proof is missing.
+	MissingPublicKeyOrProof,
+	/// The number of blinded and evaluated elements does not match.
+	BatchSizeMismatch,
+	/// The proof is not valid.
+	InvalidProof,
+}
+
+impl fmt::Display for OprfError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		fmt::Debug::fmt(self, f)
+	}
+}
+
+impl Error for OprfError {}
+
+// HELPERS
+
+/// expand_message_xmd from RFC 9380 with SHA-512, only for outputs up to
+/// 255 * 64 bytes.
+fn expand_message_xmd(msgs: &[&[u8]], dst: &[u8], len: usize) -> Vec<u8> {
+	debug_assert!(len <= 255 * 64 && dst.len() <= 255);
+	let ell = (len + 63) / 64;
+
+	let mut hasher = Sha512::new();
+	hasher.update([0u8; 128]);
+	for msg in msgs {
+		hasher.update(msg);
+	}
+	hasher.update((len as u16).to_be_bytes());
+	hasher.update([0]);
+	hasher.update(dst);
+	hasher.update([dst.len() as u8]);
+	let b0 = hasher.finalize();
+
+	let mut out = Vec::with_capacity(ell * 64);
+	let mut prev = [0u8; 64];
+	for i in 1..=ell {
+		let mut hasher = Sha512::new();
+		let mut input = b0;
+		crate::xor(&mut input, &prev);
+		hasher.update(input);
+		hasher.update([i as u8]);
+		hasher.update(dst);
+		hasher.update([dst.len() as u8]);
+		prev = hasher.finalize().into();
+		out.extend_from_slice(&prev);
+	}
+
+	out.truncate(len);
+	out
+}
+
+fn hash_to_group(mode: Mode, input: &[u8]) -> RistrettoPoint {
+	let mut dst = b"HashToGroup-".to_vec();
+	dst.extend_from_slice(&mode.context_string());
+
+	let bytes = expand_message_xmd(&[input], &dst, 64);
+	RistrettoPoint::from_uniform_bytes(&bytes.try_into().unwrap())
+}
+
+fn hash_to_scalar_dst(msgs: &[&[u8]], dst: &[u8]) -> Scalar {
+	let bytes = expand_message_xmd(msgs, dst, 64);
+	Scalar::from_bytes_mod_order_wide(&bytes.try_into().unwrap())
+}
+
+fn hash_to_scalar(mode: Mode, msgs: &[&[u8]]) -> Scalar {
+	let mut dst = b"HashToScalar-".to_vec();
+	dst.extend_from_slice(&mode.context_string());
+
+	hash_to_scalar_dst(msgs, &dst)
+}
+
+/// Prefixes the data with its length as two bytes.
+fn length_prefixed(data: &[u8]) -> Vec<u8> {
+	let mut v = (data.len() as u16).to_be_bytes().to_vec();
+	v.extend_from_slice(data);
+	v
+}
+
+fn finalize_hash(
+	input: &[u8],
+	info: Option<&[u8]>,
+	n: &RistrettoPoint,
+) -> Output {
+	let mut hasher = Sha512::new();
+	hasher.update(length_prefixed(input));
+	if let Some(info) = info {
+		hasher.update(length_prefixed(info));
+	}
+	hasher.update(length_prefixed(n.compress().as_bytes()));
+	hasher.update(b"Finalize");
+
+	Token::from(<[u8; 64]>::from(hasher.finalize()))
+}
+
+/// The scalar `m` which tweaks the key in the POPRF mode.
+fn info_scalar(info: &[u8]) -> Scalar {
+	hash_to_scalar(Mode::Poprf, &[b"Info", &length_prefixed(info)])
+}
+
+// ELEMENTS
+
+macro_rules! point_type {
+	($(#[$doc:meta])* $name:ident) => {
+		$(#[$doc])*
+		#[derive(Clone, PartialEq, Eq)]
+		pub struct $name {
+			point: RistrettoPoint,
+		}
+
+		impl $name {
+			pub const LEN: usize = 32;
+
+			pub fn to_bytes(&self) -> [u8; 32] {
+				self.point.compress().to_bytes()
+			}
+		}
+
+		impl fmt::Debug for $name {
+			fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+				f.debug_tuple(stringify!($name))
+					.field(&self.to_bytes())
+					.finish()
+			}
+		}
+
+		impl TryFrom<&[u8]> for $name {
+			type Error = TryFromError;
+
+			fn try_from(v: &[u8]) -> Result<Self, Self::Error> {
+				read_non_identity_point(v).map(|point| Self { point })
+			}
+		}
+	};
+}
+
+point_type!(
+	/// The public key of the server, needed in the verifiable modes.
+	PublicKey
+);
+
+point_type!(
+	/// The blinded input, sent from the client to the server.
+	BlindedElement
+);
+
+point_type!(
+	/// The evaluated blinded input, sent from the server to the client.
+	EvaluatedElement
+);
+
+/// A proof that the server used the key matching its public key.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Proof {
+	c: Scalar,
+	s: Scalar,
+}
+
+impl Proof {
+	pub const LEN: usize = 64;
+
+	pub fn to_bytes(&self) -> [u8; 64] {
+		let mut bytes = [0u8; 64];
+		bytes[..32].copy_from_slice(self.c.as_bytes());
+		bytes[32..].copy_from_slice(self.s.as_bytes());
+		bytes
+	}
+}
+
+impl fmt::Debug for Proof {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_tuple("Proof").field(&self.to_bytes()).finish()
+	}
+}
+
+impl TryFrom<&[u8]> for Proof {
+	type Error = TryFromError;
+
+	fn try_from(v: &[u8]) -> Result<Self, Self::Error> {
+		if v.len() != Self::LEN {
+			return Err(TryFromError::from_any(()));
+		}
+
+		Ok(Self {
+			c: read_scalar(&v[..32])?,
+			s: read_scalar(&v[32..])?,
+		})
+	}
+}
+
+/// The response of the server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Evaluation {
+	pub elements: Vec<EvaluatedElement>,
+	/// Only present in the verifiable modes.
+	pub proof: Option<Proof>,
+}
+
+// PROOFS
+
+fn compute_composites(
+	mode: Mode,
+	k: Option<&Scalar>,
+	b: &RistrettoPoint,
+	c: &[RistrettoPoint],
+	d: &[RistrettoPoint],
+) -> (RistrettoPoint, RistrettoPoint) {
+	let mut seed_dst = b"Seed-".to_vec();
+	seed_dst.extend_from_slice(&mode.context_string());
+
+	let mut hasher = Sha512::new();
+	hasher.update(length_prefixed(b.compress().as_bytes()));
+	hasher.update(length_prefixed(&seed_dst));
+	let seed = hasher.finalize();
+
+	let mut m = RistrettoPoint::identity();
+	let mut z = RistrettoPoint::identity();
+	for (i, (ci, di)) in c.iter().zip(d).enumerate() {
+		let di_scalar = hash_to_scalar(
+			mode,
+			&[
+				&length_prefixed(&seed),
+				&(i as u16).to_be_bytes(),
+				&length_prefixed(ci.compress().as_bytes()),
+				&length_prefixed(di.compress().as_bytes()),
+				b"Composite",
+			],
+		);
+		m += ci * di_scalar;
+		if k.is_none() {
+			z += di * di_scalar;
+		}
+	}
+
+	if let Some(k) = k {
+		z = m * k;
+	}
+
+	(m, z)
+}
+
+fn challenge(mode: Mode, points: [&RistrettoPoint; 5]) -> Scalar {
+	let mut msgs: Vec<Vec<u8>> = points
+		.iter()
+		.map(|p| length_prefixed(p.compress().as_bytes()))
+		.collect();
+	msgs.push(b"Challenge".to_vec());
+
+	let msgs: Vec<&[u8]> = msgs.iter().map(|m| m.as_slice()).collect();
+	hash_to_scalar(mode, &msgs)
+}
+
+fn generate_proof(
+	mode: Mode,
+	k: &Scalar,
+	b: &RistrettoPoint,
+	c: &[RistrettoPoint],
+	d: &[RistrettoPoint],
+	mut r: Scalar,
+) -> Proof {
+	let (m, z) = compute_composites(mode, Some(k), b, c, d);
+	let t2 = RistrettoPoint::mul_base(&r);
+	let t3 = m * r;
+
+	let c = challenge(mode, [b, &m, &z, &t2, &t3]);
+	let s = r - c * k;
+	r.zeroize();
+
+	Proof { c, s }
+}
+
+fn verify_proof(
+	mode: Mode,
+	b: &RistrettoPoint,
+	c: &[RistrettoPoint],
+	d: &[RistrettoPoint],
+	proof: &Proof,
+) -> bool {
+	let (m, z) = compute_composites(mode, None, b, c, d);
+	let t2 = RistrettoPoint::mul_base(&proof.s) + b * proof.c;
+	let t3 = m * proof.s + z * proof.c;
+
+	challenge(mode, [b, &m, &z, &t2, &t3]) == proof.c
+}
+
+// SERVER
+
+/// The server which holds the PRF key.
+pub struct Server {
+	mode: Mode,
+	secret: Scalar,
+	public: PublicKey,
+}
+
+impl Server {
+	pub const LEN: usize = 32;
+
+	/// Creates a server with a random key.
+	pub fn new(mode: Mode) -> Self {
+		Self::from_scalar(mode, Scalar::random(&mut OsRng))
+	}
+
+	fn from_scalar(mode: Mode, secret: Scalar) -> Self {
+		let public = PublicKey {
+			point: RistrettoPoint::mul_base(&secret),
+		};
+
+		Self {
+			mode,
+			secret,
+			public,
+		}
+	}
+
+	/// Derives the key from a seed and an info (DeriveKeyPair).
+	pub fn derive(
+		mode: Mode,
+		seed: &[u8; 32],
+		info: &[u8],
+	) -> Result<Self, OprfError> {
+		let mut dst = b"DeriveKeyPair".to_vec();
+		dst.extend_from_slice(&mode.context_string());
+		let info = length_prefixed(info);
+
+		for counter in 0..=255u8 {
+			let secret = hash_to_scalar_dst(&[seed, &info, &[counter]], &dst);
+			if secret != Scalar::ZERO {
+				return Ok(Self::from_scalar(mode, secret));
+			}
+		}
+
+		Err(OprfError::DeriveKeyPair)
+	}
+
+	/// Creates a server from the bytes returned by [`Server::to_bytes`].
+	pub fn from_bytes(mode: Mode, bytes: &[u8]) -> Result<Self, TryFromError> {
+		let secret = read_scalar(bytes)?;
+		if secret == Scalar::ZERO {
+			return Err(TryFromError::from_any(()));
+		}
+
+		Ok(Self::from_scalar(mode, secret))
+	}
+
+	/// Returns the secret key.
+	pub fn to_bytes(&self) -> [u8; 32] {
+		self.secret.to_bytes()
+	}
+
+	pub fn mode(&self) -> Mode {
+		self.mode
+	}
+
+	pub fn public_key(&self) -> &PublicKey {
+		&self.public
+	}
+
+	/// The key used to evaluate, in the POPRF mode it is tweaked with the
+	/// info.
+	fn evaluation_key(&self, info: &[u8]) -> Result<Scalar, OprfError> {
+		if self.mode != Mode::Poprf {
+			return Ok(self.secret);
+		}
+
+		let t = self.secret + info_scalar(info);
+		if t == Scalar::ZERO {
+			return Err(OprfError::InvalidInput);
+		}
+
+		Ok(t)
+	}
+
+	/// Evaluates the blinded elements of a client.
+	///
+	/// The info is only used in the POPRF mode.
+	pub fn blind_evaluate(
+		&self,
+		blinded: &[BlindedElement],
+		info: &[u8],
+	) -> Result<Evaluation, OprfError> {
+		self.blind_evaluate_with(blinded, info, Scalar::random(&mut OsRng))
+	}
+
+	fn blind_evaluate_with(
+		&self,
+		blinded: &[BlindedElement],
+		info: &[u8],
+		r: Scalar,
+	) -> Result<Evaluation, OprfError> {
+		let mut k = self.evaluation_key(info)?;
+
+		let blinded: Vec<_> = blinded.iter().map(|b| b.point).collect();
+		let evaluated: Vec<_> = match self.mode {
+			Mode::Poprf => {
+				let inv = k.invert();
+				blinded.iter().map(|b| b * inv).collect()
+			}
+			_ => blinded.iter().map(|b| b * k).collect(),
+		};
+
+		let proof = match self.mode {
+			Mode::Oprf => None,
+			Mode::Voprf => Some(generate_proof(
+				self.mode,
+				&k,
+				&self.public.point,
+				&blinded,
+				&evaluated,
+				r,
+			)),
+			// the roles of the elements are swapped since the evaluated
+			// element gets multiplied with the tweaked key to get the
+			// blinded element
+			Mode::Poprf => Some(generate_proof(
+				self.mode,
+				&k,
+				&RistrettoPoint::mul_base(&k),
+				&evaluated,
+				&blinded,
+				r,
+			)),
+		};
+		k.zeroize();
+
+		Ok(Evaluation {
+			elements: evaluated
+				.into_iter()
+				.map(|point| EvaluatedElement { point })
+				.collect(),
+			proof,
+		})
+	}
+
+	/// Computes the output directly, for example to check a token.
+	///
+	/// The info is only used in the POPRF mode.
+	pub fn evaluate(
+		&self,
+		input: &[u8],
+		info: &[u8],
+	) -> Result<Output, OprfError> {
+		let element = hash_to_group(self.mode, input);
+		if element == RistrettoPoint::identity() {
+			return Err(OprfError::InvalidInput);
+		}
+
+		let mut k = self.evaluation_key(info)?;
+		let output = match self.mode {
+			Mode::Poprf => {
+				finalize_hash(input, Some(info), &(element * k.invert()))
+			}
+			_ => finalize_hash(input, None, &(element * k)),
+		};
+		k.zeroize();
+
+		Ok(output)
+	}
+}
+
+impl fmt::Debug for Server {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("Server")
+			.field("mode", &self.mode)
+			.field("public", &self.public)
+			.finish()
+	}
+}
+
+impl Drop for Server {
+	fn drop(&mut self) {
+		self.secret.zeroize();
+	}
+}
+
+// CLIENT
+
+/// Needs to be kept by the client until the evaluation is received.
+pub struct ClientState {
+	input: Vec<u8>,
+	blind: Scalar,
+	blinded: RistrettoPoint,
+}
+
+impl fmt::Debug for ClientState {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str("ClientState")
+	}
+}
+
+impl Drop for ClientState {
+	fn drop(&mut self) {
+		self.blind.zeroize();
+	}
+}
+
+/// The client which wants to learn the outputs.
+#[derive(Debug, Clone)]
+pub struct Client {
+	mode: Mode,
+	public_key: Option<PublicKey>,
+}
+
+impl Client {
+	/// The verifiable modes need the public key of the server.
+	pub fn new(mode: Mode, public_key: Option<PublicKey>) -> Self {
+		Self { mode, public_key }
+	}
+
+	pub fn mode(&self) -> Mode {
+		self.mode
+	}
+
+	/// Blinds the input, the blinded element needs to be sent to the
+	/// server.
+	pub fn blind(
+		&self,
+		input: &[u8],
+	) -> Result<(ClientState, BlindedElement), OprfError> {
+		self.blind_with(input, Scalar::random(&mut OsRng))
+	}
+
+	fn blind_with(
+		&self,
+		input: &[u8],
+		blind: Scalar,
+	) -> Result<(ClientState, BlindedElement), OprfError> {
+		let element = hash_to_group(self.mode, input);
+		if element == RistrettoPoint::identity() {
+			return Err(OprfError::InvalidInput);
+		}
+
+		let blinded = element * blind;
+		let state = ClientState {
+			input: input.to_vec(),
+			blind,
+			blinded,
+		};
+
+		Ok((state, BlindedElement { point: blinded }))
+	}
+
+	/// Verifies the proof (in the verifiable modes) and returns the
+	/// outputs in the same order as the states.
+	///
+	/// The info is only used in the POPRF mode and needs to be the same
+	/// as the one the server used.
+	pub fn finalize(
+		&self,
+		states: Vec<ClientState>,
+		evaluation: &Evaluation,
+		info: &[u8],
+	) -> Result<Vec<Output>, OprfError> {
+		if states.len() != evaluation.elements.len() {
+			return Err(OprfError::BatchSizeMismatch);
+		}
+
+		let blinded: Vec<_> = states.iter().map(|s| s.blinded).collect();
+		let evaluated: Vec<_> =
+			evaluation.elements.iter().map(|e| e.point).collect();
+
+		if self.mode.is_verifiable() {
+			let (public_key, proof) =
+				match (&self.public_key, &evaluation.proof) {
+					(Some(k), Some(p)) => (k, p),
+					_ => return Err(OprfError::MissingPublicKeyOrProof),
+				};
+
+			let valid = match self.mode {
+				Mode::Poprf => {
+					let tweaked = RistrettoPoint::mul_base(&info_scalar(info))
+						+ public_key.point;
+					if tweaked == RistrettoPoint::identity() {
+						return Err(OprfError::DeriveKeyPair);
+					}
+
+					verify_proof(
+						self.mode, &tweaked, &evaluated, &blinded, proof,
+					)
+				}
+				_ => verify_proof(
+					self.mode,
+					&public_key.point,
+					&blinded,
+					&evaluated,
+					proof,
+				),
+			};
+
+			if !valid {
+				return Err(OprfError::InvalidProof);
+			}
+		}
+
+		let info = (self.mode == Mode::Poprf).then_some(info);
+		let outputs = states
+			.iter()
+			.zip(&evaluated)
+			.map(|(state, e)| {
+				finalize_hash(&state.input, info, &(e * state.blind.invert()))
+			})
+			.collect();
+
+		Ok(outputs)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+
+	fn hex(s: &str) -> Vec<u8> {
+		(0..s.len())
+			.step_by(2)
+			.map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+			.collect()
+	}
+
+	fn scalar(s: &str) -> Scalar {
+		read_scalar(&hex(s)).unwrap()
+	}
+
+	const SEED: [u8; 32] = [0xa3; 32];
+	const KEY_INFO: &[u8] = b"test key";
+	const BLIND: &str =
+		"64d37aed22a27f5191de1c1d69fadb899d8862b58eb4220029e036ec4c1f6706";
+	const PROOF_RANDOM: &str =
+		"222a5e897cf59db8145db8d16e597e8facb80ae7d4e26d9881aa6f61d645fc0e";
+
+	struct Vector {
+		input: &'static str,
+		blinded: &'static str,
+		evaluated: &'static str,
+		proof: Option<&'static str>,
+		output: &'static str,
+	}
+
+	fn check(
+		mode: Mode,
+		sk: &str,
+		pk: Option<&str>,
+		info: &[u8],
+		vector: Vector,
+	) {
+		let server = Server::derive(mode, &SEED, KEY_INFO).unwrap();
+		assert_eq!(server.to_bytes().to_vec(), hex(sk));
+		if let Some(pk) = pk {
+			assert_eq!(server.public_key().to_bytes().to_vec(), hex(pk));
+		}
+
+		let client = Client::new(mode, Some(server.public_key().clone()));
+		let input = hex(vector.input);
+		let (state, blinded) =
+			client.blind_with(&input, scalar(BLIND)).unwrap();
+		assert_eq!(blinded.to_bytes().to_vec(), hex(vector.blinded));
+
+		let evaluation = server
+			.blind_evaluate_with(&[blinded], info, scalar(PROOF_RANDOM))
+			.unwrap();
+		assert_eq!(
+			evaluation.elements[0].to_bytes().to_vec(),
+			hex(vector.evaluated)
+		);
+		assert_eq!(
+			evaluation.proof.as_ref().map(|p| p.to_bytes().to_vec()),
+			vector.proof.map(hex)
+		);
+
+		let outputs = client.finalize(vec![state], &evaluation, info).unwrap();
+		assert_eq!(outputs[0].to_bytes().to_vec(), hex(vector.output));
+		assert_eq!(server.evaluate(&input, info).unwrap(), outputs[0]);
+	}
+
+	// RFC 9497 A.1.1
+	#[test]
+	fn oprf_vectors() {
+		let sk =
+			"5ebcea5ee37023ccb9fc2d2019f9d7737be85591ae8652ffa9ef0f4d37063b0e";
+
+		check(
+			Mode::Oprf,
+			sk,
+			None,
+			b"",
+			Vector {
+				input: "00",
+				blinded: "609a0ae68c15a3cf6903766461307e5c8bb2f95e7e6550e1ffa2dc99e412803c",
+				evaluated: "7ec6578ae5120958eb2db1745758ff379e77cb64fe77b0b2d8cc917ea0869c7e",
+				proof: None,
+				output: "527759c3d9366f277d8c6020418d96bb393ba2afb20ff90df23fb7708264e2f3ab9135e3bd69955851de4b1f9fe8a0973396719b7912ba9ee8aa7d0b5e24bcf6",
+			},
+		);
+
+		check(
+			Mode::Oprf,
+			sk,
+			None,
+			b"",
+			Vector {
+				input: "5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a5a",
+				blinded: "da27ef466870f5f15296299850aa088629945a17d1f5b7f5ff043f76b3c06418",
+				evaluated: "b4cbf5a4f1eeda5a63ce7b77c7d23f461db3fcab0dd28e4e17cecb5c90d02c25",
+				proof: None,
+				output: "f4a74c9c592497375e796aa837e907b1a045d34306a749db9f34221f7e750cb4f2a6413a6bf6fa5e19ba6348eb673934a722a7ede2e7621306d18951e7cf2c73",
+			},
+		);
+	}
+
+	// RFC 9497 A.1.2
+	#[test]
+	fn voprf_vectors() {
+		check(
+			Mode::Voprf,
+			"e6f73f344b79b379f1a0dd37e07ff62e38d9f71345ce62ae3a9bc60b04ccd909",
+			Some("c803e2cc6b05fc15064549b5920659ca4a77b2cca6f04f6b357009335476ad4e"),
+			b"",
+			Vector {
+				input: "00",
+				blinded: "863f330cc1a1259ed5a5998a23acfd37fb4351a793a5b3c090b642ddc439b945",
+				evaluated: "aa8fa048764d5623868679402ff6108d2521884fa138cd7f9c7669a9a014267e",
+				proof: Some("ddef93772692e535d1a53903db24367355cc2cc78de93b3be5a8ffcc6985dd066d4346421d17bf5117a2a1ff0fcb2a759f58a539dfbe857a40bce4cf49ec600d"),
+				output: "b58cfbe118e0cb94d79b5fd6a6dafb98764dff49c14e1770b566e42402da1a7da4d8527693914139caee5bd03903af43a491351d23b430948dd50cde10d32b3c",
+			},
+		);
+	}
+
+	// RFC 9497 A.1.3
+	#[test]
+	fn poprf_vectors() {
+		check(
+			Mode::Poprf,
+			"145c79c108538421ac164ecbe131942136d5570b16d8bf41a24d4337da981e07",
+			Some("c647bef38497bc6ec077c22af65b696efa43bff3b4a1975a3e8e0a1c5a79d631"),
+			b"test info",
+			Vector {
+				input: "00",
+				blinded: "c8713aa89241d6989ac142f22dba30596db635c772cbf25021fdd8f3d461f715",
+				evaluated: "1a4b860d808ff19624731e67b5eff20ceb2df3c3c03b906f5693e2078450d874",
+				proof: Some("41ad1a291aa02c80b0915fbfbb0c0afa15a57e2970067a602ddb9e8fd6b7100de32e1ecff943a36f0b10e3dae6bd266cdeb8adf825d86ef27dbc6c0e30c52206"),
+				output: "ca688351e88afb1d841fde4401c79efebb2eb75e7998fa9737bd5a82a152406d38bd29f680504e54fd4587eddcf2f37a2617ac2fbd2993f7bdf45442ace7d221",
+			},
+		);
+	}
+
+	#[test]
+	fn batch_and_invalid_proof() {
+		let server = Server::new(Mode::Voprf);
+		let client =
+			Client::new(Mode::Voprf, Some(server.public_key().clone()));
+
+		let (s1, b1) = client.blind(b"a").unwrap();
+		let (s2, b2) = client.blind(b"b").unwrap();
+		let evaluation = server.blind_evaluate(&[b1, b2], b"").unwrap();
+
+		let outputs = client.finalize(vec![s1, s2], &evaluation, b"").unwrap();
+		assert_eq!(outputs[0], server.evaluate(b"a", b"").unwrap());
+		assert_eq!(outputs[1], server.evaluate(b"b", b"").unwrap());
+
+		// another key is detected
+		let other = Server::new(Mode::Voprf);
+		let (state, blinded) = client.blind(b"a").unwrap();
+		let evaluation = other.blind_evaluate(&[blinded], b"").unwrap();
+		assert_eq!(
+			client.finalize(vec![state], &evaluation, b"").unwrap_err(),
+			OprfError::InvalidProof
+		);
+	}
+
+	#[test]
+	fn poprf_info() {
+		let server = Server::new(Mode::Poprf);
+		let client =
+			Client::new(Mode::Poprf, Some(server.public_key().clone()));
+
+		let (state, blinded) = client.blind(b"a").unwrap();
+		let evaluation = server.blind_evaluate(&[blinded], b"2024").unwrap();
+		assert_eq!(
+			client
+				.finalize(vec![state], &evaluation, b"2023")
+				.unwrap_err(),
+			OprfError::InvalidProof
+		);
+
+		let (state, blinded) = client.blind(b"a").unwrap();
+		let evaluation = server.blind_evaluate(&[blinded], b"2024").unwrap();
+		let output =
+			client.finalize(vec![state], &evaluation, b"2024").unwrap();
+		assert_eq!(output[0], server.evaluate(b"a", b"2024").unwrap());
+		assert_ne!(output[0], server.evaluate(b"a", b"2023").unwrap());
+	}
+}
@@ -0,0 +1,279 @@
+//! CPace, a balanced PAKE.
+//!
+//! Both sides derive a generator from the password and the context and
+//! then perform a diffie hellman exchange with it. Only someone who knows
+//! the password can compute the same shared secret. An attacker can only
+//! guess one password per exchange.
+//!
+//! Three messages are needed, the last two confirm the shared secret.
+//!
+//! ## Example
+//! ```
+//! use fire_crypto::pake::cpace::{Initiator, Responder};
+//!
+//! // the context should contain a session id and the names of both
+//! // parties if they are known
+//! let context = b"pairing 2c4f";
+//!
+//! let (initiator, msg1) = Initiator::new(b"492013", context);
+//! let (responder, msg2) =
+//!     Responder::new(b"492013", context, &msg1).unwrap();
+//! let (initiator_secret, msg3) = initiator.finish(&msg2).unwrap();
+//! let responder_secret = responder.finish(&msg3).unwrap();
+//!
+//! assert_eq!(initiator_secret, responder_secret);
+//! ```
+
+use super::{hash, hash_to_point, point_bytes, read_point, PakeError, TAG_LEN};
+use crate::cipher::SharedSecret;
+
+use std::convert::TryInto;
+use std::fmt;
+
+use rand::rngs::OsRng;
+
+use curve25519_dalek::{RistrettoPoint, Scalar};
+
+use zeroize::Zeroize;
+
+const GENERATOR_KEY: &[u8] = b"fire-crypto cpace generator";
+const SECRET_KEY: &[u8] = b"fire-crypto cpace secret";
+
+/// The length of the first message.
+pub const MESSAGE1_LEN: usize = 32;
+/// The length of the second message.
+pub const MESSAGE2_LEN: usize = 32 + TAG_LEN;
+/// The length of the third message.
+pub const MESSAGE3_LEN: usize = TAG_LEN;
+
+fn generator(password: &[u8], context: &[u8]) -> RistrettoPoint {
+	hash_to_point(GENERATOR_KEY, &[password, context])
+}
+
+/// The derived secret and the confirmation tags.
+struct Secrets {
+	secret: [u8; 32],
+	initiator_tag: [u8; TAG_LEN],
+	responder_tag: [u8; TAG_LEN],
+}
+
+impl Secrets {
+	fn derive(
+		shared: &RistrettoPoint,
+		context: &[u8],
+		initiator: &[u8; 32],
+		responder: &[u8; 32],
+	) -> Self {
+		let mut isk = hash(
+			SECRET_KEY,
+			&[context, &point_bytes(shared), initiator, responder],
+		);
+
+		let secret = hash(&isk, &[b"secret"])[..32].try_into().unwrap();
+		let initiator_tag =
+			hash(&isk, &[b"initiator"])[..TAG_LEN].try_into().unwrap();
+		let responder_tag =
+			hash(&isk, &[b"responder"])[..TAG_LEN].try_into().unwrap();
+		isk.zeroize();
+
+		Self {
+			secret,
+			initiator_tag,
+			responder_tag,
+		}
+	}
+
+	fn into_shared_secret(mut self) -> SharedSecret {
+		SharedSecret::from_bytes(std::mem::take(&mut self.secret))
+	}
+}
+
+impl Drop for Secrets {
+	fn drop(&mut self) {
+		self.secret.zeroize();
+	}
+}
+
+/// The side which starts the exchange.
+pub struct Initiator {
+	secret: Scalar,
+	public: [u8; 32],
+	context: Vec<u8>,
+}
+
+impl Initiator {
+	/// Returns the initiator and the first message which needs to be sent
+	/// to the responder.
+	pub fn new(password: &[u8], context: &[u8]) -> (Self, Vec<u8>) {
+		let secret = Scalar::random(&mut OsRng);
+		let public = point_bytes(&(generator(password, context) * secret));
+
+		let me = Self {
+			secret,
+			public,
+			context: context.to_vec(),
+		};
+
+		(me, public.to_vec())
+	}
+
+	/// Verifies the second message and returns the shared secret and the
+	/// third message which needs to be sent to the responder.
+	pub fn finish(
+		self,
+		msg: &[u8],
+	) -> Result<(SharedSecret, Vec<u8>), PakeError> {
+		if msg.len() != MESSAGE2_LEN {
+			return Err(PakeError::InvalidLength);
+		}
+
+		let responder = read_point(&msg[..32])?;
+		let secrets = Secrets::derive(
+			&(responder * self.secret),
+			&self.context,
+			&self.public,
+			msg[..32].try_into().unwrap(),
+		);
+
+		if !crate::constant_time_eq(&secrets.responder_tag, &msg[32..]) {
+			return Err(PakeError::PasswordMismatch);
+		}
+
+		let msg = secrets.initiator_tag.to_vec();
+		Ok((secrets.into_shared_secret(), msg))
+	}
+}
+
+impl fmt::Debug for Initiator {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("Initiator")
+			.field("public", &self.public)
+			.finish()
+	}
+}
+
+impl Drop for Initiator {
+	fn drop(&mut self) {
+		self.secret.zeroize();
+	}
+}
+
+/// The side which responds to an exchange.
+pub struct Responder {
+	secrets: Secrets,
+}
+
+impl Responder {
+	/// Responds to the first message returning the responder and the
+	/// second message which needs to be sent to the initiator.
+	pub fn new(
+		password: &[u8],
+		context: &[u8],
+		msg: &[u8],
+	) -> Result<(Self, Vec<u8>), PakeError> {
+		if msg.len() != MESSAGE1_LEN {
+			return Err(PakeError::InvalidLength);
+		}
+
+		let initiator = read_point(msg)?;
+
+		let mut secret = Scalar::random(&mut OsRng);
+		let public = point_bytes(&(generator(password, context) * secret));
+		let secrets = Secrets::derive(
+			&(initiator * secret),
+			context,
+			msg.try_into().unwrap(),
+			&public,
+		);
+		secret.zeroize();
+
+		let mut msg = public.to_vec();
+		msg.extend_from_slice(&secrets.responder_tag);
+
+		Ok((Self { secrets }, msg))
+	}
+
+	/// Verifies the third message and returns the shared secret.
+	pub fn finish(self, msg: &[u8]) -> Result<SharedSecret, PakeError> {
+		if msg.len() != MESSAGE3_LEN {
+			return Err(PakeError::InvalidLength);
+		}
+
+		if !crate::constant_time_eq(&self.secrets.initiator_tag, msg) {
+			return Err(PakeError::PasswordMismatch);
+		}
+
+		Ok(self.secrets.into_shared_secret())
+	}
+}
+
+impl fmt::Debug for Responder {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str("Responder")
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+
+	#[test]
+	fn same_password() {
+		let (initiator, msg1) = Initiator::new(b"1234", b"ctx");
+		assert_eq!(msg1.len(), MESSAGE1_LEN);
+		let (responder, msg2) = Responder::new(b"1234", b"ctx", &msg1).unwrap();
+		assert_eq!(msg2.len(), MESSAGE2_LEN);
+		let (a, msg3) = initiator.finish(&msg2).unwrap();
+		assert_eq!(msg3.len(), MESSAGE3_LEN);
+		let b = responder.finish(&msg3).unwrap();
+		assert_eq!(a, b);
+
+		// another exchange leads to another secret
+		let (initiator, msg1) = Initiator::new(b"1234", b"ctx");
+		let (_, msg2) = Responder::new(b"1234", b"ctx", &msg1).unwrap();
+		let (a2, _) = initiator.finish(&msg2).unwrap();
+		assert_ne!(a, a2);
+	}
+
+	#[test]
+	fn password_mismatch() {
+		let (initiator, msg1) = Initiator::new(b"1234", b"ctx");
+		let (_, msg2) = Responder::new(b"1235", b"ctx", &msg1).unwrap();
+		assert_eq!(
+			initiator.finish(&msg2).unwrap_err(),
+			PakeError::PasswordMismatch
+		);
+
+		let (initiator, msg1) = Initiator::new(b"1234", b"ctx");
+		let (_, msg2) = Responder::new(b"1234", b"other", &msg1).unwrap();
+		assert_eq!(
+			initiator.finish(&msg2).unwrap_err(),
+			PakeError::PasswordMismatch
+		);
+
+		// the responder detects a wrong third message
+		let (_, msg1) = Initiator::new(b"1234", b"ctx");
+		let (responder, _) = Responder::new(b"1234", b"ctx", &msg1).unwrap();
+		assert_eq!(
+			responder.finish(&[0; TAG_LEN]).unwrap_err(),
+			PakeError::PasswordMismatch
+		);
+	}
+
+	#[test]
+	fn invalid_points() {
+		assert_eq!(
+			Responder::new(b"1234", b"ctx", &[0; 32]).unwrap_err(),
+			PakeError::InvalidPoint
+		);
+		assert_eq!(
+			Responder::new(b"1234", b"ctx", &[0xff; 32]).unwrap_err(),
+			PakeError::InvalidPoint
+		);
+		assert_eq!(
+			Responder::new(b"1234", b"ctx", &[0; 31]).unwrap_err(),
+			PakeError::InvalidLength
+		);
+	}
+}
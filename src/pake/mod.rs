@@ -0,0 +1,82 @@
+//! Password authenticated key exchanges.
+//!
+//! - [`cpace`] is a balanced PAKE, both sides know the password. Useful to
+//!   pair two devices with a short code.
+//! - [`opaque`] is an augmented PAKE, the server only stores a password
+//!   file from which the password can't be recovered without a dictionary
+//!   attack. Useful for logins without ever sending the password to the
+//!   server.
+//!
+//! Both protocols work on the Ristretto group and use the hash module
+//! (Blake2b) instead of the hash functions from the specifications, they
+//! are therefore not compatible with other implementations.
+//!
+//! Both protocols include key confirmation and return
+//! [`PakeError::PasswordMismatch`] if the passwords did not match.
+
+pub mod cpace;
+pub mod opaque;
+
+use crate::hash::Hasher;
+use crate::ristretto;
+
+use std::error::Error;
+use std::fmt;
+
+use curve25519_dalek::{RistrettoPoint, Scalar};
+
+/// The length of a mac or a nonce used in the protocols.
+const TAG_LEN: usize = 32;
+
+/// Get's returned if a key exchange failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PakeError {
+	/// The message has the wrong length.
+	InvalidLength,
+	/// The message contains an invalid point or the identity.
+	InvalidPoint,
+	/// The other side used another password (or the message was
+	/// modified).
+	PasswordMismatch,
+	/// The server could not prove that it knows the password file.
+	InvalidServerMac,
+}
+
+impl fmt::Display for PakeError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		fmt::Debug::fmt(self, f)
+	}
+}
+
+impl Error for PakeError {}
+
+/// Hashes every part prefixed with its length, so that parts can't be
+/// moved from one to another.
+fn hash(key: &[u8], parts: &[&[u8]]) -> [u8; 64] {
+	let mut hasher = Hasher::new_keyed(key);
+	for part in parts {
+		hasher.update((part.len() as u32).to_be_bytes());
+		hasher.update(part);
+	}
+
+	hasher.finalize().to_bytes()
+}
+
+fn hash_to_point(key: &[u8], parts: &[&[u8]]) -> RistrettoPoint {
+	RistrettoPoint::from_uniform_bytes(&hash(key, parts))
+}
+
+fn hash_to_scalar(key: &[u8], parts: &[&[u8]]) -> Scalar {
+	Scalar::from_bytes_mod_order_wide(&hash(key, parts))
+}
+
+/// Reads a point rejecting the identity.
+fn read_point(bytes: &[u8]) -> Result<RistrettoPoint, PakeError> {
+	ristretto::read_non_identity_point(bytes)
+		.map_err(|_| PakeError::InvalidPoint)
+}
+
+fn point_bytes(point: &RistrettoPoint) -> [u8; 32] {
+	point.compress().to_bytes()
+}
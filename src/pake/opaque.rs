@@ -0,0 +1,756 @@
+//! OPAQUE, an augmented PAKE.
+//!
+//! The server never learns the password, not even during registration.
+//! It only stores a [`PasswordFile`] per user, together with the
+//! [`ServerSetup`] which is the same for all users.
+//!
+//! The password is hardened with an oblivious PRF evaluated by the server,
+//! so an attacker which steals the password files also needs the server
+//! setup to start a dictionary attack. No additional key stretching is
+//! applied.
+//!
+//! The login uses a triple diffie hellman key exchange and takes three
+//! messages, if the user is unknown the server responds with a fake
+//! password file so that the client can't learn which users exist.
+//!
+//! ## Example
+//! ```
+//! use fire_crypto::pake::opaque::{
+//!     ClientLogin, ClientRegistration, ServerSetup,
+//! };
+//!
+//! let server = ServerSetup::new();
+//!
+//! // registration
+//! let (registration, request) = ClientRegistration::new(b"hunter2");
+//! let response = server.registration_response(b"alice", &request).unwrap();
+//! let password_file = registration.finish(&response).unwrap();
+//! // the password file gets sent to the server and stored
+//!
+//! // login
+//! let (client, ke1) = ClientLogin::new(b"alice", b"hunter2");
+//! let (server_login, ke2) =
+//!     server.login(b"alice", Some(&password_file), &ke1).unwrap();
+//! let (client_secret, ke3) = client.finish(&ke2).unwrap();
+//! let server_secret = server_login.finish(&ke3).unwrap();
+//!
+//! assert_eq!(client_secret, server_secret);
+//! ```
+
+use super::{
+	hash, hash_to_point, hash_to_scalar, point_bytes, read_point, PakeError,
+	TAG_LEN,
+};
+use crate::cipher::SharedSecret;
+use crate::error::TryFromError;
+
+use std::convert::{TryFrom, TryInto};
+use std::fmt;
+
+use rand::rngs::OsRng;
+
+use curve25519_dalek::{RistrettoPoint, Scalar};
+
+use zeroize::Zeroize;
+
+const OPRF_INPUT_KEY: &[u8] = b"fire-crypto opaque oprf input";
+const OPRF_KEY: &[u8] = b"fire-crypto opaque oprf key";
+const RANDOMIZED_PASSWORD_KEY: &[u8] = b"fire-crypto opaque password";
+const FAKE_KEY: &[u8] = b"fire-crypto opaque fake";
+const KEY_EXCHANGE_KEY: &[u8] = b"fire-crypto opaque key exchange";
+
+/// The length of a registration request.
+pub const REGISTRATION_REQUEST_LEN: usize = 32;
+/// The length of a registration response.
+pub const REGISTRATION_RESPONSE_LEN: usize = 64;
+/// The length of the first login message.
+pub const KE1_LEN: usize = 32 + TAG_LEN + 32;
+/// The length of the second login message.
+pub const KE2_LEN: usize = 32 + TAG_LEN + MASKED_LEN + TAG_LEN + 32 + TAG_LEN;
+/// The length of the third login message.
+pub const KE3_LEN: usize = TAG_LEN;
+
+/// server public key | envelope nonce | envelope tag
+const MASKED_LEN: usize = 32 + 2 * TAG_LEN;
+
+fn random_tag() -> [u8; TAG_LEN] {
+	let mut tag = [0u8; TAG_LEN];
+	crate::fill_random(&mut tag);
+	tag
+}
+
+fn tag(key: &[u8], parts: &[&[u8]]) -> [u8; TAG_LEN] {
+	hash(key, parts)[..TAG_LEN].try_into().unwrap()
+}
+
+/// Blinds the password for the oblivious PRF.
+fn blind(password: &[u8]) -> (Scalar, [u8; 32]) {
+	let blind = Scalar::random(&mut OsRng);
+	let point = hash_to_point(OPRF_INPUT_KEY, &[password]);
+
+	(blind, point_bytes(&(point * blind)))
+}
+
+/// Keys derived from the password and the oprf output.
+struct ClientKeys {
+	randomized_password: [u8; 64],
+}
+
+impl ClientKeys {
+	fn new(
+		password: &[u8],
+		blind: &Scalar,
+		evaluated: &[u8],
+	) -> Result<Self, PakeError> {
+		let evaluated = read_point(evaluated)?;
+		let output = point_bytes(&(evaluated * blind.invert()));
+
+		Ok(Self {
+			randomized_password: hash(
+				RANDOMIZED_PASSWORD_KEY,
+				&[password, &output],
+			),
+		})
+	}
+
+	fn masking_key(&self) -> [u8; TAG_LEN] {
+		tag(&self.randomized_password, &[b"masking key"])
+	}
+
+	fn auth_key(&self, nonce: &[u8]) -> [u8; 64] {
+		hash(&self.randomized_password, &[b"auth key", nonce])
+	}
+
+	fn private_key(&self, nonce: &[u8]) -> Scalar {
+		hash_to_scalar(&self.randomized_password, &[b"private key", nonce])
+	}
+}
+
+impl Drop for ClientKeys {
+	fn drop(&mut self) {
+		self.randomized_password.zeroize();
+	}
+}
+
+fn envelope_tag(
+	auth_key: &[u8],
+	server_public: &[u8],
+	client_public: &[u8],
+) -> [u8; TAG_LEN] {
+	tag(auth_key, &[b"envelope", server_public, client_public])
+}
+
+/// The pad to mask the server public key and the envelope.
+fn mask(masking_key: &[u8], nonce: &[u8], data: &mut [u8]) {
+	let mut pad = hash(masking_key, &[b"pad", nonce]).to_vec();
+	pad.extend_from_slice(&hash(masking_key, &[b"pad 2", nonce]));
+	crate::xor(data, &pad[..data.len()]);
+}
+
+/// The keys of the key exchange.
+struct SessionKeys {
+	server_mac: [u8; TAG_LEN],
+	client_mac: [u8; TAG_LEN],
+	secret: [u8; 32],
+}
+
+impl SessionKeys {
+	fn derive(dhs: [RistrettoPoint; 3], preamble: &[u8]) -> Self {
+		let mut prk = hash(
+			KEY_EXCHANGE_KEY,
+			&[
+				&point_bytes(&dhs[0]),
+				&point_bytes(&dhs[1]),
+				&point_bytes(&dhs[2]),
+				preamble,
+			],
+		);
+
+		let server_mac = tag(&tag(&prk, &[b"server mac"]), &[preamble]);
+		let client_mac =
+			tag(&tag(&prk, &[b"client mac"]), &[preamble, &server_mac]);
+		let secret = tag(&prk, &[b"session key"]);
+		prk.zeroize();
+
+		Self {
+			server_mac,
+			client_mac,
+			secret,
+		}
+	}
+
+	fn into_shared_secret(mut self) -> SharedSecret {
+		SharedSecret::from_bytes(std::mem::take(&mut self.secret))
+	}
+}
+
+impl Drop for SessionKeys {
+	fn drop(&mut self) {
+		self.secret.zeroize();
+	}
+}
+
+/// The data from which the mac keys and the session key get derived.
+fn preamble(credential_id: &[u8], ke1: &[u8], ke2: &[u8]) -> Vec<u8> {
+	let mut preamble = (credential_id.len() as u32).to_be_bytes().to_vec();
+	preamble.extend_from_slice(credential_id);
+	preamble.extend_from_slice(ke1);
+	preamble.extend_from_slice(ke2);
+	preamble
+}
+
+/// The server side configuration which is shared between all users.
+///
+/// Needs to be stored securely, if it is lost all users need to register
+/// again.
+pub struct ServerSetup {
+	oprf_seed: [u8; 32],
+	secret: Scalar,
+	public: RistrettoPoint,
+}
+
+impl ServerSetup {
+	pub const LEN: usize = 64;
+
+	pub fn new() -> Self {
+		let mut oprf_seed = [0u8; 32];
+		crate::fill_random(&mut oprf_seed);
+
+		Self::from_parts(oprf_seed, Scalar::random(&mut OsRng))
+	}
+
+	fn from_parts(oprf_seed: [u8; 32], secret: Scalar) -> Self {
+		Self {
+			oprf_seed,
+			public: RistrettoPoint::mul_base(&secret),
+			secret,
+		}
+	}
+
+	/// ## Panics
+	/// if the slice is not 64 bytes long or not valid.
+	pub fn from_slice(slice: &[u8]) -> Self {
+		slice.try_into().unwrap()
+	}
+
+	pub fn to_bytes(&self) -> [u8; 64] {
+		let mut bytes = [0u8; 64];
+		bytes[..32].copy_from_slice(&self.oprf_seed);
+		bytes[32..].copy_from_slice(self.secret.as_bytes());
+		bytes
+	}
+
+	/// The public key of the server, the client verifies that the server
+	/// knows the corresponding secret key.
+	pub fn public_key(&self) -> [u8; 32] {
+		point_bytes(&self.public)
+	}
+
+	fn oprf_key(&self, credential_id: &[u8]) -> Scalar {
+		hash_to_scalar(OPRF_KEY, &[&self.oprf_seed, credential_id])
+	}
+
+	fn evaluate(
+		&self,
+		credential_id: &[u8],
+		blinded: &[u8],
+	) -> Result<[u8; 32], PakeError> {
+		let blinded = read_point(blinded)?;
+		let mut key = self.oprf_key(credential_id);
+		let evaluated = point_bytes(&(blinded * key));
+		key.zeroize();
+
+		Ok(evaluated)
+	}
+
+	/// Responds to a registration request.
+	///
+	/// The credential id identifies the user and needs to be the same
+	/// when logging in.
+	pub fn registration_response(
+		&self,
+		credential_id: &[u8],
+		request: &[u8],
+	) -> Result<Vec<u8>, PakeError> {
+		if request.len() != REGISTRATION_REQUEST_LEN {
+			return Err(PakeError::InvalidLength);
+		}
+
+		let mut response = self.evaluate(credential_id, request)?.to_vec();
+		response.extend_from_slice(&self.public_key());
+
+		Ok(response)
+	}
+
+	/// A password file which looks real but can't be opened by anyone.
+	///
+	/// It is always the same for a credential id so that it can't be
+	/// distinguished from a real one by logging in multiple times.
+	fn fake_password_file(&self, credential_id: &[u8]) -> PasswordFile {
+		let client_public = hash_to_point(
+			FAKE_KEY,
+			&[&self.oprf_seed, credential_id, b"public key"],
+		);
+		let bytes = hash(FAKE_KEY, &[&self.oprf_seed, credential_id]);
+
+		PasswordFile {
+			client_public,
+			masking_key: bytes[..32].try_into().unwrap(),
+			envelope_nonce: bytes[32..].try_into().unwrap(),
+			envelope_tag: random_tag(),
+		}
+	}
+
+	/// Responds to the first login message.
+	///
+	/// If the user does not exist, pass `None` as password file, the
+	/// client will then fail with [`PakeError::PasswordMismatch`].
+	pub fn login(
+		&self,
+		credential_id: &[u8],
+		password_file: Option<&PasswordFile>,
+		ke1: &[u8],
+	) -> Result<(ServerLogin, Vec<u8>), PakeError> {
+		if ke1.len() != KE1_LEN {
+			return Err(PakeError::InvalidLength);
+		}
+
+		let client_ephemeral = read_point(&ke1[32 + TAG_LEN..])?;
+
+		let fake;
+		let file = match password_file {
+			Some(f) => f,
+			None => {
+				fake = self.fake_password_file(credential_id);
+				&fake
+			}
+		};
+
+		let masking_nonce = random_tag();
+		let mut masked = Vec::with_capacity(MASKED_LEN);
+		masked.extend_from_slice(&self.public_key());
+		masked.extend_from_slice(&file.envelope_nonce);
+		masked.extend_from_slice(&file.envelope_tag);
+		mask(&file.masking_key, &masking_nonce, &mut masked);
+
+		let mut ephemeral = Scalar::random(&mut OsRng);
+
+		let mut ke2 = Vec::with_capacity(KE2_LEN);
+		ke2.extend_from_slice(&self.evaluate(credential_id, &ke1[..32])?);
+		ke2.extend_from_slice(&masking_nonce);
+		ke2.extend_from_slice(&masked);
+		ke2.extend_from_slice(&random_tag());
+		ke2.extend_from_slice(&point_bytes(&RistrettoPoint::mul_base(
+			&ephemeral,
+		)));
+
+		let keys = SessionKeys::derive(
+			[
+				client_ephemeral * ephemeral,
+				client_ephemeral * self.secret,
+				file.client_public * ephemeral,
+			],
+			&preamble(credential_id, ke1, &ke2),
+		);
+		ephemeral.zeroize();
+
+		ke2.extend_from_slice(&keys.server_mac);
+
+		Ok((ServerLogin { keys }, ke2))
+	}
+}
+
+impl fmt::Debug for ServerSetup {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("ServerSetup")
+			.field("public_key", &self.public_key())
+			.finish()
+	}
+}
+
+impl TryFrom<&[u8]> for ServerSetup {
+	type Error = TryFromError;
+
+	fn try_from(v: &[u8]) -> Result<Self, Self::Error> {
+		if v.len() != Self::LEN {
+			return Err(TryFromError::from_any(()));
+		}
+
+		let secret = Option::from(Scalar::from_canonical_bytes(
+			v[32..].try_into().unwrap(),
+		))
+		.ok_or(TryFromError::from_any(()))?;
+
+		Ok(Self::from_parts(v[..32].try_into().unwrap(), secret))
+	}
+}
+
+impl Drop for ServerSetup {
+	fn drop(&mut self) {
+		self.oprf_seed.zeroize();
+		self.secret.zeroize();
+	}
+}
+
+/// Stored by the server for every user.
+#[derive(Clone, PartialEq, Eq)]
+pub struct PasswordFile {
+	client_public: RistrettoPoint,
+	masking_key: [u8; TAG_LEN],
+	envelope_nonce: [u8; TAG_LEN],
+	envelope_tag: [u8; TAG_LEN],
+}
+
+impl PasswordFile {
+	pub const LEN: usize = 32 + 3 * TAG_LEN;
+
+	pub fn to_bytes(&self) -> [u8; 128] {
+		let mut bytes = [0u8; 128];
+		bytes[..32].copy_from_slice(&point_bytes(&self.client_public));
+		bytes[32..64].copy_from_slice(&self.masking_key);
+		bytes[64..96].copy_from_slice(&self.envelope_nonce);
+		bytes[96..].copy_from_slice(&self.envelope_tag);
+		bytes
+	}
+}
+
+impl fmt::Debug for PasswordFile {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str("PasswordFile")
+	}
+}
+
+impl TryFrom<&[u8]> for PasswordFile {
+	type Error = TryFromError;
+
+	fn try_from(v: &[u8]) -> Result<Self, Self::Error> {
+		if v.len() != Self::LEN {
+			return Err(TryFromError::from_any(()));
+		}
+
+		Ok(Self {
+			client_public: read_point(&v[..32])
+				.map_err(TryFromError::from_any)?,
+			masking_key: v[32..64].try_into().unwrap(),
+			envelope_nonce: v[64..96].try_into().unwrap(),
+			envelope_tag: v[96..].try_into().unwrap(),
+		})
+	}
+}
+
+/// The client side of a registration.
+pub struct ClientRegistration {
+	password: Vec<u8>,
+	blind: Scalar,
+}
+
+impl ClientRegistration {
+	/// Returns the registration and the request which needs to be sent to
+	/// the server.
+	pub fn new(password: &[u8]) -> (Self, Vec<u8>) {
+		let (blind, blinded) = blind(password);
+		let me = Self {
+			password: password.to_vec(),
+			blind,
+		};
+
+		(me, blinded.to_vec())
+	}
+
+	/// Creates the password file which needs to be sent to the server.
+	pub fn finish(self, response: &[u8]) -> Result<PasswordFile, PakeError> {
+		if response.len() != REGISTRATION_RESPONSE_LEN {
+			return Err(PakeError::InvalidLength);
+		}
+
+		let server_public = &response[32..];
+		read_point(server_public)?;
+
+		let keys =
+			ClientKeys::new(&self.password, &self.blind, &response[..32])?;
+
+		let envelope_nonce = random_tag();
+		let mut private_key = keys.private_key(&envelope_nonce);
+		let client_public = RistrettoPoint::mul_base(&private_key);
+		private_key.zeroize();
+
+		let mut auth_key = keys.auth_key(&envelope_nonce);
+		let envelope_tag = envelope_tag(
+			&auth_key,
+			server_public,
+			&point_bytes(&client_public),
+		);
+		auth_key.zeroize();
+
+		Ok(PasswordFile {
+			client_public,
+			masking_key: keys.masking_key(),
+			envelope_nonce,
+			envelope_tag,
+		})
+	}
+}
+
+impl fmt::Debug for ClientRegistration {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str("ClientRegistration")
+	}
+}
+
+impl Drop for ClientRegistration {
+	fn drop(&mut self) {
+		self.password.zeroize();
+		self.blind.zeroize();
+	}
+}
+
+/// The client side of a login.
+pub struct ClientLogin {
+	credential_id: Vec<u8>,
+	password: Vec<u8>,
+	blind: Scalar,
+	ephemeral: Scalar,
+	ke1: Vec<u8>,
+}
+
+impl ClientLogin {
+	/// Returns the login and the first message which needs to be sent to
+	/// the server.
+	pub fn new(credential_id: &[u8], password: &[u8]) -> (Self, Vec<u8>) {
+		let (blind, blinded) = blind(password);
+		let ephemeral = Scalar::random(&mut OsRng);
+
+		let mut ke1 = Vec::with_capacity(KE1_LEN);
+		ke1.extend_from_slice(&blinded);
+		ke1.extend_from_slice(&random_tag());
+		ke1.extend_from_slice(&point_bytes(&RistrettoPoint::mul_base(
+			&ephemeral,
+		)));
+
+		let me = Self {
+			credential_id: credential_id.to_vec(),
+			password: password.to_vec(),
+			blind,
+			ephemeral,
+			ke1: ke1.clone(),
+		};
+
+		(me, ke1)
+	}
+
+	/// Verifies the second message and returns the shared secret and the
+	/// third message which needs to be sent to the server.
+	///
+	/// Returns [`PakeError::PasswordMismatch`] if the password is wrong or
+	/// the user does not exist.
+	pub fn finish(
+		self,
+		ke2: &[u8],
+	) -> Result<(SharedSecret, Vec<u8>), PakeError> {
+		if ke2.len() != KE2_LEN {
+			return Err(PakeError::InvalidLength);
+		}
+
+		let keys = ClientKeys::new(&self.password, &self.blind, &ke2[..32])?;
+
+		let masking_nonce = &ke2[32..32 + TAG_LEN];
+		let mut masked = ke2[32 + TAG_LEN..][..MASKED_LEN].to_vec();
+		mask(&keys.masking_key(), masking_nonce, &mut masked);
+
+		let (server_public, envelope) = masked.split_at(32);
+		let (envelope_nonce, received_tag) = envelope.split_at(TAG_LEN);
+
+		let mut private_key = keys.private_key(envelope_nonce);
+		let client_public = RistrettoPoint::mul_base(&private_key);
+
+		let mut auth_key = keys.auth_key(envelope_nonce);
+		let expected_tag = envelope_tag(
+			&auth_key,
+			server_public,
+			&point_bytes(&client_public),
+		);
+		auth_key.zeroize();
+
+		if !crate::constant_time_eq(&expected_tag, received_tag) {
+			private_key.zeroize();
+			return Err(PakeError::PasswordMismatch);
+		}
+
+		let server_public = read_point(server_public)?;
+		let ephemeral_offset = 32 + TAG_LEN + MASKED_LEN + TAG_LEN;
+		let server_ephemeral =
+			read_point(&ke2[ephemeral_offset..ephemeral_offset + 32])?;
+
+		let session = SessionKeys::derive(
+			[
+				server_ephemeral * self.ephemeral,
+				server_public * self.ephemeral,
+				server_ephemeral * private_key,
+			],
+			&preamble(
+				&self.credential_id,
+				&self.ke1,
+				&ke2[..KE2_LEN - TAG_LEN],
+			),
+		);
+		private_key.zeroize();
+
+		if !crate::constant_time_eq(
+			&session.server_mac,
+			&ke2[KE2_LEN - TAG_LEN..],
+		) {
+			return Err(PakeError::InvalidServerMac);
+		}
+
+		let ke3 = session.client_mac.to_vec();
+		Ok((session.into_shared_secret(), ke3))
+	}
+}
+
+impl fmt::Debug for ClientLogin {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str("ClientLogin")
+	}
+}
+
+impl Drop for ClientLogin {
+	fn drop(&mut self) {
+		self.password.zeroize();
+		self.blind.zeroize();
+		self.ephemeral.zeroize();
+	}
+}
+
+/// The server side of a login.
+pub struct ServerLogin {
+	keys: SessionKeys,
+}
+
+impl ServerLogin {
+	/// Verifies the third message and returns the shared secret.
+	///
+	/// Returns [`PakeError::PasswordMismatch`] if the client used the wrong
+	/// password.
+	pub fn finish(self, ke3: &[u8]) -> Result<SharedSecret, PakeError> {
+		if ke3.len() != KE3_LEN {
+			return Err(PakeError::InvalidLength);
+		}
+
+		if !crate::constant_time_eq(&self.keys.client_mac, ke3) {
+			return Err(PakeError::PasswordMismatch);
+		}
+
+		Ok(self.keys.into_shared_secret())
+	}
+}
+
+impl fmt::Debug for ServerLogin {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str("ServerLogin")
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+
+	fn register(server: &ServerSetup, id: &[u8], pw: &[u8]) -> PasswordFile {
+		let (registration, request) = ClientRegistration::new(pw);
+		assert_eq!(request.len(), REGISTRATION_REQUEST_LEN);
+		let response = server.registration_response(id, &request).unwrap();
+		assert_eq!(response.len(), REGISTRATION_RESPONSE_LEN);
+		registration.finish(&response).unwrap()
+	}
+
+	#[test]
+	fn login() {
+		let server = ServerSetup::new();
+		let file = register(&server, b"alice", b"hunter2");
+
+		// the password file and the setup can be stored
+		let file = PasswordFile::try_from(&file.to_bytes()[..]).unwrap();
+		let server = ServerSetup::from_slice(&server.to_bytes());
+
+		let (client, ke1) = ClientLogin::new(b"alice", b"hunter2");
+		assert_eq!(ke1.len(), KE1_LEN);
+		let (server_login, ke2) =
+			server.login(b"alice", Some(&file), &ke1).unwrap();
+		assert_eq!(ke2.len(), KE2_LEN);
+		let (a, ke3) = client.finish(&ke2).unwrap();
+		assert_eq!(ke3.len(), KE3_LEN);
+		let b = server_login.finish(&ke3).unwrap();
+		assert_eq!(a, b);
+	}
+
+	#[test]
+	fn wrong_password() {
+		let server = ServerSetup::new();
+		let file = register(&server, b"alice", b"hunter2");
+
+		let (client, ke1) = ClientLogin::new(b"alice", b"hunter3");
+		let (server_login, ke2) =
+			server.login(b"alice", Some(&file), &ke1).unwrap();
+		assert_eq!(
+			client.finish(&ke2).unwrap_err(),
+			PakeError::PasswordMismatch
+		);
+
+		// the server detects it as well
+		assert_eq!(
+			server_login.finish(&[0; KE3_LEN]).unwrap_err(),
+			PakeError::PasswordMismatch
+		);
+
+		// the password file is bound to the credential id
+		let (client, ke1) = ClientLogin::new(b"bob", b"hunter2");
+		let (_, ke2) = server.login(b"bob", Some(&file), &ke1).unwrap();
+		assert_eq!(
+			client.finish(&ke2).unwrap_err(),
+			PakeError::PasswordMismatch
+		);
+	}
+
+	#[test]
+	fn unknown_user() {
+		let server = ServerSetup::new();
+
+		let (client, ke1) = ClientLogin::new(b"mallory", b"hunter2");
+		let (_, ke2) = server.login(b"mallory", None, &ke1).unwrap();
+		assert_eq!(
+			client.finish(&ke2).unwrap_err(),
+			PakeError::PasswordMismatch
+		);
+
+		// the fake file stays the same
+		assert_eq!(
+			server.fake_password_file(b"mallory").masking_key,
+			server.fake_password_file(b"mallory").masking_key
+		);
+	}
+
+	#[test]
+	fn impersonated_server() {
+		let server = ServerSetup::new();
+		let file = register(&server, b"alice", b"hunter2");
+
+		// someone steals the password file but not the server setup
+		let other = ServerSetup::new();
+		let (client, ke1) = ClientLogin::new(b"alice", b"hunter2");
+		let (_, ke2) = other.login(b"alice", Some(&file), &ke1).unwrap();
+		// the oprf key is different so the password file can't be opened
+		assert_eq!(
+			client.finish(&ke2).unwrap_err(),
+			PakeError::PasswordMismatch
+		);
+
+		// a modified server mac
+		let (client, ke1) = ClientLogin::new(b"alice", b"hunter2");
+		let (_, mut ke2) = server.login(b"alice", Some(&file), &ke1).unwrap();
+		ke2[KE2_LEN - 1] ^= 1;
+		assert_eq!(
+			client.finish(&ke2).unwrap_err(),
+			PakeError::InvalidServerMac
+		);
+	}
+}
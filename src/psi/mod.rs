@@ -0,0 +1,313 @@
+//! Private set intersection, based on diffie hellman.
+//!
+//! A client learns which of its items the server knows, without sending
+//! the items in cleartext. The server learns how many items the client
+//! has, but not which ones.
+//!
+//! Items are [`Hash`]es of identifiers (for example phone numbers) which
+//! get mapped to Ristretto points. The client blinds its points with a
+//! secret scalar `a` and the server blinds them again with its secret `b`.
+//! The server also sends its own items blinded with `b` in a random order,
+//! the client blinds those with `a` and compares.
+//!
+//! ## Note
+//! Identifiers with little entropy (like phone numbers) can still be
+//! enumerated by a client which sends many requests, this should be
+//! prevented with rate limiting.
+//!
+//! ## Example
+//! ```
+//! use fire_crypto::hash::hash;
+//! use fire_crypto::psi::{Client, Server};
+//!
+//! let registered = vec![hash("+41 79 000 00 01"), hash("+41 79 000 00 02")];
+//! let contacts = vec![hash("+41 79 000 00 02"), hash("+41 79 000 00 03")];
+//!
+//! let (client, request) = Client::new(contacts);
+//! let response = Server::new().respond(&registered, &request);
+//! let intersection = client.intersect(&response).unwrap();
+//!
+//! assert_eq!(intersection, vec![hash("+41 79 000 00 02")]);
+//! ```
+
+use crate::error::TryFromError;
+use crate::hash::{Hash, Hasher};
+use crate::ristretto::read_point;
+
+use std::collections::HashSet;
+use std::convert::{TryFrom, TryInto};
+use std::error::Error;
+use std::fmt;
+
+use rand::rngs::OsRng;
+use rand::seq::SliceRandom;
+
+use curve25519_dalek::{RistrettoPoint, Scalar};
+
+use zeroize::Zeroize;
+
+const HASH_TO_POINT_KEY: &[u8] = b"fire-crypto psi";
+
+fn hash_to_point(item: &Hash) -> RistrettoPoint {
+	let bytes = Hasher::hash_keyed(HASH_TO_POINT_KEY, item).to_bytes();
+	RistrettoPoint::from_uniform_bytes(&bytes)
+}
+
+fn read_u32(bytes: &[u8]) -> Result<usize, TryFromError> {
+	bytes
+		.get(..4)
+		.map(|b| u32::from_be_bytes(b.try_into().unwrap()) as usize)
+		.ok_or(TryFromError::from_any(()))
+}
+
+fn read_points(bytes: &[u8]) -> Result<Vec<RistrettoPoint>, TryFromError> {
+	if bytes.len() % 32 != 0 {
+		return Err(TryFromError::from_any(()));
+	}
+
+	bytes.chunks_exact(32).map(read_point).collect()
+}
+
+fn write_points(bytes: &mut Vec<u8>, points: &[RistrettoPoint]) {
+	for point in points {
+		bytes.extend_from_slice(point.compress().as_bytes());
+	}
+}
+
+/// Get's returned if the response does not match the request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResponseMismatch;
+
+impl fmt::Display for ResponseMismatch {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		fmt::Debug::fmt(self, f)
+	}
+}
+
+impl Error for ResponseMismatch {}
+
+/// The blinded items of the client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+	blinded: Vec<RistrettoPoint>,
+}
+
+impl Request {
+	pub fn len(&self) -> usize {
+		self.blinded.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.blinded.is_empty()
+	}
+
+	pub fn to_bytes(&self) -> Vec<u8> {
+		let mut bytes = Vec::with_capacity(self.blinded.len() * 32);
+		write_points(&mut bytes, &self.blinded);
+		bytes
+	}
+}
+
+impl TryFrom<&[u8]> for Request {
+	type Error = TryFromError;
+
+	fn try_from(v: &[u8]) -> Result<Self, Self::Error> {
+		Ok(Self {
+			blinded: read_points(v)?,
+		})
+	}
+}
+
+/// The items of the client blinded twice and the shuffled items of the
+/// server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+	/// In the same order as the request.
+	blinded: Vec<RistrettoPoint>,
+	/// In a random order.
+	items: Vec<RistrettoPoint>,
+}
+
+impl Response {
+	pub fn to_bytes(&self) -> Vec<u8> {
+		let len = 4 + (self.blinded.len() + self.items.len()) * 32;
+		let mut bytes = Vec::with_capacity(len);
+		bytes.extend_from_slice(&(self.blinded.len() as u32).to_be_bytes());
+		write_points(&mut bytes, &self.blinded);
+		write_points(&mut bytes, &self.items);
+		bytes
+	}
+}
+
+impl TryFrom<&[u8]> for Response {
+	type Error = TryFromError;
+
+	fn try_from(v: &[u8]) -> Result<Self, Self::Error> {
+		let blinded_len = read_u32(v)?
+			.checked_mul(32)
+			.filter(|l| *l <= v.len() - 4)
+			.ok_or(TryFromError::from_any(()))?;
+		let (blinded, items) = v[4..].split_at(blinded_len);
+
+		Ok(Self {
+			blinded: read_points(blinded)?,
+			items: read_points(items)?,
+		})
+	}
+}
+
+/// The side which wants to know which of its items the server knows.
+pub struct Client {
+	secret: Scalar,
+	items: Vec<Hash>,
+}
+
+impl Client {
+	/// Returns the client and the request which needs to be sent to the
+	/// server.
+	pub fn new(items: Vec<Hash>) -> (Self, Request) {
+		let secret = Scalar::random(&mut OsRng);
+		let blinded = items.iter().map(|i| hash_to_point(i) * secret).collect();
+
+		(Self { secret, items }, Request { blinded })
+	}
+
+	/// Returns the items which the server also has, in the original
+	/// order.
+	pub fn intersect(
+		&self,
+		response: &Response,
+	) -> Result<Vec<Hash>, ResponseMismatch> {
+		if response.blinded.len() != self.items.len() {
+			return Err(ResponseMismatch);
+		}
+
+		let server_items: HashSet<_> = response
+			.items
+			.iter()
+			.map(|p| (p * self.secret).compress().to_bytes())
+			.collect();
+
+		let intersection = self
+			.items
+			.iter()
+			.zip(&response.blinded)
+			.filter(|(_, p)| server_items.contains(p.compress().as_bytes()))
+			.map(|(item, _)| item.clone())
+			.collect();
+
+		Ok(intersection)
+	}
+}
+
+impl fmt::Debug for Client {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("Client")
+			.field("items", &self.items.len())
+			.finish()
+	}
+}
+
+impl Drop for Client {
+	fn drop(&mut self) {
+		self.secret.zeroize();
+	}
+}
+
+/// The side which holds the set of known items.
+///
+/// A new server should be created for every request, if the same secret
+/// is used for multiple requests a client can compare the responses.
+pub struct Server {
+	secret: Scalar,
+}
+
+impl Server {
+	pub fn new() -> Self {
+		Self {
+			secret: Scalar::random(&mut OsRng),
+		}
+	}
+
+	/// Blinds the request and the known items.
+	pub fn respond(&self, items: &[Hash], request: &Request) -> Response {
+		let blinded = request.blinded.iter().map(|p| p * self.secret).collect();
+
+		let mut items: Vec<_> = items
+			.iter()
+			.map(|i| hash_to_point(i) * self.secret)
+			.collect();
+		items.shuffle(&mut OsRng);
+
+		Response { blinded, items }
+	}
+}
+
+impl fmt::Debug for Server {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str("Server")
+	}
+}
+
+impl Drop for Server {
+	fn drop(&mut self) {
+		self.secret.zeroize();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+	use crate::hash::hash;
+
+	fn items(range: std::ops::Range<u32>) -> Vec<Hash> {
+		range.map(|i| hash(i.to_be_bytes())).collect()
+	}
+
+	#[test]
+	fn intersection() {
+		let server_items = items(0..100);
+		let client_items = items(90..110);
+
+		let (client, request) = Client::new(client_items.clone());
+		let request = Request::try_from(request.to_bytes().as_slice()).unwrap();
+		assert_eq!(request.len(), 20);
+
+		let response = Server::new().respond(&server_items, &request);
+		let response =
+			Response::try_from(response.to_bytes().as_slice()).unwrap();
+
+		let intersection = client.intersect(&response).unwrap();
+		assert_eq!(intersection, client_items[..10]);
+	}
+
+	#[test]
+	fn blinded() {
+		let client_items = items(0..3);
+		let (_, request) = Client::new(client_items.clone());
+		let (_, request2) = Client::new(client_items.clone());
+		// the same items look different every time
+		assert_ne!(request, request2);
+
+		let server = Server::new();
+		let a = server.respond(&client_items, &request);
+		let b = server.respond(&client_items, &request);
+		// the server items are shuffled
+		let shuffled = (0..10)
+			.any(|_| server.respond(&client_items, &request).items != a.items);
+		assert!(shuffled);
+		assert_eq!(a.blinded, b.blinded);
+	}
+
+	#[test]
+	fn mismatch() {
+		let (client, _) = Client::new(items(0..3));
+		let (_, other) = Client::new(items(0..2));
+		let response = Server::new().respond(&items(0..3), &other);
+		assert_eq!(client.intersect(&response).unwrap_err(), ResponseMismatch);
+
+		assert!(Response::try_from(&[0, 0, 0, 2, 1][..]).is_err());
+		assert!(Request::try_from(&[0; 31][..]).is_err());
+	}
+}
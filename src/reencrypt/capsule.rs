@@ -0,0 +1,196 @@
+use super::{hash_to_scalar, ReEncryptError, CAPSULE_DOMAIN};
+use crate::error::TryFromError;
+use crate::ristretto::{read_point, read_scalar};
+
+use std::convert::TryFrom;
+use std::fmt;
+
+use curve25519_dalek::{RistrettoPoint, Scalar};
+
+use zeroize::Zeroize;
+
+/// Contains the encapsulated key of a message.
+///
+/// The capsule needs to be sent with the ciphertext.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Capsule {
+	e: RistrettoPoint,
+	v: RistrettoPoint,
+	s: Scalar,
+}
+
+impl Capsule {
+	pub const LEN: usize = 96;
+
+	pub(crate) fn new(e: RistrettoPoint, v: RistrettoPoint, s: Scalar) -> Self {
+		Self { e, v, s }
+	}
+
+	/// Returns true if the capsule was created by encrypting to a
+	/// public key.
+	pub fn verify(&self) -> bool {
+		let h = hash_to_scalar(CAPSULE_DOMAIN, &[&self.e, &self.v]);
+		RistrettoPoint::mul_base(&self.s) == self.v + self.e * h
+	}
+
+	pub(crate) fn point_sum(&self) -> RistrettoPoint {
+		self.e + self.v
+	}
+
+	pub fn to_bytes(&self) -> [u8; 96] {
+		let mut bytes = [0u8; 96];
+		bytes[..32].copy_from_slice(self.e.compress().as_bytes());
+		bytes[32..64].copy_from_slice(self.v.compress().as_bytes());
+		bytes[64..].copy_from_slice(self.s.as_bytes());
+		bytes
+	}
+}
+
+impl fmt::Debug for Capsule {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_tuple("Capsule").field(&self.to_bytes()).finish()
+	}
+}
+
+impl TryFrom<&[u8]> for Capsule {
+	type Error = TryFromError;
+
+	fn try_from(v: &[u8]) -> Result<Self, Self::Error> {
+		if v.len() != Self::LEN {
+			return Err(TryFromError::from_any(()));
+		}
+
+		Ok(Self {
+			e: read_point(&v[..32])?,
+			v: read_point(&v[32..64])?,
+			s: read_scalar(&v[64..])?,
+		})
+	}
+}
+
+/// A capsule which was re-encrypted by a proxy.
+///
+/// Can only be opened by the receiver of the [`ReEncryptionKey`].
+#[derive(Clone, PartialEq, Eq)]
+pub struct CapsuleFrag {
+	e: RistrettoPoint,
+	v: RistrettoPoint,
+	ephemeral_public: RistrettoPoint,
+}
+
+impl CapsuleFrag {
+	pub const LEN: usize = 96;
+
+	pub(crate) fn point_sum(&self) -> RistrettoPoint {
+		self.e + self.v
+	}
+
+	pub(crate) fn ephemeral_public(&self) -> &RistrettoPoint {
+		&self.ephemeral_public
+	}
+
+	pub fn to_bytes(&self) -> [u8; 96] {
+		let mut bytes = [0u8; 96];
+		bytes[..32].copy_from_slice(self.e.compress().as_bytes());
+		bytes[32..64].copy_from_slice(self.v.compress().as_bytes());
+		bytes[64..]
+			.copy_from_slice(self.ephemeral_public.compress().as_bytes());
+		bytes
+	}
+}
+
+impl fmt::Debug for CapsuleFrag {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_tuple("CapsuleFrag")
+			.field(&self.to_bytes())
+			.finish()
+	}
+}
+
+impl TryFrom<&[u8]> for CapsuleFrag {
+	type Error = TryFromError;
+
+	fn try_from(v: &[u8]) -> Result<Self, Self::Error> {
+		if v.len() != Self::LEN {
+			return Err(TryFromError::from_any(()));
+		}
+
+		Ok(Self {
+			e: read_point(&v[..32])?,
+			v: read_point(&v[32..64])?,
+			ephemeral_public: read_point(&v[64..])?,
+		})
+	}
+}
+
+/// Allows a proxy to re-encrypt capsules from one keypair to another.
+///
+/// This key should only be known to the proxy.
+#[derive(Clone)]
+pub struct ReEncryptionKey {
+	key: Scalar,
+	ephemeral_public: RistrettoPoint,
+}
+
+impl ReEncryptionKey {
+	pub const LEN: usize = 64;
+
+	pub(crate) fn new(key: Scalar, ephemeral_public: RistrettoPoint) -> Self {
+		Self {
+			key,
+			ephemeral_public,
+		}
+	}
+
+	/// Re-encrypts the capsule returning an error if the capsule is
+	/// not valid.
+	pub fn re_encrypt(
+		&self,
+		capsule: &Capsule,
+	) -> Result<CapsuleFrag, ReEncryptError> {
+		if !capsule.verify() {
+			return Err(ReEncryptError::InvalidCapsule);
+		}
+
+		Ok(CapsuleFrag {
+			e: capsule.e * self.key,
+			v: capsule.v * self.key,
+			ephemeral_public: self.ephemeral_public,
+		})
+	}
+
+	pub fn to_bytes(&self) -> [u8; 64] {
+		let mut bytes = [0u8; 64];
+		bytes[..32].copy_from_slice(self.key.as_bytes());
+		bytes[32..]
+			.copy_from_slice(self.ephemeral_public.compress().as_bytes());
+		bytes
+	}
+}
+
+impl fmt::Debug for ReEncryptionKey {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str("ReEncryptionKey")
+	}
+}
+
+impl TryFrom<&[u8]> for ReEncryptionKey {
+	type Error = TryFromError;
+
+	fn try_from(v: &[u8]) -> Result<Self, Self::Error> {
+		if v.len() != Self::LEN {
+			return Err(TryFromError::from_any(()));
+		}
+
+		Ok(Self {
+			key: read_scalar(&v[..32])?,
+			ephemeral_public: read_point(&v[32..])?,
+		})
+	}
+}
+
+impl Drop for ReEncryptionKey {
+	fn drop(&mut self) {
+		self.key.zeroize();
+	}
+}
@@ -0,0 +1,206 @@
+use super::{
+	derive_key, hash_to_scalar, Capsule, CapsuleFrag, PublicKey,
+	ReEncryptError, ReEncryptionKey, RE_KEY_DOMAIN,
+};
+use crate::cipher::Mac;
+#[cfg(feature = "b64")]
+use crate::error::DecodeError;
+use crate::error::TryFromError;
+
+use std::convert::{TryFrom, TryInto};
+use std::fmt;
+
+use rand::rngs::OsRng;
+
+use curve25519_dalek::{RistrettoPoint, Scalar};
+
+use zeroize::Zeroize;
+
+#[cfg(feature = "b64")]
+use base64::engine::{general_purpose::URL_SAFE_NO_PAD, Engine};
+
+/// A Keypair that can decrypt messages and grant others access to them.
+#[derive(Clone)]
+pub struct Keypair {
+	secret: Scalar,
+	public: PublicKey,
+}
+
+impl Keypair {
+	pub const LEN: usize = 32;
+
+	fn from_scalar(secret: Scalar) -> Self {
+		let public = PublicKey::from_point(RistrettoPoint::mul_base(&secret));
+
+		Self { secret, public }
+	}
+
+	pub fn new() -> Self {
+		Self::from_scalar(Scalar::random(&mut OsRng))
+	}
+
+	/// ## Panics
+	/// if the slice is not 32 bytes long.
+	pub fn from_slice(slice: &[u8]) -> Self {
+		slice.try_into().unwrap()
+	}
+
+	pub fn to_bytes(&self) -> [u8; 32] {
+		self.secret.to_bytes()
+	}
+
+	pub fn public(&self) -> &PublicKey {
+		&self.public
+	}
+
+	/// Creates a key which allows a proxy to re-encrypt capsules
+	/// encrypted to this keypair, so that `receiver` can decrypt them.
+	pub fn re_encryption_key(&self, receiver: &PublicKey) -> ReEncryptionKey {
+		let ephemeral = Scalar::random(&mut OsRng);
+		let ephemeral_public = RistrettoPoint::mul_base(&ephemeral);
+		let shared = receiver.point() * ephemeral;
+
+		let d = hash_to_scalar(
+			RE_KEY_DOMAIN,
+			&[&ephemeral_public, receiver.point(), &shared],
+		);
+
+		ReEncryptionKey::new(self.secret * d.invert(), ephemeral_public)
+	}
+
+	/// Decrypts a message which was encrypted to this keypair.
+	pub fn decrypt(
+		&self,
+		capsule: &Capsule,
+		msg: &mut [u8],
+		mac: &Mac,
+	) -> Result<(), ReEncryptError> {
+		if !capsule.verify() {
+			return Err(ReEncryptError::InvalidCapsule);
+		}
+
+		let shared = capsule.point_sum() * self.secret;
+		derive_key(&shared)
+			.decrypt(msg, mac)
+			.map_err(|_| ReEncryptError::MacNotEqual)
+	}
+
+	/// Decrypts a message which was re-encrypted for this keypair.
+	pub fn decrypt_re_encrypted(
+		&self,
+		frag: &CapsuleFrag,
+		msg: &mut [u8],
+		mac: &Mac,
+	) -> Result<(), ReEncryptError> {
+		let ephemeral_public = frag.ephemeral_public();
+		let shared = ephemeral_public * self.secret;
+
+		let d = hash_to_scalar(
+			RE_KEY_DOMAIN,
+			&[ephemeral_public, self.public.point(), &shared],
+		);
+
+		let shared = frag.point_sum() * d;
+		derive_key(&shared)
+			.decrypt(msg, mac)
+			.map_err(|_| ReEncryptError::MacNotEqual)
+	}
+}
+
+#[cfg(not(feature = "b64"))]
+impl fmt::Debug for Keypair {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("Keypair")
+			.field("secret", &self.to_bytes())
+			.field("public", &self.public)
+			.finish()
+	}
+}
+
+#[cfg(feature = "b64")]
+impl fmt::Debug for Keypair {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("Keypair")
+			.field("secret", &self.to_string())
+			.field("public", &self.public)
+			.finish()
+	}
+}
+
+#[cfg(feature = "b64")]
+impl fmt::Display for Keypair {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		base64::display::Base64Display::new(&self.to_bytes(), &URL_SAFE_NO_PAD)
+			.fmt(f)
+	}
+}
+
+impl From<[u8; 32]> for Keypair {
+	/// The bytes get reduced modulo the group order.
+	fn from(bytes: [u8; 32]) -> Self {
+		Self::from_scalar(Scalar::from_bytes_mod_order(bytes))
+	}
+}
+
+impl TryFrom<&[u8]> for Keypair {
+	type Error = TryFromError;
+
+	fn try_from(v: &[u8]) -> Result<Self, Self::Error> {
+		<[u8; 32]>::try_from(v)
+			.map(Self::from)
+			.map_err(TryFromError::from_any)
+	}
+}
+
+#[cfg(feature = "b64")]
+impl crate::FromStr for Keypair {
+	type Err = DecodeError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		if s.len() != crate::calculate_b64_len(Self::LEN) {
+			return Err(DecodeError::InvalidLength);
+		}
+
+		let mut bytes = [0u8; Self::LEN];
+		URL_SAFE_NO_PAD
+			.decode_slice_unchecked(s, &mut bytes)
+			.map(|_| Self::from(bytes))
+			.map_err(DecodeError::inv_bytes)
+	}
+}
+
+impl Drop for Keypair {
+	fn drop(&mut self) {
+		self.secret.zeroize();
+	}
+}
+
+#[cfg(all(feature = "b64", feature = "serde"))]
+mod impl_serde {
+	use super::*;
+
+	use std::borrow::Cow;
+	use std::str::FromStr;
+
+	use _serde::de::Error;
+	use _serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+	impl Serialize for Keypair {
+		fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+		where
+			S: Serializer,
+		{
+			serializer.collect_str(&self)
+		}
+	}
+
+	impl<'de> Deserialize<'de> for Keypair {
+		fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+		where
+			D: Deserializer<'de>,
+		{
+			let s: Cow<'_, str> = Deserialize::deserialize(deserializer)?;
+			Self::from_str(s.as_ref()).map_err(D::Error::custom)
+		}
+	}
+}
@@ -0,0 +1,208 @@
+//! Contains structs used for proxy re-encryption.
+//!
+//! Data encrypted to Alice can be transformed by a proxy into data that
+//! Bob can decrypt, after Alice granted Bob access by creating a
+//! [`ReEncryptionKey`]. The proxy never sees the plaintext or any key
+//! which could decrypt it.
+//!
+//! The scheme follows Umbral (without threshold splitting) on the
+//! Ristretto group. Only the small [`Capsule`] needs to be re-encrypted,
+//! the ciphertext itself stays the same.
+//!
+//! ## Note
+//! If the proxy and Bob collude they can recover Alice's secret key.
+//!
+//! ## Example
+//! ```
+//! use fire_crypto::reencrypt::Keypair;
+//!
+//! let alice = Keypair::new();
+//! let bob = Keypair::new();
+//!
+//! // Someone encrypts a message to alice.
+//! let mut msg = *b"Hey Alice";
+//! let (capsule, mac) = alice.public().encrypt(msg.as_mut());
+//!
+//! // Alice allows bob to read her messages and gives the re-encryption
+//! // key to the proxy.
+//! let re_key = alice.re_encryption_key(bob.public());
+//!
+//! // The proxy transforms the capsule, without being able to decrypt.
+//! let capsule_frag = re_key.re_encrypt(&capsule).unwrap();
+//!
+//! // Bob can now decrypt the message.
+//! bob.decrypt_re_encrypted(&capsule_frag, msg.as_mut(), &mac).unwrap();
+//! assert_eq!(&msg, b"Hey Alice");
+//! ```
+
+mod keypair;
+pub use keypair::Keypair;
+
+mod public_key;
+pub use public_key::PublicKey;
+
+mod capsule;
+pub use capsule::{Capsule, CapsuleFrag, ReEncryptionKey};
+
+use crate::cipher::{Key, Nonce};
+use crate::hash::Hasher;
+
+use std::convert::TryInto;
+use std::error::Error;
+use std::fmt;
+
+use curve25519_dalek::{RistrettoPoint, Scalar};
+
+use zeroize::Zeroize;
+
+const CAPSULE_DOMAIN: &[u8] = b"fire-crypto reencrypt capsule";
+const RE_KEY_DOMAIN: &[u8] = b"fire-crypto reencrypt re-key";
+const KEY_DOMAIN: &[u8] = b"fire-crypto reencrypt key";
+
+/// Get's returned if a capsule or a message could not be decrypted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ReEncryptError {
+	/// The capsule was not created by encrypting to a public key.
+	InvalidCapsule,
+	/// The generated mac and the received mac are not equal.
+	MacNotEqual,
+}
+
+impl fmt::Display for ReEncryptError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		fmt::Debug::fmt(self, f)
+	}
+}
+
+impl Error for ReEncryptError {}
+
+fn hash_to_scalar(domain: &[u8], points: &[&RistrettoPoint]) -> Scalar {
+	let mut hasher = Hasher::new();
+	hasher.update(domain);
+	for point in points {
+		hasher.update(point.compress().as_bytes());
+	}
+
+	Scalar::from_bytes_mod_order_wide(&hasher.finalize().to_bytes())
+}
+
+/// Derives the symmetric key from the shared point.
+fn derive_key(shared: &RistrettoPoint) -> Key {
+	let mut hasher = Hasher::new();
+	hasher.update(KEY_DOMAIN);
+	hasher.update(shared.compress().as_bytes());
+	let mut bytes = hasher.finalize().to_bytes();
+
+	let secret = bytes[..32].try_into().unwrap();
+	let nonce = Nonce::from_slice(&bytes[32..32 + Nonce::LEN]);
+	bytes.zeroize();
+
+	Key::from_secret(secret, nonce)
+}
+
+// TESTS
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+
+	use crate::cipher::Mac;
+
+	use std::convert::TryFrom;
+	#[cfg(feature = "b64")]
+	use std::str::FromStr;
+
+	#[test]
+	fn decrypt_own() {
+		let alice = Keypair::new();
+
+		let mut msg = *b"hey thats a nice message";
+		let (capsule, mac) = alice.public().encrypt(&mut msg);
+		assert_ne!(&msg, b"hey thats a nice message");
+
+		alice.decrypt(&capsule, &mut msg, &mac).unwrap();
+		assert_eq!(&msg, b"hey thats a nice message");
+	}
+
+	#[test]
+	fn re_encrypt() {
+		let alice = Keypair::new();
+		let bob = Keypair::new();
+		let charlie = Keypair::new();
+
+		let mut msg = *b"hey thats a nice message";
+		let (capsule, mac) = alice.public().encrypt(&mut msg);
+
+		let re_key = alice.re_encryption_key(bob.public());
+		let frag = re_key.re_encrypt(&capsule).unwrap();
+
+		// charlie can't use a fragment meant for bob
+		let mut msg2 = msg;
+		assert_eq!(
+			charlie.decrypt_re_encrypted(&frag, &mut msg2, &mac),
+			Err(ReEncryptError::MacNotEqual)
+		);
+
+		bob.decrypt_re_encrypted(&frag, &mut msg, &mac).unwrap();
+		assert_eq!(&msg, b"hey thats a nice message");
+	}
+
+	#[test]
+	fn invalid_capsule() {
+		let alice = Keypair::new();
+		let bob = Keypair::new();
+
+		let (capsule, _) = alice.public().encrypt(&mut []);
+		let mut bytes = capsule.to_bytes();
+		// modify the scalar
+		bytes[64] ^= 1;
+		let capsule = Capsule::try_from(bytes.as_ref()).unwrap();
+
+		let re_key = alice.re_encryption_key(bob.public());
+		assert_eq!(
+			re_key.re_encrypt(&capsule).unwrap_err(),
+			ReEncryptError::InvalidCapsule
+		);
+		assert_eq!(
+			alice.decrypt(&capsule, &mut [], &Mac::from([0; 16])),
+			Err(ReEncryptError::InvalidCapsule)
+		);
+	}
+
+	#[test]
+	fn to_bytes() {
+		let alice = Keypair::new();
+		let bob = Keypair::new();
+
+		let alice_2 = Keypair::from(alice.to_bytes());
+		assert_eq!(alice.public(), alice_2.public());
+
+		let (capsule, _) = alice.public().encrypt(&mut []);
+		let capsule_2 = Capsule::try_from(capsule.to_bytes().as_ref()).unwrap();
+		assert_eq!(capsule, capsule_2);
+
+		let re_key = alice.re_encryption_key(bob.public());
+		let re_key_2 =
+			ReEncryptionKey::try_from(re_key.to_bytes().as_ref()).unwrap();
+
+		let frag = re_key_2.re_encrypt(&capsule).unwrap();
+		let frag_2 = CapsuleFrag::try_from(frag.to_bytes().as_ref()).unwrap();
+		assert_eq!(frag, frag_2);
+	}
+
+	#[cfg(feature = "b64")]
+	#[test]
+	fn b64() {
+		let alice = Keypair::new();
+
+		let b64 = alice.to_string();
+		let alice_2 = Keypair::from_str(&b64).unwrap();
+		assert_eq!(b64, alice_2.to_string());
+
+		let b64 = alice.public().to_string();
+		let public = PublicKey::from_str(&b64).unwrap();
+		assert_eq!(alice.public(), &public);
+	}
+}
@@ -0,0 +1,166 @@
+use super::{derive_key, hash_to_scalar, Capsule, CAPSULE_DOMAIN};
+use crate::cipher::Mac;
+#[cfg(feature = "b64")]
+use crate::error::DecodeError;
+use crate::error::TryFromError;
+use crate::ristretto::read_point;
+
+use std::convert::{TryFrom, TryInto};
+use std::hash::{Hash, Hasher};
+use std::{cmp, fmt};
+
+use rand::rngs::OsRng;
+
+use curve25519_dalek::{RistrettoPoint, Scalar};
+
+#[cfg(feature = "b64")]
+use base64::engine::{general_purpose::URL_SAFE_NO_PAD, Engine};
+
+#[derive(Clone)]
+pub struct PublicKey {
+	point: RistrettoPoint,
+	bytes: [u8; 32],
+}
+
+impl PublicKey {
+	pub const LEN: usize = 32;
+
+	pub(crate) fn from_point(point: RistrettoPoint) -> Self {
+		Self {
+			bytes: point.compress().to_bytes(),
+			point,
+		}
+	}
+
+	/// ## Panics
+	/// if the slice is not a valid public key.
+	pub fn from_slice(slice: &[u8]) -> Self {
+		slice.try_into().unwrap()
+	}
+
+	pub fn to_bytes(&self) -> [u8; 32] {
+		self.bytes
+	}
+
+	pub(crate) fn point(&self) -> &RistrettoPoint {
+		&self.point
+	}
+
+	/// Encrypts a message to this public key.
+	///
+	/// The capsule and the mac need to be sent with the ciphertext.
+	pub fn encrypt(&self, msg: &mut [u8]) -> (Capsule, Mac) {
+		let r = Scalar::random(&mut OsRng);
+		let u = Scalar::random(&mut OsRng);
+
+		let e = RistrettoPoint::mul_base(&r);
+		let v = RistrettoPoint::mul_base(&u);
+		let h = hash_to_scalar(CAPSULE_DOMAIN, &[&e, &v]);
+		let s = u + r * h;
+
+		let shared = self.point * (r + u);
+		let mac = derive_key(&shared).encrypt(msg);
+
+		(Capsule::new(e, v, s), mac)
+	}
+}
+
+#[cfg(not(feature = "b64"))]
+impl fmt::Debug for PublicKey {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_tuple("PublicKey").field(&self.as_ref()).finish()
+	}
+}
+
+#[cfg(feature = "b64")]
+impl fmt::Debug for PublicKey {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_tuple("PublicKey").field(&self.to_string()).finish()
+	}
+}
+
+#[cfg(feature = "b64")]
+impl fmt::Display for PublicKey {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		base64::display::Base64Display::new(self.as_ref(), &URL_SAFE_NO_PAD)
+			.fmt(f)
+	}
+}
+
+impl cmp::PartialEq for PublicKey {
+	fn eq(&self, other: &PublicKey) -> bool {
+		self.bytes == other.bytes
+	}
+}
+
+impl cmp::Eq for PublicKey {}
+
+impl Hash for PublicKey {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.bytes.hash(state)
+	}
+}
+
+impl TryFrom<&[u8]> for PublicKey {
+	type Error = TryFromError;
+
+	fn try_from(v: &[u8]) -> Result<Self, Self::Error> {
+		read_point(v).map(Self::from_point)
+	}
+}
+
+#[cfg(feature = "b64")]
+impl crate::FromStr for PublicKey {
+	type Err = DecodeError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		if s.len() != crate::calculate_b64_len(Self::LEN) {
+			return Err(DecodeError::InvalidLength);
+		}
+
+		let mut bytes = [0u8; Self::LEN];
+		URL_SAFE_NO_PAD
+			.decode_slice_unchecked(s, &mut bytes)
+			.map_err(DecodeError::inv_bytes)
+			.and_then(|_| {
+				Self::try_from(bytes.as_ref()).map_err(DecodeError::inv_bytes)
+			})
+	}
+}
+
+impl AsRef<[u8]> for PublicKey {
+	fn as_ref(&self) -> &[u8] {
+		&self.bytes
+	}
+}
+
+#[cfg(all(feature = "b64", feature = "serde"))]
+mod impl_serde {
+
+	use super::*;
+
+	use std::borrow::Cow;
+	use std::str::FromStr;
+
+	use _serde::de::Error;
+	use _serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+	impl Serialize for PublicKey {
+		fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+		where
+			S: Serializer,
+		{
+			serializer.collect_str(&self)
+		}
+	}
+
+	impl<'de> Deserialize<'de> for PublicKey {
+		fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+		where
+			D: Deserializer<'de>,
+		{
+			let s: Cow<'_, str> = Deserialize::deserialize(deserializer)?;
+			Self::from_str(s.as_ref()).map_err(D::Error::custom)
+		}
+	}
+}
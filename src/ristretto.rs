@@ -0,0 +1,38 @@
+//! Helpers shared by the modules which work on the Ristretto group.
+
+use crate::error::TryFromError;
+
+use std::convert::TryFrom;
+
+use curve25519_dalek::ristretto::CompressedRistretto;
+use curve25519_dalek::traits::Identity;
+use curve25519_dalek::{RistrettoPoint, Scalar};
+
+pub(crate) fn read_point(bytes: &[u8]) -> Result<RistrettoPoint, TryFromError> {
+	CompressedRistretto::from_slice(bytes)
+		.ok()
+		.and_then(|c| c.decompress())
+		.ok_or(TryFromError::from_any(()))
+}
+
+/// Like [`read_point`] but rejects the identity.
+#[allow(dead_code)]
+pub(crate) fn read_non_identity_point(
+	bytes: &[u8],
+) -> Result<RistrettoPoint, TryFromError> {
+	read_point(bytes).and_then(|p| {
+		if p == RistrettoPoint::identity() {
+			Err(TryFromError::from_any(()))
+		} else {
+			Ok(p)
+		}
+	})
+}
+
+/// Reads a scalar rejecting non canonical encodings.
+#[allow(dead_code)]
+pub(crate) fn read_scalar(bytes: &[u8]) -> Result<Scalar, TryFromError> {
+	let bytes = <[u8; 32]>::try_from(bytes).map_err(TryFromError::from_any)?;
+	Option::from(Scalar::from_canonical_bytes(bytes))
+		.ok_or(TryFromError::from_any(()))
+}
@@ -0,0 +1,392 @@
+//! Safety numbers and short authentication strings.
+//!
+//! Allows two users to verify out of band that they are talking to each
+//! other, by comparing a number, a few emojis or by scanning a qr code.
+//!
+//! A [`SafetyNumber`] can either be computed from the two identity keys
+//! (like in Signal) or from the transcript of a handshake. The order of
+//! the keys doesn't matter, so both users get the same result.
+//!
+//! ## Example
+//! ```
+//! use fire_crypto::signature::Keypair;
+//! use fire_crypto::sas::SafetyNumber;
+//!
+//! let alice = Keypair::new();
+//! let bob = Keypair::new();
+//!
+//! let on_alice = SafetyNumber::new(alice.public(), bob.public());
+//! let on_bob = SafetyNumber::new(bob.public(), alice.public());
+//!
+//! assert_eq!(on_alice.numeric(), on_bob.numeric());
+//! assert_eq!(on_alice.words(), on_bob.words());
+//!
+//! // bob scans the qr code of alice
+//! let qr = on_alice.to_qr_bytes();
+//! assert!(on_bob.verify_qr_bytes(&qr).unwrap());
+//! ```
+
+use crate::hash::Hasher;
+use crate::signature::PublicKey;
+
+use std::error::Error;
+use std::fmt;
+
+/// The amount of hash iterations when deriving the fingerprint of an
+/// identity key, makes it harder to find a key with the same safety
+/// number.
+pub const ITERATIONS: usize = 5200;
+
+const FINGERPRINT_LEN: usize = 30;
+const VERSION: u8 = 0;
+const TRANSCRIPT_KEY: &[u8] = b"fire-crypto sas transcript";
+const SAS_KEY: &[u8] = b"fire-crypto sas";
+
+/// How many emojis or words a short authentication string contains.
+pub const SAS_LEN: usize = 7;
+
+/// The emojis with their names, from the matrix sas specification.
+pub const EMOJIS: [(&str, &str); 64] = [
+	("🐶", "Dog"),
+	("🐱", "Cat"),
+	("🦁", "Lion"),
+	("🐎", "Horse"),
+	("🦄", "Unicorn"),
+	("🐷", "Pig"),
+	("🐘", "Elephant"),
+	("🐰", "Rabbit"),
+	("🐼", "Panda"),
+	("🐓", "Rooster"),
+	("🐧", "Penguin"),
+	("🐢", "Turtle"),
+	("🐟", "Fish"),
+	("🐙", "Octopus"),
+	("🦋", "Butterfly"),
+	("🌷", "Flower"),
+	("🌳", "Tree"),
+	("🌵", "Cactus"),
+	("🍄", "Mushroom"),
+	("🌏", "Globe"),
+	("🌙", "Moon"),
+	("☁️", "Cloud"),
+	("🔥", "Fire"),
+	("🍌", "Banana"),
+	("🍎", "Apple"),
+	("🍓", "Strawberry"),
+	("🌽", "Corn"),
+	("🍕", "Pizza"),
+	("🎂", "Cake"),
+	("❤️", "Heart"),
+	("😀", "Smiley"),
+	("🤖", "Robot"),
+	("🎩", "Hat"),
+	("👓", "Glasses"),
+	("🔧", "Spanner"),
+	("🎅", "Santa"),
+	("👍", "Thumbs Up"),
+	("☂️", "Umbrella"),
+	("⌛", "Hourglass"),
+	("⏰", "Clock"),
+	("🎁", "Gift"),
+	("💡", "Light Bulb"),
+	("📕", "Book"),
+	("✏️", "Pencil"),
+	("📎", "Paperclip"),
+	("✂️", "Scissors"),
+	("🔒", "Lock"),
+	("🔑", "Key"),
+	("🔨", "Hammer"),
+	("☎️", "Telephone"),
+	("🏁", "Flag"),
+	("🚂", "Train"),
+	("🚲", "Bicycle"),
+	("✈️", "Aeroplane"),
+	("🚀", "Rocket"),
+	("🏆", "Trophy"),
+	("⚽", "Ball"),
+	("🎸", "Guitar"),
+	("🎺", "Trumpet"),
+	("🔔", "Bell"),
+	("⚓", "Anchor"),
+	("🎧", "Headphones"),
+	("📁", "Folder"),
+	("📌", "Pin"),
+];
+
+/// Get's returned if qr bytes could not be parsed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum QrError {
+	InvalidLength,
+	/// The qr code was created by another version.
+	UnsupportedVersion,
+}
+
+impl fmt::Display for QrError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		fmt::Debug::fmt(self, f)
+	}
+}
+
+impl Error for QrError {}
+
+fn fingerprint(id: &[u8], key: &PublicKey) -> [u8; FINGERPRINT_LEN] {
+	let mut hasher = Hasher::new();
+	hasher.update([VERSION]);
+	hasher.update((id.len() as u64).to_be_bytes());
+	hasher.update(id);
+	hasher.update(key);
+	let mut hash = hasher.finalize();
+
+	for _ in 0..ITERATIONS {
+		let mut hasher = Hasher::new();
+		hasher.update(hash);
+		hasher.update(key);
+		hash = hasher.finalize();
+	}
+
+	hash.to_bytes()[..FINGERPRINT_LEN].try_into().unwrap()
+}
+
+/// A number both parties can compare to verify each others keys.
+#[derive(Clone, PartialEq, Eq)]
+pub struct SafetyNumber {
+	bytes: [u8; Self::LEN],
+}
+
+impl SafetyNumber {
+	pub const LEN: usize = FINGERPRINT_LEN * 2;
+
+	/// The length of the bytes returned by `to_qr_bytes`.
+	pub const QR_LEN: usize = 1 + Self::LEN;
+
+	/// Creates a safety number from two identity keys.
+	///
+	/// The order of the keys doesn't matter.
+	pub fn new(a: &PublicKey, b: &PublicKey) -> Self {
+		Self::with_identifiers(&[], a, &[], b)
+	}
+
+	/// Creates a safety number from two identity keys and the stable
+	/// identifiers of the users (for example a user id or phone number).
+	///
+	/// The order of the users doesn't matter.
+	pub fn with_identifiers(
+		a_id: &[u8],
+		a: &PublicKey,
+		b_id: &[u8],
+		b: &PublicKey,
+	) -> Self {
+		let mut a = fingerprint(a_id, a);
+		let mut b = fingerprint(b_id, b);
+		if a > b {
+			std::mem::swap(&mut a, &mut b);
+		}
+
+		let mut bytes = [0u8; Self::LEN];
+		bytes[..FINGERPRINT_LEN].copy_from_slice(&a);
+		bytes[FINGERPRINT_LEN..].copy_from_slice(&b);
+
+		Self { bytes }
+	}
+
+	/// Creates a safety number from a handshake transcript, which both
+	/// parties need to have in the same order.
+	///
+	/// ## Note
+	/// The transcript should contain values an attacker can't choose
+	/// after seeing the other values (for example ephemeral keys which
+	/// were committed to), else a matching short authentication string
+	/// can be brute forced.
+	pub fn from_transcript(transcript: &[u8]) -> Self {
+		let hash = Hasher::hash_keyed(TRANSCRIPT_KEY, transcript);
+
+		Self {
+			bytes: hash.to_bytes()[..Self::LEN].try_into().unwrap(),
+		}
+	}
+
+	pub fn to_bytes(&self) -> [u8; Self::LEN] {
+		self.bytes
+	}
+
+	/// Returns 60 digits in groups of five, separated by a space.
+	pub fn numeric(&self) -> String {
+		self.bytes
+			.chunks(5)
+			.map(|chunk| {
+				let mut n = [0u8; 8];
+				n[3..].copy_from_slice(chunk);
+				format!("{:05}", u64::from_be_bytes(n) % 100_000)
+			})
+			.collect::<Vec<_>>()
+			.join(" ")
+	}
+
+	/// Returns the indexes into [`EMOJIS`] of the short authentication
+	/// string.
+	fn sas_indexes(&self) -> [usize; SAS_LEN] {
+		let hash = Hasher::hash_keyed(SAS_KEY, self.bytes).to_bytes();
+
+		let mut n = [0u8; 8];
+		n[2..].copy_from_slice(&hash[..6]);
+		// the first 42 bits
+		let n = u64::from_be_bytes(n) >> 6;
+
+		let mut indexes = [0; SAS_LEN];
+		for (i, index) in indexes.iter_mut().enumerate() {
+			*index = ((n >> (6 * (SAS_LEN - 1 - i))) & 0b11_1111) as usize;
+		}
+
+		indexes
+	}
+
+	/// Returns a short authentication string of emojis.
+	///
+	/// This only contains 42 bits, so it should only be used with a
+	/// transcript or if the keys can't be chosen freely by an attacker.
+	pub fn emojis(&self) -> [&'static str; SAS_LEN] {
+		self.sas_indexes().map(|i| EMOJIS[i].0)
+	}
+
+	/// Returns the names of the emojis, can be used to compare the short
+	/// authentication string by reading it out loud.
+	pub fn words(&self) -> [&'static str; SAS_LEN] {
+		self.sas_indexes().map(|i| EMOJIS[i].1)
+	}
+
+	/// Returns a binary encoding which can be shown as a qr code.
+	///
+	/// The format is `version | safety number`.
+	pub fn to_qr_bytes(&self) -> [u8; Self::QR_LEN] {
+		let mut bytes = [0u8; Self::QR_LEN];
+		bytes[0] = VERSION;
+		bytes[1..].copy_from_slice(&self.bytes);
+		bytes
+	}
+
+	/// Parses the bytes of a scanned qr code.
+	pub fn from_qr_bytes(bytes: &[u8]) -> Result<Self, QrError> {
+		if bytes.len() != Self::QR_LEN {
+			return Err(QrError::InvalidLength);
+		}
+
+		if bytes[0] != VERSION {
+			return Err(QrError::UnsupportedVersion);
+		}
+
+		Ok(Self {
+			bytes: bytes[1..].try_into().unwrap(),
+		})
+	}
+
+	/// Returns true if the scanned qr code matches this safety number.
+	pub fn verify_qr_bytes(&self, bytes: &[u8]) -> Result<bool, QrError> {
+		Self::from_qr_bytes(bytes).map(|other| &other == self)
+	}
+}
+
+impl fmt::Debug for SafetyNumber {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_tuple("SafetyNumber")
+			.field(&self.numeric())
+			.finish()
+	}
+}
+
+impl fmt::Display for SafetyNumber {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(&self.numeric())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+	use crate::signature::Keypair;
+
+	#[test]
+	fn order_independent() {
+		let alice = Keypair::new();
+		let bob = Keypair::new();
+
+		let a = SafetyNumber::new(alice.public(), bob.public());
+		let b = SafetyNumber::new(bob.public(), alice.public());
+		assert_eq!(a, b);
+		assert_eq!(a.numeric(), b.numeric());
+		assert_eq!(a.emojis(), b.emojis());
+
+		let a = SafetyNumber::with_identifiers(
+			b"alice",
+			alice.public(),
+			b"bob",
+			bob.public(),
+		);
+		let b = SafetyNumber::with_identifiers(
+			b"bob",
+			bob.public(),
+			b"alice",
+			alice.public(),
+		);
+		assert_eq!(a, b);
+		assert_ne!(a, SafetyNumber::new(alice.public(), bob.public()));
+
+		let mallory = Keypair::new();
+		let c = SafetyNumber::new(alice.public(), mallory.public());
+		assert_ne!(SafetyNumber::new(alice.public(), bob.public()), c);
+	}
+
+	#[test]
+	fn numeric() {
+		let alice = Keypair::new();
+		let bob = Keypair::new();
+
+		let n = SafetyNumber::new(alice.public(), bob.public()).numeric();
+		let groups: Vec<_> = n.split(' ').collect();
+		assert_eq!(groups.len(), 12);
+		assert!(groups
+			.iter()
+			.all(|g| g.len() == 5 && g.bytes().all(|b| b.is_ascii_digit())));
+	}
+
+	#[test]
+	fn static_number() {
+		let n = SafetyNumber::from_transcript(b"handshake transcript");
+		assert_eq!(n, SafetyNumber::from_transcript(b"handshake transcript"));
+		assert_ne!(n, SafetyNumber::from_transcript(b"other transcript"));
+
+		let n = SafetyNumber { bytes: [0u8; 60] };
+		assert_eq!(n.numeric(), ["00000"; 12].join(" "));
+	}
+
+	#[test]
+	fn sas() {
+		let n = SafetyNumber::from_transcript(b"transcript");
+		let emojis = n.emojis();
+		let words = n.words();
+
+		for (emoji, word) in emojis.iter().zip(words) {
+			assert!(EMOJIS.contains(&(*emoji, word)));
+		}
+	}
+
+	#[test]
+	fn qr() {
+		let n = SafetyNumber::from_transcript(b"transcript");
+		let bytes = n.to_qr_bytes();
+		assert_eq!(SafetyNumber::from_qr_bytes(&bytes).unwrap(), n);
+		assert!(n.verify_qr_bytes(&bytes).unwrap());
+
+		let other = SafetyNumber::from_transcript(b"other");
+		assert!(!other.verify_qr_bytes(&bytes).unwrap());
+
+		assert_eq!(n.verify_qr_bytes(&bytes[1..]), Err(QrError::InvalidLength));
+		let mut bytes = bytes;
+		bytes[0] = 1;
+		assert_eq!(
+			SafetyNumber::from_qr_bytes(&bytes),
+			Err(QrError::UnsupportedVersion)
+		);
+	}
+}
@@ -0,0 +1,580 @@
+//! Encrypts the values of a config file, while keeping its structure
+//! readable.
+//!
+//! Like [sops](https://github.com/getsops/sops) every leaf value of a
+//! document gets encrypted on its own, so diffs still show which values
+//! changed. Documents are [`serde_json::Value`]s, yaml or toml files can
+//! be converted from and to them.
+//!
+//! A random data key encrypts the values and is wrapped for every
+//! recipient [`PublicKey`]. The metadata is stored under
+//! [`METADATA_KEY`], together with a mac over the paths and plaintexts of
+//! all values, so values can't be removed, reordered or swapped.
+//!
+//! ## Format
+//! Strings are encrypted as `ENC[str,<base64>]`, other values as
+//! `ENC[json,<base64>]`, the base64 contains the nonce, the mac and the
+//! ciphertext. The format is not compatible with sops.
+//!
+//! ## Example
+//! ```
+//! use fire_crypto::cipher::Keypair;
+//! use fire_crypto::secret_config::{decrypt, encrypt};
+//!
+//! use serde_json::json;
+//!
+//! let alice = Keypair::new();
+//! let ci = Keypair::new();
+//!
+//! let config = json!({
+//!     "database": { "user": "app", "password": "hunter2", "port": 5432 }
+//! });
+//!
+//! let recipients = [alice.public().clone(), ci.public().clone()];
+//! let encrypted = encrypt(&config, &recipients).unwrap();
+//! let password = encrypted["database"]["password"].as_str().unwrap();
+//! assert!(password.starts_with("ENC[str,"));
+//!
+//! assert_eq!(decrypt(&encrypted, &ci).unwrap(), config);
+//! ```
+
+use crate::cipher::{EphemeralKeypair, Key, Keypair, Mac, Nonce, PublicKey};
+use crate::hash::{Hash, Hasher};
+
+use std::convert::{TryFrom, TryInto};
+use std::error::Error;
+use std::fmt;
+
+use serde_json::{json, Map, Value};
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+
+use zeroize::Zeroize;
+
+/// The top level key under which the metadata is stored.
+pub const METADATA_KEY: &str = "fire_crypto";
+
+const VERSION: u64 = 1;
+
+const WRAP_CONTEXT: &[u8] = b"fire-crypto secret config wrap";
+const VALUE_CONTEXT: &[u8] = b"fire-crypto secret config value";
+const MAC_CONTEXT: &[u8] = b"fire-crypto secret config mac";
+
+const STR_TYPE: &str = "str";
+const JSON_TYPE: &str = "json";
+
+/// Get's returned if a document could not be encrypted or decrypted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SecretConfigError {
+	/// The document is not an object.
+	NotAnObject,
+	/// No recipient was given.
+	NoRecipients,
+	/// The document already contains [`METADATA_KEY`].
+	AlreadyEncrypted,
+	/// The document does not contain [`METADATA_KEY`].
+	NotEncrypted,
+	/// The metadata has an invalid format or an unknown version.
+	InvalidMetadata,
+	/// The data key is not wrapped for the keypair.
+	NotARecipient,
+	/// A public key has low order.
+	NonContributory,
+	/// An encrypted value has an invalid format.
+	InvalidValue,
+	/// A value or a wrapped data key was modified.
+	MacNotEqual,
+	/// Values were added, removed or moved.
+	DocumentMacNotEqual,
+}
+
+impl From<crate::cipher::NonContributory> for SecretConfigError {
+	fn from(_: crate::cipher::NonContributory) -> Self {
+		Self::NonContributory
+	}
+}
+
+impl fmt::Display for SecretConfigError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		fmt::Debug::fmt(self, f)
+	}
+}
+
+impl Error for SecretConfigError {}
+
+struct DataKey {
+	bytes: [u8; 32],
+}
+
+impl DataKey {
+	fn new() -> Self {
+		let mut bytes = [0u8; 32];
+		crate::fill_random(&mut bytes);
+
+		Self { bytes }
+	}
+
+	fn value_key(&self, path: &str, ty: &str, nonce: Nonce) -> Key {
+		let mut hasher = Hasher::new_keyed(self.bytes);
+		hasher.update(VALUE_CONTEXT);
+		hasher.update((path.len() as u64).to_be_bytes());
+		hasher.update(path);
+		hasher.update(ty);
+		let mut bytes = hasher.finalize().to_bytes();
+		let secret = bytes[..32].try_into().unwrap();
+		bytes.zeroize();
+
+		Key::from_secret(secret, nonce)
+	}
+
+	/// The mac over all entries, sorted so the order of keys in objects
+	/// does not matter.
+	fn mac(&self, mut entries: Vec<Vec<u8>>) -> Hash {
+		entries.sort_unstable();
+
+		let mut hasher = Hasher::new_keyed(self.bytes);
+		hasher.update(MAC_CONTEXT);
+		for entry in &entries {
+			hasher.update((entry.len() as u64).to_be_bytes());
+			hasher.update(entry);
+		}
+		for entry in &mut entries {
+			entry.zeroize();
+		}
+
+		hasher.finalize()
+	}
+}
+
+impl Drop for DataKey {
+	fn drop(&mut self) {
+		self.bytes.zeroize();
+	}
+}
+
+/// Derives the key which wraps the data key for one recipient.
+fn wrap_key(
+	ephemeral: &PublicKey,
+	recipient: &PublicKey,
+	shared: &[u8],
+) -> Key {
+	let mut hasher = Hasher::new_keyed(shared);
+	hasher.update(WRAP_CONTEXT);
+	hasher.update(ephemeral);
+	hasher.update(recipient);
+	let mut bytes = hasher.finalize().to_bytes();
+
+	// the key is only used once, so the nonce can be derived
+	let key = Key::from_secret(
+		bytes[..32].try_into().unwrap(),
+		Nonce::from_slice(&bytes[32..32 + Nonce::LEN]),
+	);
+	bytes.zeroize();
+
+	key
+}
+
+fn b64_decode(s: &str) -> Result<Vec<u8>, SecretConfigError> {
+	URL_SAFE_NO_PAD
+		.decode(s)
+		.map_err(|_| SecretConfigError::InvalidMetadata)
+}
+
+/// Returns the entry which gets authenticated by the document mac.
+fn mac_entry(path: &str, kind: &str, content: &[u8]) -> Vec<u8> {
+	let mut entry = Vec::with_capacity(path.len() + content.len() + 16);
+	entry.extend_from_slice(&(path.len() as u64).to_be_bytes());
+	entry.extend_from_slice(path.as_bytes());
+	entry.push(kind.len() as u8);
+	entry.extend_from_slice(kind.as_bytes());
+	entry.extend_from_slice(content);
+	entry
+}
+
+/// Calls `f` for every leaf and rebuilds the document with the returned
+/// values. Every container adds an entry for the document mac.
+fn map_leaves<F>(
+	value: &Value,
+	path: &mut String,
+	entries: &mut Vec<Vec<u8>>,
+	f: &mut F,
+) -> Result<Value, SecretConfigError>
+where
+	F: FnMut(&str, &Value) -> Result<Value, SecretConfigError>,
+{
+	let len = path.len();
+
+	let value = match value {
+		Value::Object(map) => {
+			entries.push(mac_entry(path, "object", &[]));
+
+			let mut new = Map::new();
+			for (k, v) in map {
+				// json pointer escaping
+				path.push('/');
+				path.push_str(&k.replace('~', "~0").replace('/', "~1"));
+				new.insert(k.clone(), map_leaves(v, path, entries, f)?);
+				path.truncate(len);
+			}
+
+			Value::Object(new)
+		}
+		Value::Array(list) => {
+			entries.push(mac_entry(path, "array", &[]));
+
+			let mut new = Vec::with_capacity(list.len());
+			for (i, v) in list.iter().enumerate() {
+				path.push('/');
+				path.push_str(&i.to_string());
+				new.push(map_leaves(v, path, entries, f)?);
+				path.truncate(len);
+			}
+
+			Value::Array(new)
+		}
+		leaf => f(path, leaf)?,
+	};
+
+	Ok(value)
+}
+
+/// Returns the type and the plaintext of a leaf.
+fn leaf_plaintext(value: &Value) -> (&'static str, Vec<u8>) {
+	match value {
+		Value::String(s) => (STR_TYPE, s.as_bytes().to_vec()),
+		other => (JSON_TYPE, other.to_string().into_bytes()),
+	}
+}
+
+fn encrypt_leaf(
+	data_key: &DataKey,
+	path: &str,
+	value: &Value,
+	entries: &mut Vec<Vec<u8>>,
+) -> Value {
+	let (ty, mut plaintext) = leaf_plaintext(value);
+	entries.push(mac_entry(path, ty, &plaintext));
+
+	let nonce = Nonce::new();
+	let mut bytes = nonce.to_bytes().to_vec();
+	let mac = data_key.value_key(path, ty, nonce).encrypt(&mut plaintext);
+	bytes.extend_from_slice(&mac.into_bytes());
+	bytes.append(&mut plaintext);
+
+	Value::String(format!("ENC[{},{}]", ty, URL_SAFE_NO_PAD.encode(bytes)))
+}
+
+fn decrypt_leaf(
+	data_key: &DataKey,
+	path: &str,
+	value: &Value,
+	entries: &mut Vec<Vec<u8>>,
+) -> Result<Value, SecretConfigError> {
+	let encrypted = match value {
+		Value::String(s) if s.starts_with("ENC[") && s.ends_with(']') => {
+			&s[4..s.len() - 1]
+		}
+		// unencrypted values are authenticated by the document mac
+		other => {
+			let (ty, plaintext) = leaf_plaintext(other);
+			entries.push(mac_entry(path, ty, &plaintext));
+			return Ok(other.clone());
+		}
+	};
+
+	let (ty, b64) = encrypted
+		.split_once(',')
+		.filter(|(ty, _)| *ty == STR_TYPE || *ty == JSON_TYPE)
+		.ok_or(SecretConfigError::InvalidValue)?;
+	let bytes = URL_SAFE_NO_PAD
+		.decode(b64)
+		.map_err(|_| SecretConfigError::InvalidValue)?;
+	if bytes.len() < Nonce::LEN + Mac::LEN {
+		return Err(SecretConfigError::InvalidValue);
+	}
+
+	let (nonce, rest) = bytes.split_at(Nonce::LEN);
+	let (mac, ciphertext) = rest.split_at(Mac::LEN);
+	let mut plaintext = ciphertext.to_vec();
+	data_key
+		.value_key(path, ty, Nonce::from_slice(nonce))
+		.decrypt(&mut plaintext, &Mac::from_slice(mac))
+		.map_err(|_| SecretConfigError::MacNotEqual)?;
+
+	entries.push(mac_entry(path, ty, &plaintext));
+
+	let value = if ty == STR_TYPE {
+		String::from_utf8(plaintext.clone()).map(Value::String).ok()
+	} else {
+		serde_json::from_slice(&plaintext).ok()
+	};
+	plaintext.zeroize();
+
+	value.ok_or(SecretConfigError::InvalidValue)
+}
+
+/// Returns true if the document contains the metadata of an encrypted
+/// document.
+pub fn is_encrypted(doc: &Value) -> bool {
+	doc.get(METADATA_KEY).is_some()
+}
+
+/// Encrypts every leaf value of the document, so that each recipient can
+/// decrypt it.
+pub fn encrypt(
+	doc: &Value,
+	recipients: &[PublicKey],
+) -> Result<Value, SecretConfigError> {
+	if !doc.is_object() {
+		return Err(SecretConfigError::NotAnObject);
+	}
+	if is_encrypted(doc) {
+		return Err(SecretConfigError::AlreadyEncrypted);
+	}
+	if recipients.is_empty() {
+		return Err(SecretConfigError::NoRecipients);
+	}
+
+	let data_key = DataKey::new();
+
+	let mut wrapped_keys = Vec::with_capacity(recipients.len());
+	for recipient in recipients {
+		let ephemeral = EphemeralKeypair::new();
+		let ephemeral_pk = ephemeral.public().clone();
+		let shared = ephemeral.try_diffie_hellman(recipient)?;
+
+		let mut wrapped = data_key.bytes;
+		let mac = wrap_key(&ephemeral_pk, recipient, shared.as_slice())
+			.encrypt(&mut wrapped);
+
+		let mut bytes = mac.into_bytes().to_vec();
+		bytes.extend_from_slice(&wrapped);
+
+		wrapped_keys.push(json!({
+			"public_key": URL_SAFE_NO_PAD.encode(recipient),
+			"ephemeral_key": URL_SAFE_NO_PAD.encode(ephemeral_pk),
+			"data_key": URL_SAFE_NO_PAD.encode(bytes),
+		}));
+	}
+
+	let mut entries = vec![];
+	let mut mac_entries = vec![];
+	let mut encrypted =
+		map_leaves(doc, &mut String::new(), &mut entries, &mut |path, v| {
+			Ok(encrypt_leaf(&data_key, path, v, &mut mac_entries))
+		})?;
+	entries.append(&mut mac_entries);
+
+	let mac = data_key.mac(entries);
+	encrypted.as_object_mut().unwrap().insert(
+		METADATA_KEY.into(),
+		json!({
+			"version": VERSION,
+			"recipients": wrapped_keys,
+			"mac": URL_SAFE_NO_PAD.encode(mac),
+		}),
+	);
+
+	Ok(encrypted)
+}
+
+/// Returns the data key if it is wrapped for the keypair.
+fn unwrap_data_key(
+	metadata: &Value,
+	keypair: &Keypair,
+) -> Result<DataKey, SecretConfigError> {
+	let recipients = metadata
+		.get("recipients")
+		.and_then(Value::as_array)
+		.ok_or(SecretConfigError::InvalidMetadata)?;
+
+	let public_key = URL_SAFE_NO_PAD.encode(keypair.public());
+	let recipient = recipients
+		.iter()
+		.find(|r| r.get("public_key") == Some(&Value::from(&*public_key)))
+		.ok_or(SecretConfigError::NotARecipient)?;
+
+	let field = |name: &str| {
+		recipient
+			.get(name)
+			.and_then(Value::as_str)
+			.ok_or(SecretConfigError::InvalidMetadata)
+			.and_then(b64_decode)
+	};
+
+	let ephemeral_pk = PublicKey::try_from(field("ephemeral_key")?.as_slice())
+		.map_err(|_| SecretConfigError::InvalidMetadata)?;
+
+	let wrapped = field("data_key")?;
+	if wrapped.len() != Mac::LEN + 32 {
+		return Err(SecretConfigError::InvalidMetadata);
+	}
+	let (mac, wrapped) = wrapped.split_at(Mac::LEN);
+
+	let shared = keypair.try_diffie_hellman(&ephemeral_pk)?;
+	let mut data_key = DataKey {
+		bytes: wrapped.try_into().unwrap(),
+	};
+	wrap_key(&ephemeral_pk, keypair.public(), shared.as_slice())
+		.decrypt(&mut data_key.bytes, &Mac::from_slice(mac))
+		.map_err(|_| SecretConfigError::MacNotEqual)?;
+
+	Ok(data_key)
+}
+
+/// Decrypts the document, if the keypair is one of its recipients.
+pub fn decrypt(
+	doc: &Value,
+	keypair: &Keypair,
+) -> Result<Value, SecretConfigError> {
+	let mut doc = doc
+		.as_object()
+		.ok_or(SecretConfigError::NotAnObject)?
+		.clone();
+	let metadata = doc
+		.remove(METADATA_KEY)
+		.ok_or(SecretConfigError::NotEncrypted)?;
+	if metadata.get("version").and_then(Value::as_u64) != Some(VERSION) {
+		return Err(SecretConfigError::InvalidMetadata);
+	}
+	let expected_mac = metadata
+		.get("mac")
+		.and_then(Value::as_str)
+		.ok_or(SecretConfigError::InvalidMetadata)
+		.and_then(b64_decode)?;
+
+	let data_key = unwrap_data_key(&metadata, keypair)?;
+
+	let mut entries = vec![];
+	let mut leaf_entries = vec![];
+	let decrypted = map_leaves(
+		&Value::Object(doc),
+		&mut String::new(),
+		&mut entries,
+		&mut |path, v| decrypt_leaf(&data_key, path, v, &mut leaf_entries),
+	)?;
+	entries.append(&mut leaf_entries);
+
+	if !crate::constant_time_eq(data_key.mac(entries).as_ref(), &expected_mac) {
+		return Err(SecretConfigError::DocumentMacNotEqual);
+	}
+
+	Ok(decrypted)
+}
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+
+	fn config() -> Value {
+		json!({
+			"name": "app",
+			"database": {
+				"password": "hunter2",
+				"port": 5432,
+				"replicas": ["a", "b"],
+				"tls": true,
+				"ca": null
+			},
+			"a/b~c": 1.5
+		})
+	}
+
+	#[test]
+	fn roundtrip() {
+		let alice = Keypair::new();
+		let bob = Keypair::new();
+
+		let encrypted =
+			encrypt(&config(), &[alice.public().clone(), bob.public().clone()])
+				.unwrap();
+		assert!(is_encrypted(&encrypted));
+		assert!(!is_encrypted(&config()));
+		assert!(!encrypted.to_string().contains("hunter2"));
+
+		// the structure stays readable
+		let replicas = encrypted["database"]["replicas"].as_array().unwrap();
+		assert_eq!(replicas.len(), 2);
+		assert!(encrypted["database"]["port"]
+			.as_str()
+			.unwrap()
+			.starts_with("ENC[json,"));
+
+		assert_eq!(decrypt(&encrypted, &alice).unwrap(), config());
+		assert_eq!(decrypt(&encrypted, &bob).unwrap(), config());
+
+		// roundtrip through a string
+		let s = serde_json::to_string_pretty(&encrypted).unwrap();
+		let parsed: Value = serde_json::from_str(&s).unwrap();
+		assert_eq!(decrypt(&parsed, &alice).unwrap(), config());
+
+		assert_eq!(
+			decrypt(&encrypted, &Keypair::new()).unwrap_err(),
+			SecretConfigError::NotARecipient
+		);
+		assert_eq!(
+			encrypt(&encrypted, &[alice.public().clone()]).unwrap_err(),
+			SecretConfigError::AlreadyEncrypted
+		);
+		assert_eq!(
+			encrypt(&config(), &[]).unwrap_err(),
+			SecretConfigError::NoRecipients
+		);
+		assert_eq!(
+			decrypt(&config(), &alice).unwrap_err(),
+			SecretConfigError::NotEncrypted
+		);
+	}
+
+	#[test]
+	fn modified_values() {
+		let alice = Keypair::new();
+		let encrypted = encrypt(&config(), &[alice.public().clone()]).unwrap();
+
+		// swapping two values
+		let mut swapped = encrypted.clone();
+		let db = &mut swapped["database"];
+		let port = db["port"].take();
+		db["port"] = db["tls"].take();
+		db["tls"] = port;
+		assert_eq!(
+			decrypt(&swapped, &alice).unwrap_err(),
+			SecretConfigError::MacNotEqual
+		);
+
+		// removing a value
+		let mut removed = encrypted.clone();
+		removed["database"].as_object_mut().unwrap().remove("ca");
+		assert_eq!(
+			decrypt(&removed, &alice).unwrap_err(),
+			SecretConfigError::DocumentMacNotEqual
+		);
+
+		// adding a plaintext value
+		let mut added = encrypted.clone();
+		added["database"]["user"] = json!("root");
+		assert_eq!(
+			decrypt(&added, &alice).unwrap_err(),
+			SecretConfigError::DocumentMacNotEqual
+		);
+
+		// replacing the whole document mac
+		let mut other_mac = encrypted.clone();
+		let other = encrypt(&config(), &[alice.public().clone()]).unwrap();
+		other_mac[METADATA_KEY]["mac"] = other[METADATA_KEY]["mac"].clone();
+		assert_eq!(
+			decrypt(&other_mac, &alice).unwrap_err(),
+			SecretConfigError::DocumentMacNotEqual
+		);
+
+		let mut invalid = encrypted;
+		invalid["name"] = json!("ENC[str,@@]");
+		assert_eq!(
+			decrypt(&invalid, &alice).unwrap_err(),
+			SecretConfigError::InvalidValue
+		);
+	}
+}
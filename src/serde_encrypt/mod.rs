@@ -0,0 +1,406 @@
+//! Field level encryption for serde.
+//!
+//! Values get serialized to json, encrypted and then emitted as a base64
+//! (url safe, without padding) string. The key is not passed to serde but
+//! supplied with an [`EncryptionContext`] which is active for the duration
+//! of a closure on the current thread.
+//!
+//! Fields can either use the [`Encrypted`] wrapper or the adapter
+//! functions with `#[serde(with = "fire_crypto::serde_encrypt")]`.
+//!
+//! A [`Keyring`] should be preferred for data at rest, since every value
+//! uses a random nonce and contains the key id. With a [`SyncKey`] the
+//! values need to be deserialized in the same order as they were
+//! serialized.
+//!
+//! ## Example
+//! ```
+//! use fire_crypto::keyring::Keyring;
+//! use fire_crypto::serde_encrypt::{Encrypted, EncryptionContext};
+//!
+//! use std::sync::Arc;
+//!
+//! let mut keyring = Keyring::new();
+//! keyring.generate_key();
+//! let ctx = EncryptionContext::keyring(Arc::new(keyring));
+//!
+//! let ssn = Encrypted("756.1234.5678.97".to_string());
+//! let json = ctx.scope(|| serde_json::to_string(&ssn)).unwrap();
+//! assert!(!json.contains("756"));
+//!
+//! let ssn: Encrypted<String> =
+//!     ctx.scope(|| serde_json::from_str(&json)).unwrap();
+//! assert_eq!(ssn.0, "756.1234.5678.97");
+//!
+//! // without a context the value can't be read
+//! assert!(serde_json::from_str::<Encrypted<String>>(&json).is_err());
+//! ```
+
+use crate::cipher::{Mac, SyncKey};
+use crate::keyring::{Keyring, KeyringError};
+
+use std::cell::RefCell;
+use std::error::Error;
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+
+use _serde::de::{self, DeserializeOwned, Deserializer};
+use _serde::ser::{self, Serializer};
+use _serde::{Deserialize, Serialize};
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+
+thread_local! {
+	static CONTEXTS: RefCell<Vec<EncryptionContext>> =
+		const { RefCell::new(Vec::new()) };
+}
+
+/// Get's returned if a value could not be encrypted or decrypted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum EncryptedFieldError {
+	/// The value was (de)serialized outside of
+	/// [`EncryptionContext::scope`].
+	NoEncryptionContext,
+	/// The string is not valid base64.
+	InvalidBase64,
+	/// The ciphertext is shorter than a mac.
+	InvalidLength,
+	/// The ciphertext was modified or encrypted with another [`SyncKey`].
+	MacNotEqual,
+	/// The keyring could not encrypt or decrypt the value.
+	Keyring(KeyringError),
+	/// The value could not be converted to or from json.
+	Json(String),
+}
+
+impl From<KeyringError> for EncryptedFieldError {
+	fn from(e: KeyringError) -> Self {
+		Self::Keyring(e)
+	}
+}
+
+impl fmt::Display for EncryptedFieldError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		fmt::Debug::fmt(self, f)
+	}
+}
+
+impl Error for EncryptedFieldError {}
+
+#[derive(Clone)]
+enum Inner {
+	Keyring(Arc<Keyring>),
+	SyncKey(Arc<SyncKey>),
+}
+
+/// The key which is used to encrypt and decrypt fields.
+#[derive(Clone)]
+pub struct EncryptionContext {
+	inner: Inner,
+}
+
+impl EncryptionContext {
+	/// Encrypts with the primary key of the keyring.
+	pub fn keyring(keyring: Arc<Keyring>) -> Self {
+		Self {
+			inner: Inner::Keyring(keyring),
+		}
+	}
+
+	/// Encrypts with the next nonce of the key.
+	pub fn sync_key(key: Arc<SyncKey>) -> Self {
+		Self {
+			inner: Inner::SyncKey(key),
+		}
+	}
+
+	/// Makes this context active on the current thread while `f` runs.
+	///
+	/// Scopes can be nested, the innermost context is used.
+	pub fn scope<R>(&self, f: impl FnOnce() -> R) -> R {
+		struct Guard;
+
+		impl Drop for Guard {
+			fn drop(&mut self) {
+				CONTEXTS.with(|c| c.borrow_mut().pop());
+			}
+		}
+
+		CONTEXTS.with(|c| c.borrow_mut().push(self.clone()));
+		let _guard = Guard;
+
+		f()
+	}
+
+	fn current() -> Result<Self, EncryptedFieldError> {
+		CONTEXTS
+			.with(|c| c.borrow().last().cloned())
+			.ok_or(EncryptedFieldError::NoEncryptionContext)
+	}
+
+	fn encrypt(
+		&self,
+		mut msg: Vec<u8>,
+	) -> Result<Vec<u8>, EncryptedFieldError> {
+		match &self.inner {
+			Inner::Keyring(k) => Ok(k.encrypt(&msg)?),
+			Inner::SyncKey(k) => {
+				let mac = k.encrypt(&mut msg);
+				let mut bytes = mac.into_bytes().to_vec();
+				bytes.append(&mut msg);
+				Ok(bytes)
+			}
+		}
+	}
+
+	fn decrypt(&self, bytes: &[u8]) -> Result<Vec<u8>, EncryptedFieldError> {
+		match &self.inner {
+			Inner::Keyring(k) => Ok(k.decrypt(bytes)?),
+			Inner::SyncKey(k) => {
+				if bytes.len() < Mac::LEN {
+					return Err(EncryptedFieldError::InvalidLength);
+				}
+
+				let (mac, ciphertext) = bytes.split_at(Mac::LEN);
+				let mut msg = ciphertext.to_vec();
+				k.decrypt(&mut msg, &Mac::from_slice(mac))
+					.map_err(|_| EncryptedFieldError::MacNotEqual)?;
+				Ok(msg)
+			}
+		}
+	}
+}
+
+impl fmt::Debug for EncryptionContext {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match &self.inner {
+			Inner::Keyring(_) => f.write_str("EncryptionContext::Keyring"),
+			Inner::SyncKey(_) => f.write_str("EncryptionContext::SyncKey"),
+		}
+	}
+}
+
+fn encrypt_value<T: Serialize + ?Sized>(
+	value: &T,
+) -> Result<String, EncryptedFieldError> {
+	let ctx = EncryptionContext::current()?;
+	let json = serde_json::to_vec(value)
+		.map_err(|e| EncryptedFieldError::Json(e.to_string()))?;
+
+	Ok(URL_SAFE_NO_PAD.encode(ctx.encrypt(json)?))
+}
+
+fn decrypt_value<T: DeserializeOwned>(
+	s: &str,
+) -> Result<T, EncryptedFieldError> {
+	let ctx = EncryptionContext::current()?;
+	let bytes = URL_SAFE_NO_PAD
+		.decode(s)
+		.map_err(|_| EncryptedFieldError::InvalidBase64)?;
+
+	let json = ctx.decrypt(&bytes)?;
+	serde_json::from_slice(&json)
+		.map_err(|e| EncryptedFieldError::Json(e.to_string()))
+}
+
+/// Serializes an encrypted field, use with
+/// `#[serde(with = "fire_crypto::serde_encrypt")]`.
+pub fn serialize<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+	T: Serialize + ?Sized,
+	S: Serializer,
+{
+	let s = encrypt_value(value).map_err(ser::Error::custom)?;
+	serializer.serialize_str(&s)
+}
+
+/// Deserializes an encrypted field, use with
+/// `#[serde(with = "fire_crypto::serde_encrypt")]`.
+pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+where
+	T: DeserializeOwned,
+	D: Deserializer<'de>,
+{
+	let s: String = Deserialize::deserialize(deserializer)?;
+	decrypt_value(&s).map_err(de::Error::custom)
+}
+
+/// A value which gets encrypted when serialized and decrypted when
+/// deserialized, see the [module](self) documentation.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Encrypted<T>(pub T);
+
+impl<T> Encrypted<T> {
+	pub fn into_inner(self) -> T {
+		self.0
+	}
+}
+
+impl<T> Deref for Encrypted<T> {
+	type Target = T;
+
+	fn deref(&self) -> &T {
+		&self.0
+	}
+}
+
+impl<T> DerefMut for Encrypted<T> {
+	fn deref_mut(&mut self) -> &mut T {
+		&mut self.0
+	}
+}
+
+impl<T> From<T> for Encrypted<T> {
+	fn from(value: T) -> Self {
+		Self(value)
+	}
+}
+
+impl<T> fmt::Debug for Encrypted<T> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str("Encrypted")
+	}
+}
+
+impl<T: Serialize> Serialize for Encrypted<T> {
+	fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+	where
+		S: Serializer,
+	{
+		serialize(&self.0, serializer)
+	}
+}
+
+impl<'de, T: DeserializeOwned> Deserialize<'de> for Encrypted<T> {
+	fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+	where
+		D: Deserializer<'de>,
+	{
+		deserialize(deserializer).map(Self)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+	use crate::cipher::{Keypair, Nonce};
+
+	use std::collections::BTreeMap;
+
+	use serde_json::{json, Value};
+
+	fn keyring_ctx() -> EncryptionContext {
+		let mut keyring = Keyring::new();
+		keyring.generate_key();
+		EncryptionContext::keyring(Arc::new(keyring))
+	}
+
+	#[test]
+	fn adapter_functions() {
+		let ctx = keyring_ctx();
+
+		let mut record = BTreeMap::new();
+		record.insert("iban", vec![1u8, 2, 3]);
+
+		let value = ctx
+			.scope(|| serialize(&record, serde_json::value::Serializer))
+			.unwrap();
+		let s = value.as_str().unwrap();
+		assert!(URL_SAFE_NO_PAD.decode(s).is_ok());
+
+		let back: BTreeMap<String, Vec<u8>> =
+			ctx.scope(|| deserialize(&value)).unwrap();
+		assert_eq!(back["iban"], [1, 2, 3]);
+	}
+
+	#[test]
+	fn wrapper_in_record() {
+		let ctx = keyring_ctx();
+
+		let mut record = BTreeMap::new();
+		record.insert("name", Encrypted(json!({ "first": "Alice" })));
+		let s = ctx.scope(|| serde_json::to_string(&record)).unwrap();
+		assert!(!s.contains("Alice"));
+
+		let record: BTreeMap<String, Encrypted<Value>> =
+			ctx.scope(|| serde_json::from_str(&s)).unwrap();
+		assert_eq!(record["name"]["first"], "Alice");
+
+		// another keyring can't decrypt
+		let err = keyring_ctx()
+			.scope(|| {
+				serde_json::from_str::<BTreeMap<String, Encrypted<Value>>>(&s)
+			})
+			.unwrap_err();
+		assert!(err.to_string().contains("Keyring(MacNotEqual)"));
+	}
+
+	#[test]
+	fn owned_deserializers() {
+		let ctx = keyring_ctx();
+		let s = ctx
+			.scope(|| serde_json::to_string(&Encrypted("secret".to_string())))
+			.unwrap();
+
+		let value: Value = serde_json::from_str(&s).unwrap();
+		let secret: Encrypted<String> =
+			ctx.scope(|| serde_json::from_value(value)).unwrap();
+		assert_eq!(secret.0, "secret");
+
+		let secret: Encrypted<String> =
+			ctx.scope(|| serde_json::from_reader(s.as_bytes())).unwrap();
+		assert_eq!(secret.0, "secret");
+	}
+
+	#[test]
+	fn errors() {
+		let s = keyring_ctx()
+			.scope(|| serde_json::to_string(&Encrypted(1u32)))
+			.unwrap();
+
+		let err = serde_json::from_str::<Encrypted<u32>>(&s).unwrap_err();
+		assert!(err.to_string().contains("NoEncryptionContext"));
+		assert!(serde_json::to_string(&Encrypted(1u32)).is_err());
+
+		let err = keyring_ctx()
+			.scope(|| serde_json::from_str::<Encrypted<u32>>("\"a+/\""))
+			.unwrap_err();
+		assert!(err.to_string().contains("InvalidBase64"));
+	}
+
+	#[test]
+	fn sync_key_and_nesting() {
+		let alice = Keypair::new();
+		let bob = Keypair::new();
+		let nonce = Nonce::new();
+		let send = alice
+			.diffie_hellman(bob.public())
+			.to_key(nonce.clone())
+			.into_sync();
+		let recv = bob.diffie_hellman(alice.public()).to_key(nonce).into_sync();
+
+		let send = EncryptionContext::sync_key(Arc::new(send));
+		let recv = EncryptionContext::sync_key(Arc::new(recv));
+		let keyring = keyring_ctx();
+
+		let (a, b) = send.scope(|| {
+			let a = serde_json::to_string(&Encrypted("a")).unwrap();
+			// the inner scope wins
+			let b = keyring
+				.scope(|| serde_json::to_string(&Encrypted("b")))
+				.unwrap();
+			(a, b)
+		});
+
+		let a: Encrypted<String> =
+			recv.scope(|| serde_json::from_str(&a)).unwrap();
+		assert_eq!(a.0, "a");
+		let b: Encrypted<String> =
+			keyring.scope(|| serde_json::from_str(&b)).unwrap();
+		assert_eq!(b.0, "b");
+	}
+}
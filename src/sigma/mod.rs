@@ -0,0 +1,486 @@
+//! An authenticated key exchange (SIGMA-I).
+//!
+//! Both sides generate an [`EphemeralKeypair`] and sign the exchanged
+//! ephemeral public keys with their long term [`signature::Keypair`].
+//! Additionally each side proves knowledge of the shared secret with a
+//! mac over its identity. The identities are encrypted so a passive
+//! observer does not learn who talks to whom.
+//!
+//! The handshake consists of three messages:
+//! 1. initiator -> responder: ephemeral key
+//! 2. responder -> initiator: ephemeral key, encrypted identity, signature
+//!    and mac
+//! 3. initiator -> responder: encrypted identity, signature and mac
+//!
+//! ## Example
+//! ```
+//! use fire_crypto::signature::Keypair;
+//! use fire_crypto::sigma::{Initiator, Responder};
+//!
+//! let alice = Keypair::new();
+//! let bob = Keypair::new();
+//!
+//! let (initiator, msg1) = Initiator::new();
+//! let (responder, msg2) = Responder::new(&bob, &msg1).unwrap();
+//! let (mut alice_session, msg3) = initiator.finish(&alice, &msg2).unwrap();
+//! let mut bob_session = responder.finish(&msg3).unwrap();
+//!
+//! // both sides learn the verified identity of the other
+//! assert_eq!(alice_session.peer, *bob.public());
+//! assert_eq!(bob_session.peer, *alice.public());
+//!
+//! let mut msg = *b"hey bob";
+//! let mac = alice_session.send_key.encrypt(&mut msg);
+//! bob_session.receive_key.decrypt(&mut msg, &mac).unwrap();
+//! assert_eq!(&msg, b"hey bob");
+//! ```
+
+use crate::cipher::{
+	self, EphemeralKeypair, Key, Mac, NonContributory, Nonce, SharedSecret,
+};
+use crate::hash::Hasher;
+use crate::signature::{self, Signature};
+
+use std::convert::{TryFrom, TryInto};
+use std::error::Error;
+use std::fmt;
+
+use zeroize::Zeroize;
+
+const KDF_KEY: &[u8] = b"fire-crypto sigma";
+const INITIATOR: &[u8] = b"fire-crypto sigma initiator";
+const RESPONDER: &[u8] = b"fire-crypto sigma responder";
+
+const PUBLIC_KEY_LEN: usize = 32;
+const MAC_LEN: usize = 32;
+/// public key | signature | mac
+const IDENTITY_LEN: usize = PUBLIC_KEY_LEN + 64 + MAC_LEN;
+
+/// The length of the first message.
+pub const MESSAGE1_LEN: usize = PUBLIC_KEY_LEN;
+/// The length of the second message.
+pub const MESSAGE2_LEN: usize = PUBLIC_KEY_LEN + Mac::LEN + IDENTITY_LEN;
+/// The length of the third message.
+pub const MESSAGE3_LEN: usize = Mac::LEN + IDENTITY_LEN;
+
+/// All keys derived from the ephemeral diffie hellman exchange.
+struct Keys {
+	mac: [u8; 32],
+	initiator: Key,
+	responder: Key,
+	initiator_to_responder: Key,
+	responder_to_initiator: Key,
+}
+
+impl Keys {
+	fn derive(
+		secret: &SharedSecret,
+		initiator: &cipher::PublicKey,
+		responder: &cipher::PublicKey,
+	) -> Self {
+		let derive = |label: &[u8]| {
+			let mut hasher = Hasher::new_keyed(KDF_KEY);
+			hasher.update(secret.as_slice());
+			hasher.update(initiator);
+			hasher.update(responder);
+			hasher.update(label);
+			hasher.finalize().to_bytes()
+		};
+
+		let key = |label: &[u8]| {
+			let mut bytes = derive(label);
+			let key = Key::from_secret(
+				bytes[..32].try_into().unwrap(),
+				Nonce::from_slice(&bytes[32..56]),
+			);
+			bytes.zeroize();
+			key
+		};
+
+		let mut mac_bytes = derive(b"mac");
+		let mac = mac_bytes[..32].try_into().unwrap();
+		mac_bytes.zeroize();
+
+		Self {
+			mac,
+			initiator: key(b"initiator handshake"),
+			responder: key(b"responder handshake"),
+			initiator_to_responder: key(b"initiator to responder"),
+			responder_to_initiator: key(b"responder to initiator"),
+		}
+	}
+
+	fn mac(&self, label: &[u8], identity: &signature::PublicKey) -> [u8; 32] {
+		let mut hasher = Hasher::new_keyed(self.mac);
+		hasher.update(label);
+		hasher.update(identity);
+		hasher.finalize().to_bytes()[..MAC_LEN].try_into().unwrap()
+	}
+}
+
+impl Drop for Keys {
+	fn drop(&mut self) {
+		self.mac.zeroize();
+	}
+}
+
+fn signed_data(
+	label: &[u8],
+	initiator: &cipher::PublicKey,
+	responder: &cipher::PublicKey,
+) -> Vec<u8> {
+	let mut data = label.to_vec();
+	data.extend_from_slice(initiator.as_ref());
+	data.extend_from_slice(responder.as_ref());
+	data
+}
+
+/// Creates the encrypted identity part of message two or three.
+fn seal_identity(
+	keypair: &signature::Keypair,
+	label: &[u8],
+	keys: &Keys,
+	key: &mut Key,
+	initiator: &cipher::PublicKey,
+	responder: &cipher::PublicKey,
+) -> Vec<u8> {
+	let signature = keypair.sign(signed_data(label, initiator, responder));
+
+	let mut identity = Vec::with_capacity(IDENTITY_LEN);
+	identity.extend_from_slice(keypair.public().as_ref());
+	identity.extend_from_slice(&signature.to_bytes());
+	identity.extend_from_slice(&keys.mac(label, keypair.public()));
+
+	let mac = key.encrypt(&mut identity);
+
+	let mut bytes = mac.into_bytes().to_vec();
+	bytes.append(&mut identity);
+	bytes
+}
+
+/// Decrypts and verifies the identity part of message two or three.
+fn open_identity(
+	bytes: &[u8],
+	label: &[u8],
+	keys: &Keys,
+	key: &mut Key,
+	initiator: &cipher::PublicKey,
+	responder: &cipher::PublicKey,
+) -> Result<signature::PublicKey, SigmaError> {
+	let mac = Mac::from_slice(&bytes[..Mac::LEN]);
+	let mut identity = bytes[Mac::LEN..].to_vec();
+	key.decrypt(&mut identity, &mac)
+		.map_err(|_| SigmaError::MacNotEqual)?;
+
+	let peer = signature::PublicKey::try_from(&identity[..PUBLIC_KEY_LEN])
+		.map_err(|_| SigmaError::InvalidSignature)?;
+	let signature =
+		Signature::try_from(&identity[PUBLIC_KEY_LEN..PUBLIC_KEY_LEN + 64])
+			.unwrap();
+
+	if !peer.verify(signed_data(label, initiator, responder), &signature) {
+		return Err(SigmaError::InvalidSignature);
+	}
+
+	if !crate::constant_time_eq(
+		&identity[PUBLIC_KEY_LEN + 64..],
+		&keys.mac(label, &peer),
+	) {
+		return Err(SigmaError::MacNotEqual);
+	}
+
+	Ok(peer)
+}
+
+/// The result of a successful handshake.
+#[derive(Debug)]
+pub struct Session {
+	/// The verified identity of the other side.
+	///
+	/// You still need to decide if you trust this key.
+	pub peer: signature::PublicKey,
+	/// Encrypts messages to the other side.
+	pub send_key: Key,
+	/// Decrypts messages from the other side.
+	pub receive_key: Key,
+}
+
+/// The side which starts the handshake.
+#[derive(Debug)]
+pub struct Initiator {
+	ephemeral: EphemeralKeypair,
+}
+
+impl Initiator {
+	/// Returns the initiator and the first message which needs to be
+	/// sent to the responder.
+	pub fn new() -> (Self, Vec<u8>) {
+		let ephemeral = EphemeralKeypair::new();
+		let msg = ephemeral.public().as_ref().to_vec();
+
+		(Self { ephemeral }, msg)
+	}
+
+	/// Verifies the second message and returns the session and the third
+	/// message which needs to be sent to the responder.
+	pub fn finish(
+		self,
+		keypair: &signature::Keypair,
+		msg: &[u8],
+	) -> Result<(Session, Vec<u8>), SigmaError> {
+		if msg.len() != MESSAGE2_LEN {
+			return Err(SigmaError::InvalidLength);
+		}
+
+		let initiator = self.ephemeral.public().clone();
+		let responder =
+			cipher::PublicKey::try_from(&msg[..PUBLIC_KEY_LEN]).unwrap();
+
+		let secret = self.ephemeral.try_diffie_hellman(&responder)?;
+		let keys = Keys::derive(&secret, &initiator, &responder);
+
+		let peer = open_identity(
+			&msg[PUBLIC_KEY_LEN..],
+			RESPONDER,
+			&keys,
+			&mut keys.responder.dublicate(),
+			&initiator,
+			&responder,
+		)?;
+
+		let msg = seal_identity(
+			keypair,
+			INITIATOR,
+			&keys,
+			&mut keys.initiator.dublicate(),
+			&initiator,
+			&responder,
+		);
+
+		let session = Session {
+			peer,
+			send_key: keys.initiator_to_responder.dublicate(),
+			receive_key: keys.responder_to_initiator.dublicate(),
+		};
+
+		Ok((session, msg))
+	}
+}
+
+/// The side which responds to a handshake.
+pub struct Responder {
+	ephemeral: cipher::PublicKey,
+	initiator: cipher::PublicKey,
+	keys: Keys,
+}
+
+impl Responder {
+	/// Responds to the first message returning the responder and the
+	/// second message which needs to be sent to the initiator.
+	pub fn new(
+		keypair: &signature::Keypair,
+		msg: &[u8],
+	) -> Result<(Self, Vec<u8>), SigmaError> {
+		if msg.len() != MESSAGE1_LEN {
+			return Err(SigmaError::InvalidLength);
+		}
+
+		let initiator = cipher::PublicKey::try_from(msg).unwrap();
+
+		let ephemeral = EphemeralKeypair::new();
+		let responder = ephemeral.public().clone();
+		let secret = ephemeral.try_diffie_hellman(&initiator)?;
+		let keys = Keys::derive(&secret, &initiator, &responder);
+
+		let mut msg = responder.as_ref().to_vec();
+		msg.extend(seal_identity(
+			keypair,
+			RESPONDER,
+			&keys,
+			&mut keys.responder.dublicate(),
+			&initiator,
+			&responder,
+		));
+
+		let me = Self {
+			ephemeral: responder,
+			initiator,
+			keys,
+		};
+
+		Ok((me, msg))
+	}
+
+	/// Verifies the third message and returns the session.
+	pub fn finish(self, msg: &[u8]) -> Result<Session, SigmaError> {
+		if msg.len() != MESSAGE3_LEN {
+			return Err(SigmaError::InvalidLength);
+		}
+
+		let peer = open_identity(
+			msg,
+			INITIATOR,
+			&self.keys,
+			&mut self.keys.initiator.dublicate(),
+			&self.initiator,
+			&self.ephemeral,
+		)?;
+
+		Ok(Session {
+			peer,
+			send_key: self.keys.responder_to_initiator.dublicate(),
+			receive_key: self.keys.initiator_to_responder.dublicate(),
+		})
+	}
+}
+
+impl fmt::Debug for Responder {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("Responder")
+			.field("ephemeral", &self.ephemeral)
+			.field("initiator", &self.initiator)
+			.finish()
+	}
+}
+
+/// Get's returned if the handshake failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SigmaError {
+	/// The message has the wrong length.
+	InvalidLength,
+	/// An ephemeral key was a low order point.
+	NonContributory,
+	/// The message was modified or the other side does not know the
+	/// shared secret.
+	MacNotEqual,
+	/// The signature over the ephemeral keys is not valid.
+	InvalidSignature,
+}
+
+impl From<NonContributory> for SigmaError {
+	fn from(_: NonContributory) -> Self {
+		Self::NonContributory
+	}
+}
+
+impl fmt::Display for SigmaError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		fmt::Debug::fmt(self, f)
+	}
+}
+
+impl Error for SigmaError {}
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+
+	fn handshake(
+		alice: &signature::Keypair,
+		bob: &signature::Keypair,
+	) -> (Session, Session) {
+		let (initiator, msg1) = Initiator::new();
+		assert_eq!(msg1.len(), MESSAGE1_LEN);
+		let (responder, msg2) = Responder::new(bob, &msg1).unwrap();
+		assert_eq!(msg2.len(), MESSAGE2_LEN);
+		let (a, msg3) = initiator.finish(alice, &msg2).unwrap();
+		assert_eq!(msg3.len(), MESSAGE3_LEN);
+		let b = responder.finish(&msg3).unwrap();
+
+		(a, b)
+	}
+
+	#[test]
+	fn directional_keys() {
+		let alice = signature::Keypair::new();
+		let bob = signature::Keypair::new();
+		let (mut a, mut b) = handshake(&alice, &bob);
+
+		assert_eq!(a.peer, *bob.public());
+		assert_eq!(b.peer, *alice.public());
+
+		let mut msg = *b"to bob";
+		let mac = a.send_key.encrypt(&mut msg);
+		b.receive_key.decrypt(&mut msg, &mac).unwrap();
+		assert_eq!(&msg, b"to bob");
+
+		let mut msg = *b"to alice";
+		let mac = b.send_key.encrypt(&mut msg);
+		let mut copy = msg;
+		b.receive_key.decrypt(&mut copy, &mac).unwrap_err();
+		a.receive_key.decrypt(&mut msg, &mac).unwrap();
+		assert_eq!(&msg, b"to alice");
+	}
+
+	#[test]
+	fn modified_messages() {
+		let alice = signature::Keypair::new();
+		let bob = signature::Keypair::new();
+
+		// a man in the middle replaces the ephemeral key of the responder
+		let (initiator, msg1) = Initiator::new();
+		let (_, mut msg2) = Responder::new(&bob, &msg1).unwrap();
+		let mallory = EphemeralKeypair::new();
+		msg2[..32].copy_from_slice(mallory.public().as_ref());
+		assert_eq!(
+			initiator.finish(&alice, &msg2).unwrap_err(),
+			SigmaError::MacNotEqual
+		);
+
+		let (initiator, msg1) = Initiator::new();
+		let (responder, msg2) = Responder::new(&bob, &msg1).unwrap();
+		let (_, mut msg3) = initiator.finish(&alice, &msg2).unwrap();
+		msg3[20] ^= 1;
+		assert_eq!(
+			responder.finish(&msg3).unwrap_err(),
+			SigmaError::MacNotEqual
+		);
+
+		assert_eq!(
+			Responder::new(&bob, &[0; 32]).unwrap_err(),
+			SigmaError::NonContributory
+		);
+		assert_eq!(
+			Responder::new(&bob, &[1; 31]).unwrap_err(),
+			SigmaError::InvalidLength
+		);
+	}
+
+	#[test]
+	fn replayed_signature() {
+		let alice = signature::Keypair::new();
+		let bob = signature::Keypair::new();
+
+		// a signature of bob from an earlier handshake
+		let old = signed_data(
+			RESPONDER,
+			EphemeralKeypair::new().public(),
+			EphemeralKeypair::new().public(),
+		);
+		let old_signature = bob.sign(old);
+
+		// mallory responds to alice pretending to be bob
+		let (initiator, msg1) = Initiator::new();
+		let initiator_key = cipher::PublicKey::try_from(&msg1[..]).unwrap();
+		let ephemeral = EphemeralKeypair::new();
+		let responder_key = ephemeral.public().clone();
+		let secret = ephemeral.try_diffie_hellman(&initiator_key).unwrap();
+		let keys = Keys::derive(&secret, &initiator_key, &responder_key);
+
+		let mut identity = bob.public().as_ref().to_vec();
+		identity.extend_from_slice(&old_signature.to_bytes());
+		identity.extend_from_slice(&keys.mac(RESPONDER, bob.public()));
+		let mac = keys.responder.dublicate().encrypt(&mut identity);
+
+		let mut msg2 = responder_key.as_ref().to_vec();
+		msg2.extend_from_slice(&mac.into_bytes());
+		msg2.extend(identity);
+
+		assert_eq!(
+			initiator.finish(&alice, &msg2).unwrap_err(),
+			SigmaError::InvalidSignature
+		);
+	}
+}
@@ -0,0 +1,497 @@
+use super::{PublicKey, Signature};
+
+use std::error::Error;
+use std::fmt;
+
+use curve25519_dalek::constants::ED25519_BASEPOINT_POINT;
+use curve25519_dalek::edwards::{
+	CompressedEdwardsY, EdwardsPoint, VartimeEdwardsPrecomputation,
+};
+use curve25519_dalek::traits::{
+	IsIdentity, VartimeMultiscalarMul, VartimePrecomputedMultiscalarMul,
+};
+use curve25519_dalek::Scalar;
+
+use sha2::{Digest, Sha512};
+
+/// Get's returned if at least one signature in a batch is invalid.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchError {
+	invalid: Vec<usize>,
+}
+
+impl BatchError {
+	/// Returns the indexes of the invalid entries in ascending order.
+	pub fn invalid(&self) -> &[usize] {
+		&self.invalid
+	}
+
+	pub fn into_invalid(self) -> Vec<usize> {
+		self.invalid
+	}
+}
+
+impl fmt::Display for BatchError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{} invalid signatures", self.invalid.len())
+	}
+}
+
+impl Error for BatchError {}
+
+/// A signature which passed the checks that don't need the equation.
+struct Entry {
+	index: usize,
+	r: EdwardsPoint,
+	s: Scalar,
+	a: EdwardsPoint,
+	k: Scalar,
+}
+
+impl Entry {
+	/// Returns `None` if the signature can't be valid, the same checks as
+	/// `verify_strict` are done.
+	fn new(
+		index: usize,
+		public_key: &PublicKey,
+		a: &EdwardsPoint,
+		msg: &[u8],
+		signature: &Signature,
+	) -> Option<Self> {
+		let bytes = signature.to_bytes();
+		let r =
+			CompressedEdwardsY(bytes[..32].try_into().unwrap()).decompress()?;
+		let s = Option::from(Scalar::from_canonical_bytes(
+			bytes[32..].try_into().unwrap(),
+		))?;
+		if r.is_small_order() || a.is_small_order() {
+			return None;
+		}
+
+		let hash = Sha512::new()
+			.chain_update(&bytes[..32])
+			.chain_update(public_key)
+			.chain_update(msg)
+			.finalize();
+		let k = Scalar::from_bytes_mod_order_wide(&hash.into());
+
+		Some(Self {
+			index,
+			r,
+			s,
+			a: *a,
+			k,
+		})
+	}
+
+	/// Checks `[8]([s]B - R - [k]A) == 0`, the batch equation with a
+	/// single entry.
+	fn verify(&self) -> bool {
+		let p = EdwardsPoint::vartime_double_scalar_mul_basepoint(
+			&self.k, &-self.a, &self.s,
+		);
+		(p - self.r).mul_by_cofactor().is_identity()
+	}
+}
+
+fn decompress(public_key: &PublicKey) -> EdwardsPoint {
+	CompressedEdwardsY(public_key.to_bytes())
+		.decompress()
+		// a public key is always a valid point
+		.unwrap()
+}
+
+/// A random 128 bit scalar.
+fn random_scalar() -> Scalar {
+	let mut bytes = [0u8; 32];
+	crate::fill_random(&mut bytes[..16]);
+	Scalar::from_bytes_mod_order(bytes)
+}
+
+/// Verifies the entries together, if that fails the entries get split
+/// until the invalid ones are found.
+fn bisect<F>(entries: &[Entry], batch: &F, invalid: &mut Vec<usize>)
+where
+	F: Fn(&[Entry], &[Scalar]) -> bool,
+{
+	match entries {
+		[] => {}
+		[entry] => {
+			if !entry.verify() {
+				invalid.push(entry.index);
+			}
+		}
+		entries => {
+			let z: Vec<_> = entries.iter().map(|_| random_scalar()).collect();
+			if batch(entries, &z) {
+				return;
+			}
+
+			let (a, b) = entries.split_at(entries.len() / 2);
+			bisect(a, batch, invalid);
+			bisect(b, batch, invalid);
+		}
+	}
+}
+
+/// Checks `[8] Σ z_i ([s_i]B - R_i - [k_i]A_i) == 0`.
+///
+/// Without the cofactor a small order component in one entry could be
+/// cancelled by the random `z_i`, so the result would depend on the
+/// other entries and on chance.
+fn batch_equation(entries: &[Entry], z: &[Scalar]) -> bool {
+	let b_scalar: Scalar = entries.iter().zip(z).map(|(e, z)| z * e.s).sum();
+
+	let scalars = std::iter::once(b_scalar)
+		.chain(z.iter().map(|z| -z))
+		.chain(entries.iter().zip(z).map(|(e, z)| -(z * e.k)));
+	let points = std::iter::once(ED25519_BASEPOINT_POINT)
+		.chain(entries.iter().map(|e| e.r))
+		.chain(entries.iter().map(|e| e.a));
+
+	EdwardsPoint::vartime_multiscalar_mul(scalars, points)
+		.mul_by_cofactor()
+		.is_identity()
+}
+
+fn into_result(mut invalid: Vec<usize>) -> Result<(), BatchError> {
+	if invalid.is_empty() {
+		Ok(())
+	} else {
+		invalid.sort_unstable();
+		Err(BatchError { invalid })
+	}
+}
+
+/// Verifies multiple signatures at once.
+///
+/// If some signatures are invalid, the error contains their indexes.
+/// Whether an entry is valid does not depend on the other entries.
+///
+/// ## Difference to [`PublicKey::verify`]
+/// The equation is multiplied by the cofactor 8, so small order components
+/// of `R` or of the public key are ignored. A signature which
+/// [`PublicKey::verify`] only rejects because of such a component is
+/// accepted here. A correct signer never creates such signatures, all
+/// other checks are the same (canonical `s`, `R` and the public key are
+/// not of small order).
+pub fn verify_batch<M>(
+	items: &[(&PublicKey, M, &Signature)],
+) -> Result<(), BatchError>
+where
+	M: AsRef<[u8]>,
+{
+	let mut invalid = vec![];
+	let mut entries = Vec::with_capacity(items.len());
+	for (i, (public_key, msg, signature)) in items.iter().enumerate() {
+		let a = decompress(public_key);
+		match Entry::new(i, public_key, &a, msg.as_ref(), signature) {
+			Some(entry) => entries.push(entry),
+			None => invalid.push(i),
+		}
+	}
+
+	bisect(&entries, &batch_equation, &mut invalid);
+
+	into_result(invalid)
+}
+
+/// Verifies signatures of a single public key, with precomputed tables
+/// for the key.
+///
+/// Creating the verifier takes some time, so it should be reused.
+pub struct PrecomputedVerifier {
+	public_key: PublicKey,
+	point: EdwardsPoint,
+	/// `B` and `-A`
+	table: VartimeEdwardsPrecomputation,
+}
+
+impl PrecomputedVerifier {
+	pub fn new(public_key: PublicKey) -> Self {
+		let point = decompress(&public_key);
+		let table = VartimeEdwardsPrecomputation::new([
+			ED25519_BASEPOINT_POINT,
+			-point,
+		]);
+
+		Self {
+			public_key,
+			point,
+			table,
+		}
+	}
+
+	pub fn public_key(&self) -> &PublicKey {
+		&self.public_key
+	}
+
+	/// Returns the same result as [`PublicKey::verify`].
+	pub fn verify(&self, msg: impl AsRef<[u8]>, signature: &Signature) -> bool {
+		let entry = Entry::new(
+			0,
+			&self.public_key,
+			&self.point,
+			msg.as_ref(),
+			signature,
+		);
+
+		entry.map_or(false, |e| {
+			self.table.vartime_multiscalar_mul([e.s, e.k]) == e.r
+		})
+	}
+
+	/// Verifies multiple signatures at once, see [`verify_batch`].
+	///
+	/// Like [`verify_batch`] this can accept signatures which
+	/// [`PrecomputedVerifier::verify`] rejects.
+	pub fn verify_batch<M>(
+		&self,
+		items: &[(M, &Signature)],
+	) -> Result<(), BatchError>
+	where
+		M: AsRef<[u8]>,
+	{
+		let mut invalid = vec![];
+		let mut entries = Vec::with_capacity(items.len());
+		for (i, (msg, signature)) in items.iter().enumerate() {
+			let entry = Entry::new(
+				i,
+				&self.public_key,
+				&self.point,
+				msg.as_ref(),
+				signature,
+			);
+			match entry {
+				Some(entry) => entries.push(entry),
+				None => invalid.push(i),
+			}
+		}
+
+		// the public key is the same for every entry, so its scalars
+		// can be added up
+		let batch = |entries: &[Entry], z: &[Scalar]| {
+			let b_scalar: Scalar =
+				entries.iter().zip(z).map(|(e, z)| z * e.s).sum();
+			let a_scalar: Scalar =
+				entries.iter().zip(z).map(|(e, z)| z * e.k).sum();
+
+			self.table
+				.vartime_mixed_multiscalar_mul(
+					[b_scalar, a_scalar],
+					z.iter().map(|z| -z),
+					entries.iter().map(|e| e.r),
+				)
+				.mul_by_cofactor()
+				.is_identity()
+		};
+		bisect(&entries, &batch, &mut invalid);
+
+		into_result(invalid)
+	}
+}
+
+impl fmt::Debug for PrecomputedVerifier {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("PrecomputedVerifier")
+			.field("public_key", &self.public_key)
+			.finish()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+	use crate::signature::Keypair;
+
+	use curve25519_dalek::constants::EIGHT_TORSION;
+
+	/// Signs with `R = [r]B + torsion` where `[a]B` is the prime order
+	/// part of the public key, returns the signature and `k`.
+	fn sign_raw(
+		a: &Scalar,
+		public_key: &PublicKey,
+		torsion: &EdwardsPoint,
+		msg: &[u8],
+	) -> (Signature, Scalar) {
+		let r = random_scalar();
+		let big_r = (ED25519_BASEPOINT_POINT * r + torsion).compress();
+		let hash = Sha512::new()
+			.chain_update(big_r.as_bytes())
+			.chain_update(public_key)
+			.chain_update(msg)
+			.finalize();
+		let k = Scalar::from_bytes_mod_order_wide(&hash.into());
+
+		let mut bytes = [0u8; 64];
+		bytes[..32].copy_from_slice(big_r.as_bytes());
+		bytes[32..].copy_from_slice((r + k * a).as_bytes());
+		(Signature::from_slice(&bytes), k)
+	}
+
+	fn public_key(point: EdwardsPoint) -> PublicKey {
+		PublicKey::from_slice(point.compress().as_bytes())
+	}
+
+	fn signed(n: usize) -> Vec<(Keypair, Vec<u8>, Signature)> {
+		(0..n)
+			.map(|i| {
+				let keypair = Keypair::new();
+				let msg = format!("log line {}", i).into_bytes();
+				let signature = keypair.sign(&msg);
+				(keypair, msg, signature)
+			})
+			.collect()
+	}
+
+	fn items(
+		signed: &[(Keypair, Vec<u8>, Signature)],
+	) -> Vec<(&PublicKey, &[u8], &Signature)> {
+		signed
+			.iter()
+			.map(|(k, m, s)| (k.public(), m.as_slice(), s))
+			.collect()
+	}
+
+	#[test]
+	fn batch() {
+		let signed = signed(64);
+		verify_batch(&items(&signed)).unwrap();
+		verify_batch::<&[u8]>(&[]).unwrap();
+
+		let mut items = items(&signed);
+		items[3].1 = b"modified";
+		items[40].1 = b"modified";
+		// signature of another key
+		items[63].0 = signed[0].0.public();
+		let err = verify_batch(&items).unwrap_err();
+		assert_eq!(err.invalid(), [3, 40, 63]);
+
+		for (i, (public_key, msg, signature)) in items.iter().enumerate() {
+			let valid = !err.invalid().contains(&i);
+			assert_eq!(public_key.verify(msg, signature), valid);
+		}
+	}
+
+	#[test]
+	fn rejected_like_verify_strict() {
+		let keypair = Keypair::new();
+		let msg = b"msg";
+		let mut bytes = keypair.sign(msg).to_bytes();
+
+		// non canonical s
+		bytes[63] |= 0xf0;
+		let non_canonical = Signature::from_slice(&bytes);
+
+		// small order R (the identity)
+		let mut bytes = [0u8; 64];
+		bytes[0] = 1;
+		let small_order_r = Signature::from_slice(&bytes);
+
+		let public_key = keypair.public();
+		let items = [
+			(public_key, &msg[..], &non_canonical),
+			(public_key, &msg[..], &small_order_r),
+		];
+		assert_eq!(verify_batch(&items).unwrap_err().invalid(), [0, 1]);
+		assert!(!public_key.verify(msg, &non_canonical));
+		assert!(!public_key.verify(msg, &small_order_r));
+
+		let verifier = PrecomputedVerifier::new(public_key.clone());
+		assert!(!verifier.verify(msg, &non_canonical));
+		assert!(!verifier.verify(msg, &small_order_r));
+	}
+
+	#[test]
+	fn precomputed() {
+		let keypair = Keypair::new();
+		let verifier = PrecomputedVerifier::new(keypair.public().clone());
+
+		let msgs: Vec<_> = (0..20).map(|i| format!("log line {}", i)).collect();
+		let signatures: Vec<_> = msgs.iter().map(|m| keypair.sign(m)).collect();
+
+		for (msg, signature) in msgs.iter().zip(&signatures) {
+			assert!(verifier.verify(msg, signature));
+			assert!(!verifier.verify("other", signature));
+		}
+
+		let mut items: Vec<_> =
+			msgs.iter().map(String::as_str).zip(&signatures).collect();
+		verifier.verify_batch(&items).unwrap();
+
+		items[7].0 = "modified";
+		let other = Keypair::new().sign(items[12].0);
+		items[12].1 = &other;
+		let err = verifier.verify_batch(&items).unwrap_err();
+		assert_eq!(err.invalid(), [7, 12]);
+	}
+
+	#[test]
+	fn torsion_r() {
+		let a = random_scalar();
+		let public_key = public_key(ED25519_BASEPOINT_POINT * a);
+		let msg = b"msg";
+
+		// only passes the equation multiplied by the cofactor
+		let (torsion, _) = sign_raw(&a, &public_key, &EIGHT_TORSION[1], msg);
+		let (valid, _) = sign_raw(&a, &public_key, &EIGHT_TORSION[0], msg);
+		assert!(!public_key.verify(msg, &torsion));
+		assert!(public_key.verify(msg, &valid));
+
+		let items = [
+			(&public_key, &msg[..], &valid),
+			(&public_key, &msg[..], &torsion),
+			(&public_key, &b"modified"[..], &torsion),
+			(&public_key, &msg[..], &valid),
+		];
+		assert_eq!(verify_batch(&items).unwrap_err().invalid(), [2]);
+		verify_batch(&items[1..2]).unwrap();
+
+		let verifier = PrecomputedVerifier::new(public_key.clone());
+		assert!(!verifier.verify(msg, &torsion));
+		let items = [(msg, &valid), (msg, &torsion)];
+		verifier.verify_batch(&items).unwrap();
+	}
+
+	#[test]
+	fn torsion_public_key() {
+		let a = random_scalar();
+		let t = EIGHT_TORSION[1];
+		let public_key = public_key(ED25519_BASEPOINT_POINT * a + t);
+		let msg = b"msg";
+
+		// R = [r]B - [k]T is accepted by verify_strict
+		let strict_valid = loop {
+			let j = (random_scalar().as_bytes()[0] & 7) as u64;
+			let torsion = -(t * Scalar::from(j));
+			let (signature, k) = sign_raw(&a, &public_key, &torsion, msg);
+			if (k.as_bytes()[0] & 7) as u64 == j {
+				break signature;
+			}
+		};
+		// R = [r]B is only accepted by verify_strict if k is a multiple of 8
+		let strict_invalid = loop {
+			let (signature, k) =
+				sign_raw(&a, &public_key, &EIGHT_TORSION[0], msg);
+			if k.as_bytes()[0] & 7 != 0 {
+				break signature;
+			}
+		};
+		assert!(public_key.verify(msg, &strict_valid));
+		assert!(!public_key.verify(msg, &strict_invalid));
+
+		let items = [
+			(&public_key, &msg[..], &strict_invalid),
+			(&public_key, &msg[..], &strict_valid),
+			(&public_key, &b"modified"[..], &strict_valid),
+		];
+		assert_eq!(verify_batch(&items).unwrap_err().invalid(), [2]);
+
+		let verifier = PrecomputedVerifier::new(public_key.clone());
+		assert!(verifier.verify(msg, &strict_valid));
+		assert!(!verifier.verify(msg, &strict_invalid));
+		let items = [(msg, &strict_valid), (msg, &strict_invalid)];
+		verifier.verify_batch(&items).unwrap();
+	}
+}
@@ -9,6 +9,11 @@ pub use public_key::PublicKey;
 mod signature;
 pub use signature::Signature;
 
+#[cfg(feature = "signature-batch")]
+mod batch;
+#[cfg(feature = "signature-batch")]
+pub use batch::{verify_batch, BatchError, PrecomputedVerifier};
+
 // TESTS
 
 #[cfg(test)]
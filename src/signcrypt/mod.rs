@@ -0,0 +1,275 @@
+//! Signcryption, encrypting a message to a recipient while proving who
+//! sent it.
+//!
+//! The message is signed together with the public keys of the sender,
+//! the recipient and the ephemeral key and then encrypted to the
+//! recipient. Because the recipient is part of the signed data, the
+//! recipient can't forward the signed message to someone else
+//! (surreptitious forwarding), the signature would not verify anymore.
+//!
+//! The identity of the sender is encrypted, only the recipient learns who
+//! sent the message.
+//!
+//! ## Example
+//! ```
+//! use fire_crypto::{cipher, signature, signcrypt};
+//!
+//! let alice = signature::Keypair::new();
+//! let bob = cipher::Keypair::new();
+//!
+//! let sealed = signcrypt::seal(&alice, bob.public(), b"hi bob").unwrap();
+//!
+//! let opened = signcrypt::open(&bob, &sealed).unwrap();
+//! assert_eq!(opened.sender, *alice.public());
+//! assert_eq!(opened.message, b"hi bob");
+//! ```
+
+use crate::cipher::{self, EphemeralKeypair, Key, Mac, NonContributory, Nonce};
+use crate::hash::Hasher;
+use crate::signature::{self, Signature};
+
+use std::convert::{TryFrom, TryInto};
+use std::error::Error;
+use std::fmt;
+
+use zeroize::Zeroize;
+
+const KDF_KEY: &[u8] = b"fire-crypto signcrypt";
+const SIGNATURE_CONTEXT: &[u8] = b"fire-crypto signcrypt signature";
+
+/// The number of bytes [`seal`] adds to the message.
+pub const OVERHEAD: usize = 32 + Mac::LEN + 32 + 64;
+
+/// A message opened with [`open`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Opened {
+	/// The sender, the signature was verified against this key.
+	///
+	/// You still need to decide if you trust this key.
+	pub sender: signature::PublicKey,
+	pub message: Vec<u8>,
+}
+
+fn signed_data(
+	sender: &signature::PublicKey,
+	recipient: &cipher::PublicKey,
+	ephemeral: &cipher::PublicKey,
+	msg: &[u8],
+) -> Vec<u8> {
+	let mut data = Vec::with_capacity(SIGNATURE_CONTEXT.len() + 96 + msg.len());
+	data.extend_from_slice(SIGNATURE_CONTEXT);
+	data.extend_from_slice(sender.as_ref());
+	data.extend_from_slice(recipient.as_ref());
+	data.extend_from_slice(ephemeral.as_ref());
+	data.extend_from_slice(msg);
+	data
+}
+
+fn derive_key(
+	secret: &cipher::SharedSecret,
+	recipient: &cipher::PublicKey,
+	ephemeral: &cipher::PublicKey,
+) -> Key {
+	let mut hasher = Hasher::new_keyed(KDF_KEY);
+	hasher.update(secret.as_slice());
+	hasher.update(ephemeral);
+	hasher.update(recipient);
+	let mut bytes = hasher.finalize().to_bytes();
+
+	// every ephemeral key is only used once so a fixed nonce is fine
+	let key = Key::from_secret(
+		bytes[..32].try_into().unwrap(),
+		Nonce::from_slice(&bytes[32..56]),
+	);
+	bytes.zeroize();
+
+	key
+}
+
+/// Encrypts `inner` to the recipient with an ephemeral key which
+/// the signature must already cover.
+fn encrypt(
+	ephemeral: EphemeralKeypair,
+	recipient: &cipher::PublicKey,
+	mut inner: Vec<u8>,
+) -> Result<Vec<u8>, NonContributory> {
+	let ephemeral_public = ephemeral.public().clone();
+	let secret = ephemeral.try_diffie_hellman(recipient)?;
+	let mut key = derive_key(&secret, recipient, &ephemeral_public);
+
+	let mac = key.encrypt(&mut inner);
+
+	let mut bytes = Vec::with_capacity(32 + Mac::LEN + inner.len());
+	bytes.extend_from_slice(ephemeral_public.as_ref());
+	bytes.extend_from_slice(&mac.into_bytes());
+	bytes.append(&mut inner);
+
+	Ok(bytes)
+}
+
+/// Signs the message with the key of the sender and encrypts it to the
+/// recipient.
+///
+/// Returns an error if the recipient is a low order point.
+pub fn seal(
+	sender: &signature::Keypair,
+	recipient: &cipher::PublicKey,
+	msg: &[u8],
+) -> Result<Vec<u8>, NonContributory> {
+	let ephemeral = EphemeralKeypair::new();
+
+	let signature = sender.sign(signed_data(
+		sender.public(),
+		recipient,
+		ephemeral.public(),
+		msg,
+	));
+
+	let mut inner = Vec::with_capacity(96 + msg.len());
+	inner.extend_from_slice(sender.public().as_ref());
+	inner.extend_from_slice(&signature.to_bytes());
+	inner.extend_from_slice(msg);
+
+	encrypt(ephemeral, recipient, inner)
+}
+
+/// Decrypts a message sealed to the recipient and verifies the signature
+/// of the sender.
+pub fn open(
+	recipient: &cipher::Keypair,
+	bytes: &[u8],
+) -> Result<Opened, SigncryptError> {
+	if bytes.len() < OVERHEAD {
+		return Err(SigncryptError::InvalidLength);
+	}
+
+	let ephemeral = cipher::PublicKey::try_from(&bytes[..32]).unwrap();
+	let mac = Mac::from_slice(&bytes[32..32 + Mac::LEN]);
+	let mut inner = bytes[32 + Mac::LEN..].to_vec();
+
+	let secret = recipient.try_diffie_hellman(&ephemeral)?;
+	let mut key = derive_key(&secret, recipient.public(), &ephemeral);
+	key.decrypt(&mut inner, &mac)
+		.map_err(|_| SigncryptError::MacNotEqual)?;
+
+	let sender = signature::PublicKey::try_from(&inner[..32])
+		.map_err(|_| SigncryptError::InvalidSignature)?;
+	let signature = Signature::try_from(&inner[32..96]).unwrap();
+	let message = inner.split_off(96);
+
+	let data = signed_data(&sender, recipient.public(), &ephemeral, &message);
+	if !sender.verify(data, &signature) {
+		return Err(SigncryptError::InvalidSignature);
+	}
+
+	Ok(Opened { sender, message })
+}
+
+/// Get's returned if a message could not be opened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SigncryptError {
+	/// The message is too short.
+	InvalidLength,
+	/// The ephemeral key was a low order point.
+	NonContributory,
+	/// The message was not encrypted to this recipient or was modified.
+	MacNotEqual,
+	/// The signature does not cover this sender, recipient and message.
+	InvalidSignature,
+}
+
+impl From<NonContributory> for SigncryptError {
+	fn from(_: NonContributory) -> Self {
+		Self::NonContributory
+	}
+}
+
+impl fmt::Display for SigncryptError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		fmt::Debug::fmt(self, f)
+	}
+}
+
+impl Error for SigncryptError {}
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+
+	#[test]
+	fn seal_open() {
+		let alice = signature::Keypair::new();
+		let bob = cipher::Keypair::new();
+
+		let sealed = seal(&alice, bob.public(), b"hello").unwrap();
+		assert_eq!(sealed.len(), OVERHEAD + 5);
+
+		let opened = open(&bob, &sealed).unwrap();
+		assert_eq!(opened.sender, *alice.public());
+		assert_eq!(opened.message, b"hello");
+
+		// empty messages are fine
+		let sealed = seal(&alice, bob.public(), b"").unwrap();
+		assert!(open(&bob, &sealed).unwrap().message.is_empty());
+
+		assert_eq!(
+			open(&bob, &sealed[..OVERHEAD - 1]).unwrap_err(),
+			SigncryptError::InvalidLength
+		);
+	}
+
+	#[test]
+	fn wrong_recipient_or_modified() {
+		let alice = signature::Keypair::new();
+		let bob = cipher::Keypair::new();
+		let carol = cipher::Keypair::new();
+
+		let mut sealed = seal(&alice, bob.public(), b"hello").unwrap();
+		assert_eq!(
+			open(&carol, &sealed).unwrap_err(),
+			SigncryptError::MacNotEqual
+		);
+
+		let last = sealed.len() - 1;
+		sealed[last] ^= 1;
+		assert_eq!(
+			open(&bob, &sealed).unwrap_err(),
+			SigncryptError::MacNotEqual
+		);
+
+		// a low order ephemeral key
+		let mut sealed = seal(&alice, bob.public(), b"hello").unwrap();
+		sealed[..32].copy_from_slice(&[0; 32]);
+		assert_eq!(
+			open(&bob, &sealed).unwrap_err(),
+			SigncryptError::NonContributory
+		);
+	}
+
+	#[test]
+	fn surreptitious_forwarding() {
+		let alice = signature::Keypair::new();
+		let bob = cipher::Keypair::new();
+		let carol = cipher::Keypair::new();
+
+		let sealed = seal(&alice, bob.public(), b"i love you").unwrap();
+
+		// bob decrypts the message and encrypts the signed content again
+		// to carol
+		let ephemeral = cipher::PublicKey::try_from(&sealed[..32]).unwrap();
+		let secret = bob.try_diffie_hellman(&ephemeral).unwrap();
+		let mut key = derive_key(&secret, bob.public(), &ephemeral);
+		let mac = Mac::from_slice(&sealed[32..48]);
+		let mut inner = sealed[48..].to_vec();
+		key.decrypt(&mut inner, &mac).unwrap();
+
+		let forwarded =
+			encrypt(EphemeralKeypair::new(), carol.public(), inner).unwrap();
+		assert_eq!(
+			open(&carol, &forwarded).unwrap_err(),
+			SigncryptError::InvalidSignature
+		);
+	}
+}
@@ -0,0 +1,536 @@
+//! Web push message encryption (RFC 8291) with vapid (RFC 8292).
+//!
+//! The payload is encrypted with the `aes128gcm` content coding
+//! (RFC 8188), using a key derived from a P-256 diffie hellman exchange
+//! with the key of the browser and the auth secret of the subscription.
+//!
+//! A [`RequestBuilder`] returns the headers and the body which need to be
+//! posted to the endpoint of the subscription, the http client is up to
+//! the caller.
+//!
+//! ## Example
+//! ```
+//! use fire_crypto::web_push::{RequestBuilder, Subscription, VapidKey};
+//!
+//! // the server key, the public key is passed to the browser
+//! let vapid = VapidKey::new();
+//!
+//! // received from the browser after `pushManager.subscribe`
+//! let subscription = Subscription::from_b64(
+//!     "https://push.example.net/push/JzLQ3raZJfFBR0aqvOMsLrt54w4rJUsV",
+//!     "BCVxsr7N_eNgVRqvHtD0zTZsEc6-VV-JvLexhqUzORcxaOzi6-AYWXvTBHm4bjyPjs7Vd8pZGH6SRpkNtoIAiw4",
+//!     "BTBZMqHH6r4Tts7J_aSIgg",
+//! )
+//! .unwrap();
+//!
+//! let mut builder = RequestBuilder::new(&subscription);
+//! builder.set_ttl(60);
+//! builder.set_vapid(&vapid, "mailto:push@example.com");
+//! let request = builder.build(b"Hello").unwrap();
+//!
+//! assert_eq!(request.endpoint, subscription.endpoint());
+//! assert!(request.headers.contains(&("TTL", "60".into())));
+//! ```
+
+mod vapid;
+pub use vapid::{VapidKey, MAX_VAPID_EXPIRATION};
+
+use std::convert::TryInto;
+use std::error::Error;
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+
+use rand::rngs::OsRng;
+
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use p256::{ecdh, PublicKey, SecretKey};
+
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes128Gcm, Nonce};
+
+use hkdf::Hkdf;
+use sha2::Sha256;
+
+use zeroize::Zeroize;
+
+/// The length of an uncompressed P-256 public key.
+const PUBLIC_KEY_LEN: usize = 65;
+const AUTH_LEN: usize = 16;
+const SALT_LEN: usize = 16;
+const TAG_LEN: usize = 16;
+const RECORD_SIZE: u32 = 4096;
+const HEADER_LEN: usize = SALT_LEN + 4 + 1 + PUBLIC_KEY_LEN;
+
+/// The maximum length of a payload, push services only need to accept
+/// bodies up to 4096 bytes.
+pub const MAX_PAYLOAD_LEN: usize =
+	RECORD_SIZE as usize - HEADER_LEN - TAG_LEN - 1;
+
+/// The default ttl of a message (4 weeks).
+pub const DEFAULT_TTL: u32 = 4 * 7 * 24 * 60 * 60;
+
+/// Get's returned if a push message could not be created.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum WebPushError {
+	/// The `p256dh` key of the subscription is not a valid P-256 point.
+	InvalidPublicKey,
+	/// The `auth` secret of the subscription is not 16 bytes long.
+	InvalidAuthSecret,
+	/// The endpoint is not an http(s) url.
+	InvalidEndpoint,
+	/// The payload is longer than [`MAX_PAYLOAD_LEN`].
+	PayloadTooLarge,
+	/// The topic is longer than 32 characters or not url safe base64.
+	InvalidTopic,
+	/// A vapid claim is empty or contains characters which would need to
+	/// be escaped.
+	InvalidClaim,
+}
+
+impl fmt::Display for WebPushError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		fmt::Debug::fmt(self, f)
+	}
+}
+
+impl Error for WebPushError {}
+
+fn now() -> u64 {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map(|d| d.as_secs())
+		.unwrap_or(0)
+}
+
+/// Decodes base64 (url safe), browsers sometimes add padding.
+fn b64_decode(s: &str) -> Option<Vec<u8>> {
+	URL_SAFE_NO_PAD.decode(s.trim_end_matches('=')).ok()
+}
+
+/// A push subscription of a browser.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Subscription {
+	endpoint: String,
+	p256dh: PublicKey,
+	auth: [u8; AUTH_LEN],
+}
+
+impl Subscription {
+	/// `p256dh` is the uncompressed public key of the browser and `auth`
+	/// the 16 byte auth secret.
+	pub fn new(
+		endpoint: impl Into<String>,
+		p256dh: &[u8],
+		auth: &[u8],
+	) -> Result<Self, WebPushError> {
+		let endpoint = endpoint.into();
+		if !endpoint.starts_with("https://") && !endpoint.starts_with("http://")
+		{
+			return Err(WebPushError::InvalidEndpoint);
+		}
+
+		if p256dh.len() != PUBLIC_KEY_LEN {
+			return Err(WebPushError::InvalidPublicKey);
+		}
+		let p256dh = PublicKey::from_sec1_bytes(p256dh)
+			.map_err(|_| WebPushError::InvalidPublicKey)?;
+
+		let auth = auth
+			.try_into()
+			.map_err(|_| WebPushError::InvalidAuthSecret)?;
+
+		Ok(Self {
+			endpoint,
+			p256dh,
+			auth,
+		})
+	}
+
+	/// Creates a subscription from the values of `PushSubscription.toJSON`
+	/// which are base64 (url safe) encoded.
+	pub fn from_b64(
+		endpoint: impl Into<String>,
+		p256dh: &str,
+		auth: &str,
+	) -> Result<Self, WebPushError> {
+		let p256dh =
+			b64_decode(p256dh).ok_or(WebPushError::InvalidPublicKey)?;
+		let auth = b64_decode(auth).ok_or(WebPushError::InvalidAuthSecret)?;
+
+		Self::new(endpoint, &p256dh, &auth)
+	}
+
+	pub fn endpoint(&self) -> &str {
+		&self.endpoint
+	}
+
+	/// Returns the origin of the endpoint, which is the audience of the
+	/// vapid token.
+	fn origin(&self) -> &str {
+		// checked in new
+		let scheme_len = self.endpoint.find("://").unwrap() + 3;
+		let host_len = self.endpoint[scheme_len..]
+			.find('/')
+			.unwrap_or(self.endpoint.len() - scheme_len);
+
+		&self.endpoint[..scheme_len + host_len]
+	}
+
+	/// Encrypts the payload for this subscription with the `aes128gcm`
+	/// content coding.
+	pub fn encrypt(&self, payload: &[u8]) -> Result<Vec<u8>, WebPushError> {
+		let mut salt = [0u8; SALT_LEN];
+		crate::fill_random(&mut salt);
+
+		self.encrypt_with(payload, &SecretKey::random(&mut OsRng), &salt)
+	}
+
+	fn encrypt_with(
+		&self,
+		payload: &[u8],
+		as_secret: &SecretKey,
+		salt: &[u8; SALT_LEN],
+	) -> Result<Vec<u8>, WebPushError> {
+		if payload.len() > MAX_PAYLOAD_LEN {
+			return Err(WebPushError::PayloadTooLarge);
+		}
+
+		let ua_public = self.p256dh.to_encoded_point(false);
+		let as_public = as_secret.public_key().to_encoded_point(false);
+
+		let shared = ecdh::diffie_hellman(
+			as_secret.to_nonzero_scalar(),
+			self.p256dh.as_affine(),
+		);
+
+		// combine the shared secret with the auth secret
+		let mut key_info = b"WebPush: info\0".to_vec();
+		key_info.extend_from_slice(ua_public.as_bytes());
+		key_info.extend_from_slice(as_public.as_bytes());
+
+		let mut ikm = [0u8; 32];
+		Hkdf::<Sha256>::new(Some(&self.auth), shared.raw_secret_bytes())
+			.expand(&key_info, &mut ikm)
+			.unwrap();
+
+		let prk = Hkdf::<Sha256>::new(Some(salt), &ikm);
+		ikm.zeroize();
+
+		let mut cek = [0u8; 16];
+		prk.expand(b"Content-Encoding: aes128gcm\0", &mut cek)
+			.unwrap();
+		let mut nonce = [0u8; 12];
+		prk.expand(b"Content-Encoding: nonce\0", &mut nonce)
+			.unwrap();
+
+		// a single record, followed by the last record delimiter
+		let mut record = Vec::with_capacity(payload.len() + 1);
+		record.extend_from_slice(payload);
+		record.push(2);
+
+		let ciphertext = Aes128Gcm::new(&cek.into())
+			.encrypt(Nonce::from_slice(&nonce), record.as_slice())
+			// the record is smaller than the record size
+			.unwrap();
+		cek.zeroize();
+		record.zeroize();
+
+		let mut body = Vec::with_capacity(HEADER_LEN + ciphertext.len());
+		body.extend_from_slice(salt);
+		body.extend_from_slice(&RECORD_SIZE.to_be_bytes());
+		body.push(PUBLIC_KEY_LEN as u8);
+		body.extend_from_slice(as_public.as_bytes());
+		body.extend_from_slice(&ciphertext);
+
+		Ok(body)
+	}
+}
+
+/// How urgent a message is, push services may use it to save battery.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Urgency {
+	VeryLow,
+	Low,
+	Normal,
+	High,
+}
+
+impl Urgency {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Self::VeryLow => "very-low",
+			Self::Low => "low",
+			Self::Normal => "normal",
+			Self::High => "high",
+		}
+	}
+}
+
+/// A request which needs to be posted to the endpoint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebPushRequest {
+	pub endpoint: String,
+	pub headers: Vec<(&'static str, String)>,
+	pub body: Vec<u8>,
+}
+
+/// Builds the request to send a push message to a subscription.
+#[derive(Debug)]
+pub struct RequestBuilder<'a> {
+	subscription: &'a Subscription,
+	ttl: u32,
+	urgency: Option<Urgency>,
+	topic: Option<String>,
+	vapid: Option<(&'a VapidKey, String)>,
+}
+
+impl<'a> RequestBuilder<'a> {
+	pub fn new(subscription: &'a Subscription) -> Self {
+		Self {
+			subscription,
+			ttl: DEFAULT_TTL,
+			urgency: None,
+			topic: None,
+			vapid: None,
+		}
+	}
+
+	/// Sets how many seconds the push service should keep the message if
+	/// the browser is not reachable.
+	pub fn set_ttl(&mut self, ttl: u32) {
+		self.ttl = ttl;
+	}
+
+	pub fn set_urgency(&mut self, urgency: Urgency) {
+		self.urgency = Some(urgency);
+	}
+
+	/// A message replaces a pending message with the same topic.
+	pub fn set_topic(&mut self, topic: impl Into<String>) {
+		self.topic = Some(topic.into());
+	}
+
+	/// Signs the request, the token is valid for 12 hours.
+	pub fn set_vapid(&mut self, key: &'a VapidKey, subject: impl Into<String>) {
+		self.vapid = Some((key, subject.into()));
+	}
+
+	pub fn build(self, payload: &[u8]) -> Result<WebPushRequest, WebPushError> {
+		let mut headers = vec![
+			("Content-Encoding", "aes128gcm".to_string()),
+			("Content-Type", "application/octet-stream".to_string()),
+			("TTL", self.ttl.to_string()),
+		];
+
+		if let Some(urgency) = self.urgency {
+			headers.push(("Urgency", urgency.as_str().to_string()));
+		}
+
+		if let Some(topic) = self.topic {
+			let valid = !topic.is_empty()
+				&& topic.len() <= 32
+				&& topic.bytes().all(|b| {
+					b.is_ascii_alphanumeric() || b == b'-' || b == b'_'
+				});
+			if !valid {
+				return Err(WebPushError::InvalidTopic);
+			}
+
+			headers.push(("Topic", topic));
+		}
+
+		if let Some((key, subject)) = self.vapid {
+			let expiration = now() + MAX_VAPID_EXPIRATION / 2;
+			let audience = self.subscription.origin();
+			headers.push((
+				"Authorization",
+				key.authorization(audience, &subject, expiration)?,
+			));
+		}
+
+		Ok(WebPushRequest {
+			endpoint: self.subscription.endpoint.clone(),
+			headers,
+			body: self.subscription.encrypt(payload)?,
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+
+	use p256::ecdsa::signature::Verifier;
+	use p256::ecdsa::{Signature, VerifyingKey};
+
+	fn b64(s: &str) -> Vec<u8> {
+		URL_SAFE_NO_PAD.decode(s).unwrap()
+	}
+
+	// RFC 8291 section 5
+	const ENDPOINT: &str =
+		"https://push.example.net/push/JzLQ3raZJfFBR0aqvOMsLrt54w4rJUsV";
+	const UA_PUBLIC: &str = "BCVxsr7N_eNgVRqvHtD0zTZsEc6-VV-JvLexhqUzORcxaOzi6-AYWXvTBHm4bjyPjs7Vd8pZGH6SRpkNtoIAiw4";
+	const UA_PRIVATE: &str = "q1dXpw3UpT5VOmu_cf_v6ih07Aems3njxI-JWgLcM94";
+	const AUTH: &str = "BTBZMqHH6r4Tts7J_aSIgg";
+
+	fn subscription() -> Subscription {
+		Subscription::from_b64(ENDPOINT, UA_PUBLIC, AUTH).unwrap()
+	}
+
+	/// Decrypts a single record like a browser would.
+	fn decrypt(ua_secret: &SecretKey, auth: &[u8], body: &[u8]) -> Vec<u8> {
+		let salt = &body[..SALT_LEN];
+		let as_public = &body[SALT_LEN + 5..HEADER_LEN];
+		let ua_public = ua_secret.public_key().to_encoded_point(false);
+
+		let shared = ecdh::diffie_hellman(
+			ua_secret.to_nonzero_scalar(),
+			PublicKey::from_sec1_bytes(as_public).unwrap().as_affine(),
+		);
+
+		let mut key_info = b"WebPush: info\0".to_vec();
+		key_info.extend_from_slice(ua_public.as_bytes());
+		key_info.extend_from_slice(as_public);
+		let mut ikm = [0u8; 32];
+		Hkdf::<Sha256>::new(Some(auth), shared.raw_secret_bytes())
+			.expand(&key_info, &mut ikm)
+			.unwrap();
+
+		let prk = Hkdf::<Sha256>::new(Some(salt), &ikm);
+		let mut cek = [0u8; 16];
+		prk.expand(b"Content-Encoding: aes128gcm\0", &mut cek)
+			.unwrap();
+		let mut nonce = [0u8; 12];
+		prk.expand(b"Content-Encoding: nonce\0", &mut nonce)
+			.unwrap();
+
+		let mut record = Aes128Gcm::new(&cek.into())
+			.decrypt(Nonce::from_slice(&nonce), &body[HEADER_LEN..])
+			.unwrap();
+		assert_eq!(record.pop(), Some(2));
+		record
+	}
+
+	#[test]
+	fn rfc_example() {
+		let as_secret = SecretKey::from_slice(&b64(
+			"yfWPiYE-n46HLnH0KqZOF1fJJU3MYrct3AELtAQ-oRw",
+		))
+		.unwrap();
+		let salt = b64("DGv6ra1nlYgDCS1FRnbzlw").try_into().unwrap();
+
+		let body = subscription()
+			.encrypt_with(
+				b"When I grow up, I want to be a watermelon",
+				&as_secret,
+				&salt,
+			)
+			.unwrap();
+
+		assert_eq!(
+			URL_SAFE_NO_PAD.encode(&body),
+			"DGv6ra1nlYgDCS1FRnbzlwAAEABBBP4z9KsN6nGRTbVYI_c7VJSPQTBtkgcy27mlmlMoZIIgDll6e3vCYLocInmYWAmS6TlzAC8wEqKK6PBru3jl7A_yl95bQpu6cVPTpK4Mqgkf1CXztLVBSt2Ks3oZwbuwXPXLWyouBWLVWGNWQexSgSxsj_Qulcy4a-fN"
+		);
+	}
+
+	#[test]
+	fn roundtrip() {
+		let ua_secret = SecretKey::from_slice(&b64(UA_PRIVATE)).unwrap();
+		let subscription = subscription();
+
+		let body = subscription.encrypt(b"hey").unwrap();
+		let other = subscription.encrypt(b"hey").unwrap();
+		assert_ne!(body, other);
+		assert_eq!(decrypt(&ua_secret, &b64(AUTH), &body), b"hey");
+
+		let max = vec![1; MAX_PAYLOAD_LEN];
+		let body = subscription.encrypt(&max).unwrap();
+		assert_eq!(body.len(), RECORD_SIZE as usize);
+		assert_eq!(decrypt(&ua_secret, &b64(AUTH), &body), max);
+
+		assert_eq!(
+			subscription.encrypt(&[1; MAX_PAYLOAD_LEN + 1]).unwrap_err(),
+			WebPushError::PayloadTooLarge
+		);
+	}
+
+	#[test]
+	fn invalid_subscriptions() {
+		let err = |endpoint, p256dh, auth| {
+			Subscription::from_b64(endpoint, p256dh, auth).unwrap_err()
+		};
+
+		assert_eq!(
+			err("ftp://push.example.net", UA_PUBLIC, AUTH),
+			WebPushError::InvalidEndpoint
+		);
+		assert_eq!(
+			err(ENDPOINT, &UA_PUBLIC[..40], AUTH),
+			WebPushError::InvalidPublicKey
+		);
+		assert_eq!(
+			err(ENDPOINT, UA_PUBLIC, "BTBZMqHH6r4T"),
+			WebPushError::InvalidAuthSecret
+		);
+		// padding is allowed
+		let auth = format!("{}==", AUTH);
+		assert!(Subscription::from_b64(ENDPOINT, UA_PUBLIC, &auth).is_ok());
+	}
+
+	#[test]
+	fn request() {
+		let subscription = subscription();
+		let vapid = VapidKey::new();
+
+		let mut builder = RequestBuilder::new(&subscription);
+		builder.set_urgency(Urgency::High);
+		builder.set_topic("news");
+		builder.set_vapid(&vapid, "mailto:push@example.com");
+		let request = builder.build(b"Hello").unwrap();
+
+		let header = |name| {
+			request
+				.headers
+				.iter()
+				.find(|(n, _)| *n == name)
+				.map(|(_, v)| v.as_str())
+				.unwrap()
+		};
+		assert_eq!(header("Content-Encoding"), "aes128gcm");
+		assert_eq!(header("TTL"), DEFAULT_TTL.to_string());
+		assert_eq!(header("Urgency"), "high");
+		assert_eq!(header("Topic"), "news");
+
+		// verify the vapid token
+		let auth = header("Authorization").strip_prefix("vapid t=").unwrap();
+		let (jwt, k) = auth.split_once(", k=").unwrap();
+		assert_eq!(k, vapid.public_key_b64());
+
+		let (msg, sig) = jwt.rsplit_once('.').unwrap();
+		let claims = msg.split('.').nth(1).unwrap();
+		let claims = String::from_utf8(b64(claims)).unwrap();
+		assert!(claims.contains(r#""aud":"https://push.example.net""#));
+		assert!(claims.contains(r#""sub":"mailto:push@example.com""#));
+
+		let key = VerifyingKey::from_sec1_bytes(&b64(k)).unwrap();
+		let sig = Signature::from_slice(&b64(sig)).unwrap();
+		key.verify(msg.as_bytes(), &sig).unwrap();
+
+		let mut builder = RequestBuilder::new(&subscription);
+		builder.set_topic("not a valid topic");
+		assert_eq!(builder.build(b"").unwrap_err(), WebPushError::InvalidTopic);
+
+		assert_eq!(
+			vapid.sign("https://a", "mailto:\"", 0).unwrap_err(),
+			WebPushError::InvalidClaim
+		);
+		let restored = VapidKey::from_slice(&vapid.to_bytes());
+		assert_eq!(restored.public_key(), vapid.public_key());
+	}
+}
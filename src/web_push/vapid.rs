@@ -0,0 +1,129 @@
+use super::WebPushError;
+
+use std::convert::TryFrom;
+use std::fmt;
+
+use crate::error::TryFromError;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+
+use rand::rngs::OsRng;
+
+use p256::ecdsa::signature::Signer;
+use p256::ecdsa::{Signature, SigningKey};
+
+/// The maximum time a vapid token is allowed to be valid (24 hours).
+pub const MAX_VAPID_EXPIRATION: u64 = 24 * 60 * 60;
+
+/// Returns true if the claim can be put into a json string without
+/// escaping.
+fn is_valid_claim(s: &str) -> bool {
+	!s.is_empty() && !s.chars().any(|c| c == '"' || c == '\\' || c.is_control())
+}
+
+/// The key which identifies an application server to push services
+/// (RFC 8292).
+///
+/// The public key is the `applicationServerKey` which needs to be passed
+/// to `pushManager.subscribe` in the browser.
+pub struct VapidKey {
+	inner: SigningKey,
+}
+
+impl VapidKey {
+	pub const LEN: usize = 32;
+
+	pub fn new() -> Self {
+		Self {
+			inner: SigningKey::random(&mut OsRng),
+		}
+	}
+
+	/// ## Panics
+	/// if the slice is not a valid P-256 secret key.
+	pub fn from_slice(slice: &[u8]) -> Self {
+		Self::try_from(slice).unwrap()
+	}
+
+	pub fn to_bytes(&self) -> [u8; 32] {
+		self.inner.to_bytes().into()
+	}
+
+	/// Returns the uncompressed public key (65 bytes).
+	pub fn public_key(&self) -> Vec<u8> {
+		self.inner
+			.verifying_key()
+			.to_encoded_point(false)
+			.as_bytes()
+			.to_vec()
+	}
+
+	/// Returns the public key as base64 (url safe, without padding).
+	pub fn public_key_b64(&self) -> String {
+		URL_SAFE_NO_PAD.encode(self.public_key())
+	}
+
+	/// Creates a signed jwt (ES256).
+	///
+	/// `audience` is the origin of the push endpoint, `subject` a `mailto:`
+	/// or `https:` url to contact the sender and `expiration` the unix
+	/// timestamp when the token expires.
+	pub fn sign(
+		&self,
+		audience: &str,
+		subject: &str,
+		expiration: u64,
+	) -> Result<String, WebPushError> {
+		if !is_valid_claim(audience) || !is_valid_claim(subject) {
+			return Err(WebPushError::InvalidClaim);
+		}
+
+		let header = URL_SAFE_NO_PAD.encode(r#"{"typ":"JWT","alg":"ES256"}"#);
+		let claims = URL_SAFE_NO_PAD.encode(format!(
+			r#"{{"aud":"{}","exp":{},"sub":"{}"}}"#,
+			audience, expiration, subject
+		));
+
+		let msg = format!("{}.{}", header, claims);
+		let signature: Signature = self.inner.sign(msg.as_bytes());
+
+		Ok(format!(
+			"{}.{}",
+			msg,
+			URL_SAFE_NO_PAD.encode(signature.to_bytes())
+		))
+	}
+
+	/// Returns the value of the `Authorization` header.
+	pub fn authorization(
+		&self,
+		audience: &str,
+		subject: &str,
+		expiration: u64,
+	) -> Result<String, WebPushError> {
+		Ok(format!(
+			"vapid t={}, k={}",
+			self.sign(audience, subject, expiration)?,
+			self.public_key_b64()
+		))
+	}
+}
+
+impl fmt::Debug for VapidKey {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("VapidKey")
+			.field("public_key", &self.public_key_b64())
+			.finish()
+	}
+}
+
+impl TryFrom<&[u8]> for VapidKey {
+	type Error = TryFromError;
+
+	fn try_from(v: &[u8]) -> Result<Self, Self::Error> {
+		SigningKey::from_slice(v)
+			.map(|inner| Self { inner })
+			.map_err(TryFromError::from_any)
+	}
+}
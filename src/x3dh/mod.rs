@@ -0,0 +1,705 @@
+//! X3DH asynchronous key agreement, like in Signal.
+//!
+//! Allows to start an encrypted conversation with someone who is
+//! offline. The responder publishes a [`PrekeyBundle`], the initiator
+//! uses it to compute a shared secret and sends an [`InitialMessage`]
+//! together with the first encrypted message. When the responder comes
+//! online it computes the same shared secret from the initial message.
+//!
+//! Unlike in Signal the identity consists of two keys, a signature
+//! keypair which signs the prekeys and a diffie hellman keypair.
+//!
+//! ## Example
+//! ```
+//! use fire_crypto::x3dh::{
+//!     initiate, respond, IdentityKeypair, OneTimePrekey, PrekeyBundle,
+//!     SignedPrekey,
+//! };
+//!
+//! // bob uploads a bundle to the server
+//! let bob = IdentityKeypair::new();
+//! let signed_prekey = SignedPrekey::new(1, &bob);
+//! let one_time_prekey = OneTimePrekey::new(1);
+//! let bundle = PrekeyBundle::new(&bob, &signed_prekey, Some(&one_time_prekey));
+//!
+//! // alice fetches the bundle while bob is offline
+//! let alice = IdentityKeypair::new();
+//! let (initial, alice_agreement) = initiate(&alice, &bundle).unwrap();
+//!
+//! // bob receives the initial message
+//! let bob_agreement =
+//!     respond(&bob, &signed_prekey, Some(&one_time_prekey), &initial)
+//!         .unwrap();
+//!
+//! assert_eq!(alice_agreement.shared_secret, bob_agreement.shared_secret);
+//! assert_eq!(
+//!     alice_agreement.associated_data,
+//!     bob_agreement.associated_data
+//! );
+//! ```
+
+use crate::cipher::{self, NonContributory, SharedSecret};
+use crate::error::TryFromError;
+use crate::hash::Hasher;
+use crate::signature::{self, Signature};
+
+use std::convert::{TryFrom, TryInto};
+use std::error::Error;
+use std::fmt;
+
+use zeroize::Zeroize;
+
+const KDF_KEY: &[u8] = b"fire-crypto x3dh";
+const SIGNED_PREKEY_CONTEXT: &[u8] = b"fire-crypto x3dh signed prekey";
+
+fn read_u32(bytes: &[u8]) -> u32 {
+	u32::from_be_bytes(bytes[..4].try_into().unwrap())
+}
+
+/// A long term identity, consisting of a signature keypair and a diffie
+/// hellman keypair.
+pub struct IdentityKeypair {
+	signing: signature::Keypair,
+	dh: cipher::Keypair,
+}
+
+impl IdentityKeypair {
+	pub const LEN: usize = 64;
+
+	pub fn new() -> Self {
+		Self::from_keypairs(signature::Keypair::new(), cipher::Keypair::new())
+	}
+
+	pub fn from_keypairs(
+		signing: signature::Keypair,
+		dh: cipher::Keypair,
+	) -> Self {
+		Self { signing, dh }
+	}
+
+	pub fn signing(&self) -> &signature::Keypair {
+		&self.signing
+	}
+
+	pub fn dh(&self) -> &cipher::Keypair {
+		&self.dh
+	}
+
+	pub fn public(&self) -> IdentityPublicKey {
+		IdentityPublicKey {
+			signing: self.signing.public().clone(),
+			dh: self.dh.public().clone(),
+		}
+	}
+
+	pub fn to_bytes(&self) -> [u8; 64] {
+		let mut bytes = [0u8; 64];
+		bytes[..32].copy_from_slice(&self.signing.to_bytes());
+		bytes[32..].copy_from_slice(&self.dh.to_bytes());
+		bytes
+	}
+}
+
+impl fmt::Debug for IdentityKeypair {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("IdentityKeypair")
+			.field("public", &self.public())
+			.finish()
+	}
+}
+
+impl TryFrom<&[u8]> for IdentityKeypair {
+	type Error = TryFromError;
+
+	fn try_from(v: &[u8]) -> Result<Self, Self::Error> {
+		if v.len() != Self::LEN {
+			return Err(TryFromError::from_any(()));
+		}
+
+		Ok(Self {
+			signing: signature::Keypair::try_from(&v[..32])?,
+			dh: cipher::Keypair::try_from(&v[32..])?,
+		})
+	}
+}
+
+/// The public part of an [`IdentityKeypair`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdentityPublicKey {
+	signing: signature::PublicKey,
+	dh: cipher::PublicKey,
+}
+
+impl IdentityPublicKey {
+	pub const LEN: usize = 64;
+
+	pub fn signing(&self) -> &signature::PublicKey {
+		&self.signing
+	}
+
+	pub fn dh(&self) -> &cipher::PublicKey {
+		&self.dh
+	}
+
+	pub fn to_bytes(&self) -> [u8; 64] {
+		let mut bytes = [0u8; 64];
+		bytes[..32].copy_from_slice(&self.signing.to_bytes());
+		bytes[32..].copy_from_slice(&self.dh.to_bytes());
+		bytes
+	}
+}
+
+impl TryFrom<&[u8]> for IdentityPublicKey {
+	type Error = TryFromError;
+
+	fn try_from(v: &[u8]) -> Result<Self, Self::Error> {
+		if v.len() != Self::LEN {
+			return Err(TryFromError::from_any(()));
+		}
+
+		Ok(Self {
+			signing: signature::PublicKey::try_from(&v[..32])?,
+			dh: cipher::PublicKey::try_from(&v[32..])?,
+		})
+	}
+}
+
+fn signed_prekey_msg(
+	identity: &cipher::PublicKey,
+	prekey: &cipher::PublicKey,
+) -> Vec<u8> {
+	let mut msg = SIGNED_PREKEY_CONTEXT.to_vec();
+	msg.extend_from_slice(identity.as_ref());
+	msg.extend_from_slice(prekey.as_ref());
+	msg
+}
+
+/// A medium term prekey signed by the identity, should be replaced
+/// periodically.
+pub struct SignedPrekey {
+	id: u32,
+	keypair: cipher::Keypair,
+	signature: Signature,
+}
+
+impl SignedPrekey {
+	pub const LEN: usize = 4 + 32 + Signature::LEN;
+
+	/// Creates a new prekey and signs it with the identity.
+	pub fn new(id: u32, identity: &IdentityKeypair) -> Self {
+		let keypair = cipher::Keypair::new();
+		let signature = identity
+			.signing
+			.sign(signed_prekey_msg(identity.dh.public(), keypair.public()));
+
+		Self {
+			id,
+			keypair,
+			signature,
+		}
+	}
+
+	pub fn id(&self) -> u32 {
+		self.id
+	}
+
+	pub fn public(&self) -> &cipher::PublicKey {
+		self.keypair.public()
+	}
+
+	pub fn signature(&self) -> &Signature {
+		&self.signature
+	}
+
+	pub fn to_bytes(&self) -> [u8; Self::LEN] {
+		let mut bytes = [0u8; Self::LEN];
+		bytes[..4].copy_from_slice(&self.id.to_be_bytes());
+		bytes[4..36].copy_from_slice(&self.keypair.to_bytes());
+		bytes[36..].copy_from_slice(&self.signature.to_bytes());
+		bytes
+	}
+}
+
+impl fmt::Debug for SignedPrekey {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("SignedPrekey")
+			.field("id", &self.id)
+			.field("public", self.public())
+			.finish()
+	}
+}
+
+impl TryFrom<&[u8]> for SignedPrekey {
+	type Error = TryFromError;
+
+	fn try_from(v: &[u8]) -> Result<Self, Self::Error> {
+		if v.len() != Self::LEN {
+			return Err(TryFromError::from_any(()));
+		}
+
+		Ok(Self {
+			id: read_u32(v),
+			keypair: cipher::Keypair::try_from(&v[4..36])?,
+			signature: Signature::try_from(&v[36..])?,
+		})
+	}
+}
+
+/// A prekey which should only be used for a single initial message.
+pub struct OneTimePrekey {
+	id: u32,
+	keypair: cipher::Keypair,
+}
+
+impl OneTimePrekey {
+	pub const LEN: usize = 4 + 32;
+
+	pub fn new(id: u32) -> Self {
+		Self {
+			id,
+			keypair: cipher::Keypair::new(),
+		}
+	}
+
+	pub fn id(&self) -> u32 {
+		self.id
+	}
+
+	pub fn public(&self) -> &cipher::PublicKey {
+		self.keypair.public()
+	}
+
+	pub fn to_bytes(&self) -> [u8; Self::LEN] {
+		let mut bytes = [0u8; Self::LEN];
+		bytes[..4].copy_from_slice(&self.id.to_be_bytes());
+		bytes[4..].copy_from_slice(&self.keypair.to_bytes());
+		bytes
+	}
+}
+
+impl fmt::Debug for OneTimePrekey {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("OneTimePrekey")
+			.field("id", &self.id)
+			.field("public", self.public())
+			.finish()
+	}
+}
+
+impl TryFrom<&[u8]> for OneTimePrekey {
+	type Error = TryFromError;
+
+	fn try_from(v: &[u8]) -> Result<Self, Self::Error> {
+		if v.len() != Self::LEN {
+			return Err(TryFromError::from_any(()));
+		}
+
+		Ok(Self {
+			id: read_u32(v),
+			keypair: cipher::Keypair::try_from(&v[4..])?,
+		})
+	}
+}
+
+/// The public keys a responder publishes so others can start a
+/// conversation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrekeyBundle {
+	identity: IdentityPublicKey,
+	signed_prekey_id: u32,
+	signed_prekey: cipher::PublicKey,
+	signature: Signature,
+	one_time_prekey: Option<(u32, cipher::PublicKey)>,
+}
+
+impl PrekeyBundle {
+	const MIN_LEN: usize = IdentityPublicKey::LEN + SignedPrekey::LEN + 1;
+
+	pub fn new(
+		identity: &IdentityKeypair,
+		signed_prekey: &SignedPrekey,
+		one_time_prekey: Option<&OneTimePrekey>,
+	) -> Self {
+		Self {
+			identity: identity.public(),
+			signed_prekey_id: signed_prekey.id,
+			signed_prekey: signed_prekey.public().clone(),
+			signature: signed_prekey.signature.clone(),
+			one_time_prekey: one_time_prekey
+				.map(|k| (k.id, k.public().clone())),
+		}
+	}
+
+	pub fn identity(&self) -> &IdentityPublicKey {
+		&self.identity
+	}
+
+	/// Returns true if the signed prekey was signed by the identity.
+	pub fn verify(&self) -> bool {
+		self.identity.signing.verify(
+			signed_prekey_msg(&self.identity.dh, &self.signed_prekey),
+			&self.signature,
+		)
+	}
+
+	pub fn to_bytes(&self) -> Vec<u8> {
+		let mut bytes = Vec::with_capacity(Self::MIN_LEN + 36);
+		bytes.extend_from_slice(&self.identity.to_bytes());
+		bytes.extend_from_slice(&self.signed_prekey_id.to_be_bytes());
+		bytes.extend_from_slice(self.signed_prekey.as_ref());
+		bytes.extend_from_slice(&self.signature.to_bytes());
+
+		match &self.one_time_prekey {
+			Some((id, key)) => {
+				bytes.push(1);
+				bytes.extend_from_slice(&id.to_be_bytes());
+				bytes.extend_from_slice(key.as_ref());
+			}
+			None => bytes.push(0),
+		}
+
+		bytes
+	}
+}
+
+impl TryFrom<&[u8]> for PrekeyBundle {
+	type Error = TryFromError;
+
+	fn try_from(v: &[u8]) -> Result<Self, Self::Error> {
+		if v.len() != Self::MIN_LEN && v.len() != Self::MIN_LEN + 36 {
+			return Err(TryFromError::from_any(()));
+		}
+
+		let one_time_prekey = match (v[Self::MIN_LEN - 1], v.len()) {
+			(0, Self::MIN_LEN) => None,
+			(1, _) if v.len() > Self::MIN_LEN => {
+				let rest = &v[Self::MIN_LEN..];
+				Some((read_u32(rest), cipher::PublicKey::try_from(&rest[4..])?))
+			}
+			_ => return Err(TryFromError::from_any(())),
+		};
+
+		Ok(Self {
+			identity: IdentityPublicKey::try_from(&v[..64])?,
+			signed_prekey_id: read_u32(&v[64..]),
+			signed_prekey: cipher::PublicKey::try_from(&v[68..100])?,
+			signature: Signature::try_from(&v[100..164])?,
+			one_time_prekey,
+		})
+	}
+}
+
+/// Sent by the initiator together with the first message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InitialMessage {
+	identity: IdentityPublicKey,
+	ephemeral: cipher::PublicKey,
+	signed_prekey_id: u32,
+	one_time_prekey_id: Option<u32>,
+}
+
+impl InitialMessage {
+	const MIN_LEN: usize = IdentityPublicKey::LEN + 32 + 4 + 1;
+
+	/// The identity of the initiator.
+	///
+	/// The responder needs to decide if it trusts this identity.
+	pub fn identity(&self) -> &IdentityPublicKey {
+		&self.identity
+	}
+
+	pub fn signed_prekey_id(&self) -> u32 {
+		self.signed_prekey_id
+	}
+
+	/// The one time prekey which needs to be passed to [`respond`] and
+	/// then deleted.
+	pub fn one_time_prekey_id(&self) -> Option<u32> {
+		self.one_time_prekey_id
+	}
+
+	pub fn to_bytes(&self) -> Vec<u8> {
+		let mut bytes = Vec::with_capacity(Self::MIN_LEN + 4);
+		bytes.extend_from_slice(&self.identity.to_bytes());
+		bytes.extend_from_slice(self.ephemeral.as_ref());
+		bytes.extend_from_slice(&self.signed_prekey_id.to_be_bytes());
+
+		match self.one_time_prekey_id {
+			Some(id) => {
+				bytes.push(1);
+				bytes.extend_from_slice(&id.to_be_bytes());
+			}
+			None => bytes.push(0),
+		}
+
+		bytes
+	}
+}
+
+impl TryFrom<&[u8]> for InitialMessage {
+	type Error = TryFromError;
+
+	fn try_from(v: &[u8]) -> Result<Self, Self::Error> {
+		let one_time_prekey_id = match (v.get(Self::MIN_LEN - 1), v.len()) {
+			(Some(0), Self::MIN_LEN) => None,
+			(Some(1), len) if len == Self::MIN_LEN + 4 => {
+				Some(read_u32(&v[Self::MIN_LEN..]))
+			}
+			_ => return Err(TryFromError::from_any(())),
+		};
+
+		Ok(Self {
+			identity: IdentityPublicKey::try_from(&v[..64])?,
+			ephemeral: cipher::PublicKey::try_from(&v[64..96])?,
+			signed_prekey_id: read_u32(&v[96..]),
+			one_time_prekey_id,
+		})
+	}
+}
+
+/// The result of a key agreement.
+#[derive(Debug)]
+pub struct Agreement {
+	pub shared_secret: SharedSecret,
+	/// The identities of the initiator and the responder, should be used
+	/// as associated data (or be authenticated otherwise) for the first
+	/// message.
+	pub associated_data: Vec<u8>,
+}
+
+fn agreement(
+	dhs: &[SharedSecret],
+	initiator: &IdentityPublicKey,
+	responder: &IdentityPublicKey,
+) -> Agreement {
+	let mut hasher = Hasher::new_keyed(KDF_KEY);
+	hasher.update([0xff; 32]);
+	for dh in dhs {
+		hasher.update(dh.as_slice());
+	}
+	let mut bytes = hasher.finalize().to_bytes();
+	let shared_secret =
+		SharedSecret::from_bytes(bytes[..32].try_into().unwrap());
+	bytes.zeroize();
+
+	let mut associated_data = initiator.to_bytes().to_vec();
+	associated_data.extend_from_slice(&responder.to_bytes());
+
+	Agreement {
+		shared_secret,
+		associated_data,
+	}
+}
+
+/// Computes the shared secret with the bundle of the responder.
+///
+/// The initial message needs to be sent to the responder.
+pub fn initiate(
+	identity: &IdentityKeypair,
+	bundle: &PrekeyBundle,
+) -> Result<(InitialMessage, Agreement), X3dhError> {
+	if !bundle.verify() {
+		return Err(X3dhError::InvalidSignature);
+	}
+
+	// the ephemeral key is used for up to three exchanges, so it can't be
+	// an EphemeralKeypair
+	let ephemeral = cipher::Keypair::new();
+	let ephemeral_public = ephemeral.public().clone();
+
+	let mut dhs = vec![
+		identity.dh.try_diffie_hellman(&bundle.signed_prekey)?,
+		ephemeral.try_diffie_hellman(&bundle.identity.dh)?,
+		ephemeral.try_diffie_hellman(&bundle.signed_prekey)?,
+	];
+	if let Some((_, one_time_prekey)) = &bundle.one_time_prekey {
+		dhs.push(ephemeral.try_diffie_hellman(one_time_prekey)?);
+	}
+
+	let agreement = agreement(&dhs, &identity.public(), &bundle.identity);
+
+	let msg = InitialMessage {
+		identity: identity.public(),
+		ephemeral: ephemeral_public,
+		signed_prekey_id: bundle.signed_prekey_id,
+		one_time_prekey_id: bundle.one_time_prekey.as_ref().map(|(id, _)| *id),
+	};
+
+	Ok((msg, agreement))
+}
+
+/// Computes the shared secret from an initial message.
+///
+/// The one time prekey with the id `msg.one_time_prekey_id()` needs to be
+/// passed and should be deleted afterwards.
+pub fn respond(
+	identity: &IdentityKeypair,
+	signed_prekey: &SignedPrekey,
+	one_time_prekey: Option<&OneTimePrekey>,
+	msg: &InitialMessage,
+) -> Result<Agreement, X3dhError> {
+	if msg.signed_prekey_id != signed_prekey.id {
+		return Err(X3dhError::SignedPrekeyMismatch);
+	}
+
+	if msg.one_time_prekey_id != one_time_prekey.map(|k| k.id) {
+		return Err(X3dhError::OneTimePrekeyMismatch);
+	}
+
+	let mut dhs = vec![
+		signed_prekey.keypair.try_diffie_hellman(&msg.identity.dh)?,
+		identity.dh.try_diffie_hellman(&msg.ephemeral)?,
+		signed_prekey.keypair.try_diffie_hellman(&msg.ephemeral)?,
+	];
+	if let Some(one_time_prekey) = one_time_prekey {
+		dhs.push(one_time_prekey.keypair.try_diffie_hellman(&msg.ephemeral)?);
+	}
+
+	Ok(agreement(&dhs, &msg.identity, &identity.public()))
+}
+
+/// Get's returned if the key agreement failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum X3dhError {
+	/// The signed prekey was not signed by the identity.
+	InvalidSignature,
+	/// A public key was a low order point.
+	NonContributory,
+	/// The initial message used another signed prekey.
+	SignedPrekeyMismatch,
+	/// The initial message used another or no one time prekey.
+	OneTimePrekeyMismatch,
+}
+
+impl From<NonContributory> for X3dhError {
+	fn from(_: NonContributory) -> Self {
+		Self::NonContributory
+	}
+}
+
+impl fmt::Display for X3dhError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		fmt::Debug::fmt(self, f)
+	}
+}
+
+impl Error for X3dhError {}
+
+#[cfg(test)]
+mod tests {
+
+	use super::*;
+
+	#[test]
+	fn with_one_time_prekey() {
+		let bob = IdentityKeypair::new();
+		let spk = SignedPrekey::new(7, &bob);
+		let opk = OneTimePrekey::new(42);
+		let bundle = PrekeyBundle::new(&bob, &spk, Some(&opk));
+
+		let alice = IdentityKeypair::new();
+		let (msg, a) = initiate(&alice, &bundle).unwrap();
+		assert_eq!(msg.signed_prekey_id(), 7);
+		assert_eq!(msg.one_time_prekey_id(), Some(42));
+		assert_eq!(msg.identity(), &alice.public());
+
+		let b = respond(&bob, &spk, Some(&opk), &msg).unwrap();
+		assert_eq!(a.shared_secret, b.shared_secret);
+		assert_eq!(a.associated_data, b.associated_data);
+		assert_eq!(a.associated_data.len(), 128);
+
+		// the one time prekey is required
+		assert_eq!(
+			respond(&bob, &spk, None, &msg).unwrap_err(),
+			X3dhError::OneTimePrekeyMismatch
+		);
+		assert_eq!(
+			respond(&bob, &SignedPrekey::new(8, &bob), Some(&opk), &msg)
+				.unwrap_err(),
+			X3dhError::SignedPrekeyMismatch
+		);
+	}
+
+	#[test]
+	fn without_one_time_prekey() {
+		let bob = IdentityKeypair::new();
+		let spk = SignedPrekey::new(1, &bob);
+		let bundle = PrekeyBundle::new(&bob, &spk, None);
+
+		let alice = IdentityKeypair::new();
+		let (msg, a) = initiate(&alice, &bundle).unwrap();
+		let b = respond(&bob, &spk, None, &msg).unwrap();
+		assert_eq!(a.shared_secret, b.shared_secret);
+
+		// a second initiation leads to another secret
+		let (_, a2) = initiate(&alice, &bundle).unwrap();
+		assert_ne!(a.shared_secret, a2.shared_secret);
+	}
+
+	#[test]
+	fn invalid_signature() {
+		let bob = IdentityKeypair::new();
+		let mallory = IdentityKeypair::new();
+
+		// the prekey is signed by mallory
+		let spk = SignedPrekey::new(1, &mallory);
+		let bundle = PrekeyBundle::new(&bob, &spk, None);
+		assert!(!bundle.verify());
+
+		let alice = IdentityKeypair::new();
+		assert_eq!(
+			initiate(&alice, &bundle).unwrap_err(),
+			X3dhError::InvalidSignature
+		);
+	}
+
+	#[test]
+	fn low_order_ephemeral() {
+		let bob = IdentityKeypair::new();
+		let spk = SignedPrekey::new(1, &bob);
+		let alice = IdentityKeypair::new();
+
+		let msg = InitialMessage {
+			identity: alice.public(),
+			ephemeral: cipher::PublicKey::from([0u8; 32]),
+			signed_prekey_id: 1,
+			one_time_prekey_id: None,
+		};
+		assert_eq!(
+			respond(&bob, &spk, None, &msg).unwrap_err(),
+			X3dhError::NonContributory
+		);
+	}
+
+	#[test]
+	fn bytes() {
+		let bob = IdentityKeypair::new();
+		let spk = SignedPrekey::new(3, &bob);
+		let opk = OneTimePrekey::new(4);
+
+		for opk in [None, Some(&opk)] {
+			let bundle = PrekeyBundle::new(&bob, &spk, opk);
+			let bytes = bundle.to_bytes();
+			let parsed = PrekeyBundle::try_from(bytes.as_ref()).unwrap();
+			assert_eq!(parsed, bundle);
+			assert!(PrekeyBundle::try_from(&bytes[1..]).is_err());
+
+			let alice = IdentityKeypair::new();
+			let (msg, a) = initiate(&alice, &parsed).unwrap();
+			let msg_bytes = msg.to_bytes();
+			let msg = InitialMessage::try_from(msg_bytes.as_ref()).unwrap();
+
+			// restore bob's keys from bytes
+			let bob = IdentityKeypair::try_from(bob.to_bytes().as_ref());
+			let spk = SignedPrekey::try_from(spk.to_bytes().as_ref());
+			let opk = opk.map(|k| {
+				OneTimePrekey::try_from(k.to_bytes().as_ref()).unwrap()
+			});
+
+			let b = respond(&bob.unwrap(), &spk.unwrap(), opk.as_ref(), &msg)
+				.unwrap();
+			assert_eq!(a.shared_secret, b.shared_secret);
+		}
+	}
+}